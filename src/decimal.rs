@@ -0,0 +1,891 @@
+//! # Decimal Module
+//!
+//! This module provides the arbitrary-precision decimal numbers used by the evaluator in
+//! `NumericMode::Decimal`.
+//!
+//! ## Precision
+//!
+//! A `Decimal` is a `BigDecimal` together with its precision, the number of significant
+//! digits it is rounded to. Every operation rounds its result to the larger precision of its
+//! operands, and computes internally with a few more digits so that the rounded result is
+//! correct to the last digit or very close to it.
+//!
+//! ## Functions
+//!
+//! Besides the arithmetic operators, a `Decimal` provides the built-in functions and
+//! constants at its precision:
+//!
+//! - `sqrt`, `cbrt`, `hypot`, `pow`, `exp`, `ln`, `log10`, `log2` and `log`.
+//! - `sin`, `cos`, `tan`, `asin`, `acos`, `atan` and `atan2`, in radians.
+//! - `sinh`, `cosh`, `tanh`, `asinh`, `acosh` and `atanh`.
+//! - The constants `pi`, `e`, `tau` and `phi`, through `Decimal::constant`.
+//!
+//! A function returns `None` when its argument is outside of its domain, such as `sqrt(-1)`,
+//! or its result cannot be represented, such as `exp(1e10)`; the evaluator then falls back
+//! to floating point, which yields `NaN` or an infinity.
+//!
+//! ```
+//! use calcrs::decimal::Decimal;
+//!
+//! let two = Decimal::from_f64(2.0, 30).unwrap();
+//! assert_eq!(two.sqrt().unwrap().to_string(), "1.41421356237309504880168872421");
+//! ```
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::num::NonZeroU64;
+use std::ops::{Add, Div, Mul, Neg, Rem, Sub};
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, Context, RoundingMode};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+use crate::evaluator::AngleMode;
+
+/// The precision used when none is given, in significant digits.
+pub const DEFAULT_PRECISION: u64 = 50;
+
+/// The extra digits computed internally before rounding a result.
+pub const GUARD_DIGITS: u64 = 10;
+
+/// The largest magnitude of an argument to `exp`, above which the result is not computed.
+const MAX_EXP_ARGUMENT: i64 = 1_000_000_000;
+
+/// The largest order of magnitude of an argument to the trigonometric functions.
+const MAX_TRIG_MAGNITUDE: i64 = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decimal {
+    value: BigDecimal,
+    precision: u64,
+}
+
+impl Decimal {
+    /// Rounds `value` to `precision` significant digits.
+    pub fn new(value: BigDecimal, precision: u64) -> Self {
+        let precision = precision.max(1);
+        Decimal {
+            value: round(&value, precision),
+            precision,
+        }
+    }
+
+    /// The shortest decimal that parses to `value`, or `None` if `value` is not finite.
+    pub fn from_f64(value: f64, precision: u64) -> Option<Self> {
+        if !value.is_finite() {
+            return None;
+        }
+        // `{:e}` writes the shortest digits that round-trip, as in `-1.25e-3`.
+        let value = BigDecimal::from_str(&format!("{:e}", value)).ok()?;
+        Some(Decimal::new(value, precision))
+    }
+
+    pub fn from_rational(value: &BigRational, precision: u64) -> Self {
+        let numerator = BigDecimal::from(value.numer().clone());
+        let denominator = BigDecimal::from(value.denom().clone());
+        Decimal::new(divide(&numerator, &denominator, precision), precision)
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.value.to_f64().unwrap_or(f64::NAN)
+    }
+
+    pub fn value(&self) -> &BigDecimal {
+        &self.value
+    }
+
+    pub fn precision(&self) -> u64 {
+        self.precision
+    }
+
+    /// The same decimal with another precision, rounded if it is lower.
+    pub fn with_precision(&self, precision: u64) -> Decimal {
+        Decimal::new(self.value.clone(), precision)
+    }
+
+    pub fn abs(&self) -> Decimal {
+        Decimal {
+            value: self.value.abs(),
+            precision: self.precision,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.value.is_zero()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.value.is_integer()
+    }
+
+    /// The built-in constant `name` among `pi`, `e`, `tau` and `phi`.
+    pub fn constant(name: &str, precision: u64) -> Option<Self> {
+        let digits = precision + GUARD_DIGITS;
+        let value = match name {
+            "pi" => pi(digits),
+            "tau" => pi(digits).double(),
+            "e" => exp(&BigDecimal::one(), digits)?,
+            "phi" => (BigDecimal::one() + sqrt(&BigDecimal::from(5), digits)?).half(),
+            _ => return None,
+        };
+        Some(Decimal::new(value, precision))
+    }
+
+    /// Converts an angle in the unit `from` to the unit `to`.
+    pub fn convert_angle(&self, from: AngleMode, to: AngleMode) -> Decimal {
+        if from == to {
+            return self.clone();
+        }
+        let digits = self.digits();
+        let radians = &self.value * radians_per_unit(from, digits);
+        self.result(divide(&radians, &radians_per_unit(to, digits), digits))
+    }
+
+    /// Raises the decimal to the power `exponent`.
+    pub fn pow(&self, exponent: &Decimal) -> Option<Decimal> {
+        let precision = self.precision.max(exponent.precision);
+        let digits = precision + GUARD_DIGITS;
+        // The exponent is compared to `i64::MAX` first, since converting a larger one expands
+        // all of its digits.
+        if exponent.is_integer() && exponent.value.abs() <= i64::MAX {
+            if let Some(exponent) = exponent.value.to_i64() {
+                if exponent == 0 {
+                    return Some(Decimal::new(BigDecimal::one(), precision));
+                }
+                if self.is_zero() && exponent < 0 {
+                    return None;
+                }
+                let extra = exponent.unsigned_abs().ilog10() as u64;
+                let context = context(digits + extra);
+                let value = self.value.powi_with_context(exponent, &context);
+                return Some(Decimal::new(value, precision));
+            }
+        }
+        match self.value.sign() {
+            num_bigint::Sign::Minus => None,
+            num_bigint::Sign::NoSign if exponent.value.is_positive() => {
+                Some(Decimal::new(BigDecimal::zero(), precision))
+            }
+            num_bigint::Sign::NoSign => None,
+            num_bigint::Sign::Plus => {
+                // x^y = e^(y ln x), where ln x needs as many more digits as y ln x has before
+                // its point, and e^(y ln x) is not computed when y ln x is too large.
+                let estimate = &exponent.value * ln(&self.value, digits)?;
+                if estimate.abs() > MAX_EXP_ARGUMENT {
+                    return None;
+                }
+                let extra = estimate.order_of_magnitude().max(0) as u64;
+                let logarithm = ln(&self.value, digits + extra)?;
+                let value = exp(&(&exponent.value * logarithm), digits)?;
+                Some(Decimal::new(value, precision))
+            }
+        }
+    }
+
+    /// The factorial of a natural number no larger than `limit`.
+    pub fn factorial(&self, limit: u32) -> Option<Decimal> {
+        if !self.is_integer() || self.value > limit {
+            return None;
+        }
+        let n = self.value.to_u32()?;
+        let product = (2..=n).fold(BigInt::one(), |product, k| product * k);
+        Some(Decimal::new(BigDecimal::from(product), self.precision))
+    }
+
+    pub fn sqrt(&self) -> Option<Decimal> {
+        Some(self.result(sqrt(&self.value, self.digits())?))
+    }
+
+    pub fn cbrt(&self) -> Option<Decimal> {
+        let value = self.value.cbrt_with_context(&context(self.digits()));
+        Some(self.result(value))
+    }
+
+    pub fn hypot(&self, other: &Decimal) -> Option<Decimal> {
+        let digits = self.digits().max(other.digits());
+        let sum = sum(&self.value.square(), &other.value.square(), digits);
+        Some(self.result_with(other, sqrt(&sum, digits)?))
+    }
+
+    pub fn exp(&self) -> Option<Decimal> {
+        Some(self.result(exp(&self.value, self.digits())?))
+    }
+
+    pub fn ln(&self) -> Option<Decimal> {
+        Some(self.result(ln(&self.value, self.digits())?))
+    }
+
+    pub fn log10(&self) -> Option<Decimal> {
+        let digits = self.digits();
+        Some(self.result(divide(&ln(&self.value, digits)?, &ln10(digits), digits)))
+    }
+
+    pub fn log2(&self) -> Option<Decimal> {
+        let digits = self.digits();
+        Some(self.result(divide(&ln(&self.value, digits)?, &ln2(digits), digits)))
+    }
+
+    /// The logarithm of `x` in the base `self`.
+    pub fn log(&self, x: &Decimal) -> Option<Decimal> {
+        let digits = self.digits().max(x.digits());
+        let base = ln(&self.value, digits)?;
+        if base.is_zero() {
+            return None;
+        }
+        Some(self.result_with(x, divide(&ln(&x.value, digits)?, &base, digits)))
+    }
+
+    pub fn sin(&self) -> Option<Decimal> {
+        Some(self.result(sin_cos(&self.value, self.digits())?.0))
+    }
+
+    pub fn cos(&self) -> Option<Decimal> {
+        Some(self.result(sin_cos(&self.value, self.digits())?.1))
+    }
+
+    pub fn tan(&self) -> Option<Decimal> {
+        let digits = self.digits();
+        let (sin, cos) = sin_cos(&self.value, digits)?;
+        if cos.is_zero() {
+            return None;
+        }
+        Some(self.result(divide(&sin, &cos, digits)))
+    }
+
+    pub fn asin(&self) -> Option<Decimal> {
+        let digits = self.digits();
+        let one = BigDecimal::one();
+        let value = match self.value.abs().cmp(&one) {
+            Ordering::Greater => return None,
+            Ordering::Equal => with_sign(pi(digits).half(), &self.value),
+            Ordering::Less => {
+                let cosine = sqrt(&(one - self.value.square()), digits)?;
+                atan(&divide(&self.value, &cosine, digits), digits)
+            }
+        };
+        Some(self.result(value))
+    }
+
+    pub fn acos(&self) -> Option<Decimal> {
+        let digits = self.digits();
+        let one = BigDecimal::one();
+        if self.value.abs() > one {
+            return None;
+        }
+        if self.value == -one.clone() {
+            return Some(self.result(pi(digits)));
+        }
+        // acos(x) = 2 atan(sqrt((1 - x) / (1 + x))), which stays accurate near 1.
+        let ratio = divide(&(&one - &self.value), &(&one + &self.value), digits);
+        Some(self.result(atan(&sqrt(&ratio, digits)?, digits).double()))
+    }
+
+    pub fn atan(&self) -> Option<Decimal> {
+        Some(self.result(atan(&self.value, self.digits())))
+    }
+
+    /// The angle of the point `(x, y)`, where `self` is `y`.
+    pub fn atan2(&self, x: &Decimal) -> Option<Decimal> {
+        let digits = self.digits().max(x.digits());
+        let (y, x_value) = (&self.value, &x.value);
+        let value = if x_value.is_zero() {
+            match y.sign() {
+                num_bigint::Sign::NoSign => BigDecimal::zero(),
+                _ => with_sign(pi(digits).half(), y),
+            }
+        } else {
+            let angle = atan(&divide(y, x_value, digits), digits);
+            match (x_value.is_negative(), y.is_negative()) {
+                (false, _) => angle,
+                (true, false) => angle + pi(digits),
+                (true, true) => angle - pi(digits),
+            }
+        };
+        Some(self.result_with(x, value))
+    }
+
+    pub fn sinh(&self) -> Option<Decimal> {
+        let digits = self.digits();
+        if self.value.abs() < BigDecimal::one() {
+            return Some(self.result(taylor(&self.value, 1, false, digits)));
+        }
+        let (exp, inverse) = exp_pair(&self.value, digits)?;
+        Some(self.result((exp - inverse).half()))
+    }
+
+    pub fn cosh(&self) -> Option<Decimal> {
+        let (exp, inverse) = exp_pair(&self.value, self.digits())?;
+        Some(self.result((exp + inverse).half()))
+    }
+
+    pub fn tanh(&self) -> Option<Decimal> {
+        let digits = self.digits();
+        let sinh = self.sinh()?;
+        let cosh = self.cosh()?;
+        Some(self.result(divide(&sinh.value, &cosh.value, digits)))
+    }
+
+    pub fn asinh(&self) -> Option<Decimal> {
+        // ln(|x| + sqrt(x^2 + 1)) loses the digits of a small x to the leading 1.
+        let digits = self.digits() + self.value.order_of_magnitude().min(0).unsigned_abs();
+        let x = self.value.abs();
+        let root = sqrt(&sum(&x.square(), &BigDecimal::one(), digits), digits)?;
+        let value = ln(&sum(&x, &root, digits), digits)?;
+        Some(self.result(with_sign(value, &self.value)))
+    }
+
+    pub fn acosh(&self) -> Option<Decimal> {
+        let digits = self.digits();
+        if self.value < BigDecimal::one() {
+            return None;
+        }
+        let root = sqrt(
+            &sum(&self.value.square(), &-BigDecimal::one(), digits),
+            digits,
+        )?;
+        Some(self.result(ln(&sum(&self.value, &root, digits), digits)?))
+    }
+
+    pub fn atanh(&self) -> Option<Decimal> {
+        let digits = self.digits();
+        let one = BigDecimal::one();
+        if self.value.abs() >= one {
+            return None;
+        }
+        if self.value.abs() < BigDecimal::new(5.into(), 1) {
+            return Some(self.result(arctan_series(&self.value, true, digits)));
+        }
+        let ratio = divide(&(&one + &self.value), &(&one - &self.value), digits);
+        Some(self.result(ln(&ratio, digits)?.half()))
+    }
+
+    /// The number of digits computed internally.
+    fn digits(&self) -> u64 {
+        self.precision + GUARD_DIGITS
+    }
+
+    fn result(&self, value: BigDecimal) -> Decimal {
+        Decimal::new(value, self.precision)
+    }
+
+    fn result_with(&self, other: &Decimal, value: BigDecimal) -> Decimal {
+        Decimal::new(value, self.precision.max(other.precision))
+    }
+}
+
+impl Add for Decimal {
+    type Output = Decimal;
+
+    fn add(self, other: Decimal) -> Decimal {
+        let digits = self.digits().max(other.digits());
+        self.result_with(&other, sum(&self.value, &other.value, digits))
+    }
+}
+
+impl Sub for Decimal {
+    type Output = Decimal;
+
+    fn sub(self, other: Decimal) -> Decimal {
+        let digits = self.digits().max(other.digits());
+        self.result_with(&other, sum(&self.value, &-&other.value, digits))
+    }
+}
+
+impl Mul for Decimal {
+    type Output = Decimal;
+
+    fn mul(self, other: Decimal) -> Decimal {
+        self.result_with(&other, &self.value * &other.value)
+    }
+}
+
+/// Divides two decimals; the divisor must not be zero.
+impl Div for Decimal {
+    type Output = Decimal;
+
+    fn div(self, other: Decimal) -> Decimal {
+        let digits = self.digits().max(other.digits());
+        self.result_with(&other, divide(&self.value, &other.value, digits))
+    }
+}
+
+/// The modulo of two decimals, which is never negative like `f64::rem_euclid`; the divisor
+/// must not be zero.
+impl Rem for Decimal {
+    type Output = Decimal;
+
+    fn rem(self, other: Decimal) -> Decimal {
+        let precision = self.precision.max(other.precision);
+        let (a, b) = (&self.value, other.value.abs());
+        if a.abs() < b {
+            let digits = self.digits().max(other.digits());
+            let remainder = if a.is_negative() {
+                sum(a, &b, digits)
+            } else {
+                a.clone()
+            };
+            return Decimal::new(remainder, precision);
+        }
+        // Both are multiples of 10^-scale. The power of ten scaling `a`, which is as large as
+        // its exponent, is only computed modulo `b`, while the one scaling `b` has at most as
+        // many digits as `a` since `|a| >= |b|`.
+        let (a_digits, a_scale) = a.as_bigint_and_exponent();
+        let (b_digits, b_scale) = b.as_bigint_and_exponent();
+        let scale = a_scale.max(b_scale);
+        let ten = BigInt::from(10);
+        let modulus = b_digits * ten.pow((scale - b_scale) as u32);
+        let shifted = a_digits * ten.modpow(&BigInt::from(scale - a_scale), &modulus);
+        let remainder = (shifted % &modulus + &modulus) % &modulus;
+        Decimal::new(BigDecimal::new(remainder, scale), precision)
+    }
+}
+
+impl Neg for Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        Decimal {
+            value: -self.value,
+            precision: self.precision,
+        }
+    }
+}
+
+/// Displays the significant digits in plain notation, or in scientific notation when the
+/// decimal point is further from them than the precision.
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = self.value.normalized();
+        if value.is_zero() {
+            return write!(f, "0");
+        }
+        let magnitude = value.order_of_magnitude().unsigned_abs();
+        if magnitude <= self.precision {
+            write!(f, "{}", value.to_plain_string())
+        } else {
+            write!(f, "{}", value.to_scientific_notation())
+        }
+    }
+}
+
+/// The sum of `a` and `b`, or the larger of them when the other is too small to change its
+/// first `digits` digits, so that operands of far apart magnitudes are not aligned to a common
+/// scale, which would expand the larger one into all of its digits.
+fn sum(a: &BigDecimal, b: &BigDecimal, digits: u64) -> BigDecimal {
+    if a.is_zero() {
+        return b.clone();
+    }
+    if b.is_zero() {
+        return a.clone();
+    }
+    let gap = a.order_of_magnitude() - b.order_of_magnitude();
+    if gap.unsigned_abs() <= digits {
+        a + b
+    } else if gap > 0 {
+        a.clone()
+    } else {
+        b.clone()
+    }
+}
+
+/// Rounds `value` to `digits` significant digits, half to even.
+fn round(value: &BigDecimal, digits: u64) -> BigDecimal {
+    let digits = NonZeroU64::new(digits).unwrap_or(NonZeroU64::MIN);
+    value.with_precision_round(digits, RoundingMode::HalfEven)
+}
+
+fn context(digits: u64) -> Context {
+    Context::new(
+        NonZeroU64::new(digits.max(1)).unwrap_or(NonZeroU64::MIN),
+        RoundingMode::HalfEven,
+    )
+}
+
+/// `10^-digits`, scaled by the order of magnitude of `reference`.
+fn epsilon(reference: &BigDecimal, digits: u64) -> BigDecimal {
+    let scale = digits as i64 + 1 - reference.order_of_magnitude();
+    BigDecimal::new(BigInt::one(), scale)
+}
+
+fn with_sign(value: BigDecimal, sign: &BigDecimal) -> BigDecimal {
+    if sign.is_negative() {
+        -value
+    } else {
+        value
+    }
+}
+
+/// Divides `a` by `b` to `digits` significant digits.
+fn divide(a: &BigDecimal, b: &BigDecimal, digits: u64) -> BigDecimal {
+    let (a_digits, a_scale) = a.as_bigint_and_exponent();
+    let (b_digits, b_scale) = b.as_bigint_and_exponent();
+    let shift = (digits + b.digits() + 1).saturating_sub(a.digits());
+    let quotient = a_digits * BigInt::from(10).pow(shift as u32) / b_digits;
+    round(
+        &BigDecimal::new(quotient, a_scale - b_scale + shift as i64),
+        digits,
+    )
+}
+
+fn sqrt(x: &BigDecimal, digits: u64) -> Option<BigDecimal> {
+    x.sqrt_with_context(&context(digits))
+}
+
+/// The sum of the Taylor series `x^start / start! ± x^(start+2) / (start+2)! ± ...`,
+/// alternating when `alternating` is set: sine, cosine, and the hyperbolic sine.
+fn taylor(x: &BigDecimal, start: u32, alternating: bool, digits: u64) -> BigDecimal {
+    let square = round(&x.square(), digits);
+    let mut term = if start == 0 {
+        BigDecimal::one()
+    } else {
+        x.clone()
+    };
+    let epsilon = epsilon(&term, digits);
+    let mut sum = term.clone();
+    let mut n = start;
+    loop {
+        let step = BigDecimal::from((n + 1) * (n + 2));
+        term = divide(&(&term * &square), &step, digits);
+        if alternating {
+            term = -term;
+        }
+        if term.abs() < epsilon {
+            return round(&sum, digits);
+        }
+        sum += &term;
+        n += 2;
+    }
+}
+
+/// The sum of `x - x^3/3 + x^5/5 - ...` (arc tangent), or `x + x^3/3 + x^5/5 + ...`
+/// (hyperbolic arc tangent), for `|x| < 1`.
+fn arctan_series(x: &BigDecimal, hyperbolic: bool, digits: u64) -> BigDecimal {
+    if x.is_zero() {
+        return BigDecimal::zero();
+    }
+    let square = round(&x.square(), digits);
+    let epsilon = epsilon(x, digits);
+    let mut power = x.clone();
+    let mut sum = x.clone();
+    let mut n = 1u64;
+    loop {
+        power = round(&(&power * &square), digits);
+        if !hyperbolic {
+            power = -power;
+        }
+        n += 2;
+        let term = divide(&power, &BigDecimal::from(n), digits);
+        if term.abs() < epsilon {
+            return round(&sum, digits);
+        }
+        sum += term;
+    }
+}
+
+/// π, by Machin's formula `π = 16 atan(1/5) - 4 atan(1/239)`.
+fn pi(digits: u64) -> BigDecimal {
+    let digits = digits + 2;
+    let fifth = BigDecimal::new(2.into(), 1);
+    let inverse = divide(&BigDecimal::one(), &BigDecimal::from(239), digits);
+    let value = arctan_series(&fifth, false, digits) * BigDecimal::from(16)
+        - arctan_series(&inverse, false, digits) * BigDecimal::from(4);
+    round(&value, digits)
+}
+
+fn ln2(digits: u64) -> BigDecimal {
+    let third = divide(&BigDecimal::one(), &BigDecimal::from(3), digits);
+    arctan_series(&third, true, digits).double()
+}
+
+/// ln 10 = 3 ln 2 + ln 1.25, where ln 1.25 = 2 atanh(1/9).
+fn ln10(digits: u64) -> BigDecimal {
+    let ninth = divide(&BigDecimal::one(), &BigDecimal::from(9), digits);
+    let value = ln2(digits) * BigDecimal::from(3) + arctan_series(&ninth, true, digits).double();
+    round(&value, digits)
+}
+
+fn exp(x: &BigDecimal, digits: u64) -> Option<BigDecimal> {
+    if x.abs() > MAX_EXP_ARGUMENT {
+        return None;
+    }
+    // e^x = (e^(x / 2^k))^(2^k), with |x / 2^k| <= 1/2 for a fast convergence.
+    let half = BigDecimal::new(5.into(), 1);
+    let mut reduced = x.clone();
+    let mut squarings = 0u64;
+    while reduced.abs() > half {
+        reduced = reduced.half();
+        squarings += 1;
+    }
+    // Each squaring doubles the relative error.
+    let digits = digits + squarings * 3 / 10 + 1;
+    let mut value = taylor_exp(&reduced, digits);
+    for _ in 0..squarings {
+        value = round(&value.square(), digits);
+    }
+    Some(value)
+}
+
+fn taylor_exp(x: &BigDecimal, digits: u64) -> BigDecimal {
+    let epsilon = epsilon(&BigDecimal::one(), digits);
+    let mut term = BigDecimal::one();
+    let mut sum = BigDecimal::one();
+    let mut n = 1u64;
+    loop {
+        term = divide(&(&term * x), &BigDecimal::from(n), digits);
+        if term.abs() < epsilon {
+            return round(&sum, digits);
+        }
+        sum += &term;
+        n += 1;
+    }
+}
+
+/// `e^x` and `e^-x`.
+fn exp_pair(x: &BigDecimal, digits: u64) -> Option<(BigDecimal, BigDecimal)> {
+    let value = exp(x, digits)?;
+    let inverse = divide(&BigDecimal::one(), &value, digits);
+    Some((value, inverse))
+}
+
+fn ln(x: &BigDecimal, digits: u64) -> Option<BigDecimal> {
+    if !x.is_positive() {
+        return None;
+    }
+    // ln x = 2 atanh((x - 1) / (x + 1)), which converges quickly for x close to 1.
+    let series = |x: &BigDecimal, digits| {
+        let one = BigDecimal::one();
+        arctan_series(&divide(&(x - &one), &(x + &one), digits), true, digits).double()
+    };
+    if *x >= BigDecimal::new(5.into(), 1) && *x <= 2 {
+        return Some(series(x, digits));
+    }
+
+    // Otherwise x = m 2^k 10^e with m in [0.75, 1.5).
+    let exponent = x.order_of_magnitude();
+    let digits = digits + exponent.unsigned_abs().checked_ilog10().unwrap_or(0) as u64 + 1;
+    let (mantissa, scale) = x.as_bigint_and_exponent();
+    let mut mantissa = BigDecimal::new(mantissa, scale + exponent);
+    let mut twos = 0u32;
+    while mantissa >= BigDecimal::new(15.into(), 1) {
+        mantissa = mantissa.half();
+        twos += 1;
+    }
+    let value = series(&mantissa, digits)
+        + ln2(digits) * BigDecimal::from(twos)
+        + ln10(digits) * BigDecimal::from(exponent);
+    Some(round(&value, digits))
+}
+
+/// The sine and cosine of `x`.
+fn sin_cos(x: &BigDecimal, digits: u64) -> Option<(BigDecimal, BigDecimal)> {
+    let magnitude = x.order_of_magnitude();
+    if magnitude > MAX_TRIG_MAGNITUDE {
+        return None;
+    }
+    // Reduces x to [-π, π], which needs π to as many more digits as x has before its point.
+    let reduction_digits = digits + magnitude.max(0) as u64;
+    let tau = pi(reduction_digits).double();
+    let turns = divide(x, &tau, reduction_digits).with_scale_round(0, RoundingMode::HalfEven);
+    let reduced = round(&(x - turns * tau), digits);
+    Some((
+        taylor(&reduced, 1, true, digits),
+        taylor(&reduced, 0, true, digits),
+    ))
+}
+
+fn atan(x: &BigDecimal, digits: u64) -> BigDecimal {
+    let one = BigDecimal::one();
+    if x.abs() > one {
+        // atan(x) = ±π/2 - atan(1/x)
+        let inverse = divide(&one, x, digits);
+        return with_sign(pi(digits).half(), x) - atan(&inverse, digits);
+    }
+    // atan(x) = 2 atan(x / (1 + sqrt(1 + x^2))) brings x close to 0.
+    let tenth = BigDecimal::new(1.into(), 1);
+    let mut reduced = x.clone();
+    let mut doublings = 0u32;
+    while reduced.abs() > tenth {
+        let root = sqrt(&(&one + reduced.square()), digits).unwrap_or_else(|| one.clone());
+        reduced = divide(&reduced, &(&one + root), digits);
+        doublings += 1;
+    }
+    arctan_series(&reduced, false, digits) * BigDecimal::from(1u64 << doublings)
+}
+
+fn radians_per_unit(unit: AngleMode, digits: u64) -> BigDecimal {
+    match unit {
+        AngleMode::Radians => BigDecimal::one(),
+        AngleMode::Degrees => divide(&pi(digits), &BigDecimal::from(180), digits),
+        AngleMode::Gradians => divide(&pi(digits), &BigDecimal::from(200), digits),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(text: &str) -> Decimal {
+        Decimal::new(BigDecimal::from_str(text).unwrap(), DEFAULT_PRECISION)
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!((decimal("0.1") + decimal("0.2")).to_string(), "0.3");
+        assert_eq!(
+            (decimal("1") / decimal("3")).to_string(),
+            "0.33333333333333333333333333333333333333333333333333"
+        );
+        assert_eq!(
+            (decimal("2") / decimal("3")).to_string(),
+            "0.66666666666666666666666666666666666666666666666667"
+        );
+        assert_eq!((decimal("-7.5") % decimal("2")).to_string(), "0.5");
+        assert_eq!((decimal("1e30") * decimal("1e30")).to_string(), "1e60");
+        assert_eq!(
+            decimal("2").pow(&decimal("100")).unwrap().to_string(),
+            "1267650600228229401496703205376"
+        );
+        assert_eq!(decimal("0").pow(&decimal("-1")), None);
+        assert_eq!(decimal("0").pow(&decimal("0")).unwrap().to_string(), "1");
+        assert_eq!(decimal("-8").pow(&decimal("0.5")), None);
+        assert_eq!(
+            decimal("30").factorial(100).unwrap().to_string(),
+            "265252859812191058636308480000000"
+        );
+    }
+
+    #[test]
+    fn test_huge_exponents() {
+        let huge = decimal("1e1000000000");
+        let third = decimal("1") / decimal("3");
+        assert_eq!((huge.clone() + third.clone()).to_string(), "1e1000000000");
+        assert_eq!((third - huge.clone()).to_string(), "-1e1000000000");
+        assert_eq!((huge.clone() % decimal("3")).to_string(), "1");
+        assert_eq!((-huge.clone() % decimal("7")).to_string(), "3");
+        assert_eq!((decimal("-1e-1000000") % decimal("3")).to_string(), "3");
+        assert_eq!(huge.factorial(100), None);
+        assert_eq!(decimal("2").pow(&huge), None);
+        assert_eq!(decimal("1").pow(&huge).unwrap().to_string(), "1");
+        assert_eq!(huge.hypot(&decimal("1")).unwrap(), huge);
+    }
+
+    #[test]
+    fn test_constants() {
+        let constant = |name| {
+            Decimal::constant(name, DEFAULT_PRECISION)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            constant("pi"),
+            "3.1415926535897932384626433832795028841971693993751"
+        );
+        assert_eq!(
+            constant("e"),
+            "2.7182818284590452353602874713526624977572470937"
+        );
+        assert_eq!(
+            constant("phi"),
+            "1.6180339887498948482045868343656381177203091798058"
+        );
+        assert_eq!(Decimal::constant("inf", DEFAULT_PRECISION), None);
+    }
+
+    type Function = fn(&Decimal) -> Option<Decimal>;
+
+    #[test]
+    fn test_functions() {
+        let cases: [(Function, &str, &str); 14] = [
+            (
+                Decimal::sqrt,
+                "2",
+                "1.4142135623730950488016887242096980785696718753769",
+            ),
+            (
+                Decimal::cbrt,
+                "2",
+                "1.2599210498948731647672106072782283505702514647015",
+            ),
+            (
+                Decimal::ln,
+                "2",
+                "0.69314718055994530941723212145817656807550013436026",
+            ),
+            (
+                Decimal::ln,
+                "1e-100",
+                "-230.25850929940456840179914546843642076011014886288",
+            ),
+            (
+                Decimal::exp,
+                "-10",
+                "0.000045399929762484851535591515560550610237918088866565",
+            ),
+            (Decimal::log10, "1000", "3"),
+            (
+                Decimal::sin,
+                "1",
+                "0.84147098480789650665250232163029899962256306079837",
+            ),
+            (
+                Decimal::cos,
+                "100",
+                "0.86231887228768393410193851395084253551008400853551",
+            ),
+            (
+                Decimal::tan,
+                "-1",
+                "-1.5574077246549022305069748074583601730872507723815",
+            ),
+            (
+                Decimal::asin,
+                "0.5",
+                "0.52359877559829887307710723054658381403286156656252",
+            ),
+            (
+                Decimal::acos,
+                "0.999",
+                "0.044725087168733431249696232671551069904180556762158",
+            ),
+            (
+                Decimal::atan,
+                "10",
+                "1.4711276743037345918528755717617308518553063771832",
+            ),
+            (
+                Decimal::sinh,
+                "1e-20",
+                "0.000000000000000000010000000000000000000000000000000000000000166666667",
+            ),
+            (
+                Decimal::atanh,
+                "0.9",
+                "1.4722194895832202300045137159439267686186896306496",
+            ),
+        ];
+        for (function, argument, expected) in cases {
+            assert_eq!(
+                function(&decimal(argument)).unwrap().to_string(),
+                expected,
+                "{}",
+                argument
+            );
+        }
+        assert_eq!(decimal("-1").sqrt(), None);
+        assert_eq!(decimal("0").ln(), None);
+        assert_eq!(decimal("1.5").asin(), None);
+    }
+
+    #[test]
+    fn test_convert_angle() {
+        let angle = decimal("30").convert_angle(AngleMode::Degrees, AngleMode::Radians);
+        assert_eq!(angle.sin().unwrap().to_string(), "0.5",);
+        let angle = decimal("1").convert_angle(AngleMode::Radians, AngleMode::Gradians);
+        assert_eq!(
+            angle.to_string(),
+            "63.661977236758134307553505349005744813783858296183"
+        );
+    }
+}
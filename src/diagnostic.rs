@@ -0,0 +1,296 @@
+//! # Diagnostic Module
+//!
+//! This module provides functionality for rendering errors in the style of `rustc`:
+//! the offending input line is printed, the erroneous span is underlined with `^~~~`,
+//! and an optional label and help note explain what went wrong.
+//!
+//! ```text
+//! error[E0301]: division by zero
+//!  --> 1:5
+//!   |
+//! 1 | 1 / (2 - 2)
+//!   |     ^~~~~~~ this evaluates to zero
+//! ```
+//!
+//! ## Diagnostic
+//!
+//! The `Diagnostic` struct holds a message, the `Span` it refers to, an optional label
+//! displayed next to the carets and an optional help note. A `CalcError` from any stage
+//! can be converted into a `Diagnostic` with `From`; its error code is shown in the title.
+//!
+//! ## Functions
+//!
+//! - `Diagnostic::render(&self, source: &str, color: bool) -> String`: Renders the diagnostic against the source it was produced from.
+
+use std::fmt::Write;
+
+use crate::error::{CalcError, EvaluationError, LexError, ParseError};
+use crate::lexer::Span;
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub span: Span,
+    pub label: Option<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            code: None,
+            message: message.into(),
+            span,
+            label: None,
+            help: None,
+        }
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn render(&self, source: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{}{}{}", style, text, RESET)
+            } else {
+                String::from(text)
+            }
+        };
+
+        let start = self.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line = &source[line_start..line_end];
+        let line_number = source[..line_start].matches('\n').count() + 1;
+        let column = source[line_start..start].chars().count();
+        let end = self.span.end.clamp(start, line_end);
+        let width = source[start..end].chars().count().max(1);
+
+        let number = line_number.to_string();
+        let padding = " ".repeat(number.len());
+        let gutter = paint(BLUE, "|");
+
+        let mut out = String::new();
+        let title = match self.code {
+            Some(code) => format!("error[{}]", code),
+            None => String::from("error"),
+        };
+        let _ = writeln!(
+            out,
+            "{}{}",
+            paint(RED, &title),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+        let _ = writeln!(
+            out,
+            "{}{} {}:{}",
+            padding,
+            paint(BLUE, "-->"),
+            line_number,
+            column + 1
+        );
+        let _ = writeln!(out, "{} {}", padding, gutter);
+        let _ = writeln!(out, "{} {} {}", paint(BLUE, &number), gutter, line);
+
+        let mut underline = String::from("^");
+        underline.push_str(&"~".repeat(width - 1));
+        if let Some(label) = &self.label {
+            underline.push(' ');
+            underline.push_str(label);
+        }
+        let _ = writeln!(
+            out,
+            "{} {} {}{}",
+            padding,
+            gutter,
+            " ".repeat(column),
+            paint(RED, &underline)
+        );
+
+        if let Some(help) = &self.help {
+            let _ = writeln!(out, "{} {}", padding, gutter);
+            let _ = writeln!(
+                out,
+                "{} {} {} {}",
+                padding,
+                paint(BLUE, "="),
+                paint(BOLD, "help:"),
+                help
+            );
+        }
+
+        out
+    }
+}
+
+impl From<&CalcError> for Diagnostic {
+    fn from(err: &CalcError) -> Self {
+        let diagnostic = Diagnostic::new(err.to_string(), err.span()).with_code(err.code());
+        match err {
+            CalcError::Lex(LexError::InvalidCharacter { .. }) => {
+                diagnostic.with_label("not valid in an expression")
+            }
+            CalcError::Lex(LexError::MalformedNumber { .. }) => diagnostic
+                .with_label("not a valid number")
+                .with_help("numbers are written like `42`, `3.14`, `.5`, `6.02e23`, `0x1F` or `1_000`"),
+            CalcError::Parse(ParseError::UnexpectedToken { .. }) => {
+                diagnostic.with_label("expected a number, a name, `(` or `[`")
+            }
+            CalcError::Parse(ParseError::UnexpectedEndOfInput { .. }) => {
+                diagnostic.with_label("expression is incomplete")
+            }
+            CalcError::Parse(ParseError::ExpectedToken { expected, .. }) => {
+                diagnostic.with_label(format!("expected `{}` here", expected))
+            }
+            CalcError::Parse(ParseError::TrailingToken { .. }) => diagnostic
+                .with_label("expected an operator or the end of the expression")
+                .with_help("separate multiple expressions with `;`"),
+            CalcError::Parse(ParseError::InvalidAssignmentTarget { .. }) => diagnostic
+                .with_label("cannot assign to this")
+                .with_help("assign to a variable, as in `x = 1`, or define a function, as in `f(x) = x * 2`"),
+            CalcError::Parse(ParseError::UnknownUnit { .. }) => diagnostic
+                .with_label("not a unit")
+                .with_help("units are written like `m`, `km/h`, `kg m^2/s^2` or `W/(m^2 K)`"),
+            CalcError::Eval(EvaluationError::DivisionByZero { .. }) => {
+                diagnostic.with_label("this evaluates to zero")
+            }
+            CalcError::Eval(EvaluationError::InvalidOperation { .. }) => {
+                diagnostic.with_label("this operation is not supported")
+            }
+            CalcError::Eval(EvaluationError::UndefinedVariable { name, .. }) => diagnostic
+                .with_label("not defined")
+                .with_help(format!("assign a value first, e.g. `{} = 1`", name)),
+            CalcError::Eval(EvaluationError::UndefinedFunction { name, .. }) => diagnostic
+                .with_label("not defined")
+                .with_help(format!("define it first, e.g. `{}(x) = x * 2`", name)),
+            CalcError::Eval(EvaluationError::ArityMismatch { expected, .. }) => {
+                diagnostic.with_label(format!("expected {} argument(s)", expected))
+            }
+            CalcError::Eval(EvaluationError::RecursionLimit { .. }) => {
+                diagnostic.with_label("this call recurses too deeply")
+            }
+            CalcError::Eval(EvaluationError::ConstantAssignment { name, .. }) => diagnostic
+                .with_label("built-in constant")
+                .with_help(format!("use another name, e.g. `my_{} = ...`", name)),
+            CalcError::Eval(EvaluationError::DomainError { .. }) => {
+                diagnostic.with_label("argument outside of the domain")
+            }
+            CalcError::Eval(EvaluationError::Overflow { .. }) => {
+                diagnostic.with_label("this is too large to represent")
+            }
+            CalcError::Eval(EvaluationError::DimensionMismatch { found, .. }) => {
+                diagnostic.with_label(format!("this has dimension `{}`", found))
+            }
+            CalcError::Eval(EvaluationError::ShapeMismatch { found, .. }) => {
+                diagnostic.with_label(format!("this has shape `{}`", found))
+            }
+            CalcError::Eval(EvaluationError::SingularMatrix { .. }) => diagnostic
+                .with_label("this matrix has no inverse")
+                .with_help("a matrix is singular when its determinant is zero"),
+            CalcError::Eval(EvaluationError::EmptyList { .. }) => {
+                diagnostic.with_label("this needs at least one number")
+            }
+            CalcError::Eval(EvaluationError::TypeMismatch { found, .. }) => {
+                diagnostic.with_label(format!("this is a {}", found))
+            }
+            CalcError::Eval(EvaluationError::NonRealResult { .. }) => diagnostic
+                .with_label("this is not a real number")
+                .with_help("`eval_value_with` and `evaluate_value` return complex numbers and matrices"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_underlines_span() {
+        let diagnostic = Diagnostic::new("division by zero", Span::new(4, 11))
+            .with_label("this evaluates to zero");
+        let expected = "\
+error: division by zero
+ --> 1:5
+  |
+1 | 1 / (2 - 2)
+  |     ^~~~~~~ this evaluates to zero
+";
+        assert_eq!(diagnostic.render("1 / (2 - 2)", false), expected);
+    }
+
+    #[test]
+    fn test_render_help_and_end_of_input() {
+        let diagnostic = Diagnostic::new("expected right parenthese", Span::new(6, 6))
+            .with_help("add a closing `)`");
+        let expected = "\
+error: expected right parenthese
+ --> 1:7
+  |
+1 | 1 + (2
+  |       ^
+  |
+  = help: add a closing `)`
+";
+        assert_eq!(diagnostic.render("1 + (2", false), expected);
+    }
+
+    #[test]
+    fn test_render_multiline_source() {
+        let diagnostic = Diagnostic::new("invalid character `#`", Span::new(8, 9));
+        let expected = "\
+error: invalid character `#`
+ --> 2:3
+  |
+2 | 3 # 4
+  |   ^
+";
+        assert_eq!(diagnostic.render("1 + 2\n3 # 4", false), expected);
+    }
+
+    #[test]
+    fn test_render_with_color() {
+        let diagnostic = Diagnostic::new("invalid character `#`", Span::new(0, 1));
+        let rendered = diagnostic.render("#", true);
+        assert!(rendered.starts_with("\x1b[1;31merror\x1b[0m"));
+        assert!(rendered.contains("\x1b[1;31m^\x1b[0m"));
+    }
+
+    #[test]
+    fn test_from_calc_error() {
+        let err = CalcError::from(EvaluationError::DivisionByZero {
+            span: Span::new(4, 11),
+        });
+        let expected = "\
+error[E0301]: division by zero
+ --> 1:5
+  |
+1 | 1 / (2 - 2)
+  |     ^~~~~~~ this evaluates to zero
+";
+        assert_eq!(
+            Diagnostic::from(&err).render("1 / (2 - 2)", false),
+            expected
+        );
+    }
+}
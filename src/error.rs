@@ -1,16 +1,396 @@
-use std::fmt::{self, write};
+//! # Error Module
+//!
+//! This module defines the errors returned by every stage of the calculator.
+//!
+//! ## CalcError
+//!
+//! `CalcError` is the single error type returned by the public functions of the crate.
+//! It wraps one of the following sub-kinds, each of which carries the `Span` of the input
+//! responsible for the error:
+//!
+//! - `LexError`: Errors raised while tokenizing the input.
+//! - `ParseError`: Errors raised while parsing the tokens into an AST.
+//! - `EvaluationError`: Errors raised while evaluating the AST.
+//!
+//! ## Error Codes
+//!
+//! Every error has a stable code that can be used to look it up or to match on it
+//! without comparing messages:
+//!
+//! | Code    | Error                                  |
+//! |---------|----------------------------------------|
+//! | `E0102` | `LexError::InvalidCharacter`           |
+//! | `E0103` | `LexError::MalformedNumber`            |
+//! | `E0201` | `ParseError::UnexpectedToken`          |
+//! | `E0202` | `ParseError::UnexpectedEndOfInput`     |
+//! | `E0203` | `ParseError::ExpectedToken`            |
+//! | `E0204` | `ParseError::TrailingToken`            |
+//! | `E0205` | `ParseError::InvalidAssignmentTarget`  |
+//! | `E0206` | `ParseError::UnknownUnit`              |
+//! | `E0301` | `EvaluationError::DivisionByZero`      |
+//! | `E0302` | `EvaluationError::InvalidOperation`    |
+//! | `E0303` | `EvaluationError::UndefinedVariable`   |
+//! | `E0304` | `EvaluationError::UndefinedFunction`   |
+//! | `E0305` | `EvaluationError::ArityMismatch`       |
+//! | `E0306` | `EvaluationError::RecursionLimit`      |
+//! | `E0307` | `EvaluationError::ConstantAssignment`  |
+//! | `E0308` | `EvaluationError::DomainError`         |
+//! | `E0309` | `EvaluationError::Overflow`            |
+//! | `E0310` | `EvaluationError::DimensionMismatch`   |
+//! | `E0311` | `EvaluationError::ShapeMismatch`       |
+//! | `E0312` | `EvaluationError::SingularMatrix`      |
+//! | `E0313` | `EvaluationError::EmptyList`           |
+//! | `E0314` | `EvaluationError::TypeMismatch`        |
+//! | `E0315` | `EvaluationError::NonRealResult`       |
 
-#[derive(Debug)]
+use std::error::Error;
+use std::fmt;
+
+use crate::function::Arity;
+use crate::lexer::{Span, TokenKind};
+use crate::matrix::{Shape, ShapeRequirement};
+use crate::unit::Dimension;
+use crate::value::ValueType;
+
+#[derive(Debug, PartialEq)]
+pub enum CalcError {
+    Lex(LexError),
+    Parse(ParseError),
+    Eval(EvaluationError),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LexError {
+    InvalidCharacter { character: char, span: Span },
+    MalformedNumber { text: String, span: Span },
+}
+
+#[derive(Debug, PartialEq)]
 pub enum ParseError {
-    UnexpectedToken,
-    ExpectedNumber,
+    UnexpectedToken { found: TokenKind, span: Span },
+    UnexpectedEndOfInput { span: Span },
+    ExpectedToken { expected: TokenKind, span: Span },
+    TrailingToken { found: TokenKind, span: Span },
+    InvalidAssignmentTarget { span: Span },
+    UnknownUnit { name: String, span: Span },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EvaluationError {
+    DivisionByZero {
+        span: Span,
+    },
+    InvalidOperation {
+        span: Span,
+    },
+    UndefinedVariable {
+        name: String,
+        span: Span,
+    },
+    UndefinedFunction {
+        name: String,
+        span: Span,
+    },
+    ArityMismatch {
+        name: String,
+        expected: Arity,
+        found: usize,
+        span: Span,
+    },
+    RecursionLimit {
+        name: String,
+        limit: usize,
+        span: Span,
+    },
+    ConstantAssignment {
+        name: String,
+        span: Span,
+    },
+    DomainError {
+        function: String,
+        argument: f64,
+        span: Span,
+    },
+    Overflow {
+        function: String,
+        span: Span,
+    },
+    DimensionMismatch {
+        expected: Dimension,
+        found: Dimension,
+        span: Span,
+    },
+    ShapeMismatch {
+        expected: ShapeRequirement,
+        found: Shape,
+        span: Span,
+    },
+    SingularMatrix {
+        span: Span,
+    },
+    EmptyList {
+        span: Span,
+    },
+    TypeMismatch {
+        expected: ValueType,
+        found: ValueType,
+        span: Span,
+    },
+    NonRealResult {
+        value: String,
+        span: Span,
+    },
+}
+
+impl CalcError {
+    pub fn span(&self) -> Span {
+        match self {
+            CalcError::Lex(err) => err.span(),
+            CalcError::Parse(err) => err.span(),
+            CalcError::Eval(err) => err.span(),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            CalcError::Lex(err) => err.code(),
+            CalcError::Parse(err) => err.code(),
+            CalcError::Eval(err) => err.code(),
+        }
+    }
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::InvalidCharacter { span, .. } | LexError::MalformedNumber { span, .. } => {
+                *span
+            }
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            LexError::InvalidCharacter { .. } => "E0102",
+            LexError::MalformedNumber { .. } => "E0103",
+        }
+    }
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnexpectedToken { span, .. }
+            | ParseError::UnexpectedEndOfInput { span }
+            | ParseError::ExpectedToken { span, .. }
+            | ParseError::TrailingToken { span, .. }
+            | ParseError::InvalidAssignmentTarget { span }
+            | ParseError::UnknownUnit { span, .. } => *span,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::UnexpectedToken { .. } => "E0201",
+            ParseError::UnexpectedEndOfInput { .. } => "E0202",
+            ParseError::ExpectedToken { .. } => "E0203",
+            ParseError::TrailingToken { .. } => "E0204",
+            ParseError::InvalidAssignmentTarget { .. } => "E0205",
+            ParseError::UnknownUnit { .. } => "E0206",
+        }
+    }
+}
+
+impl EvaluationError {
+    pub fn span(&self) -> Span {
+        match self {
+            EvaluationError::DivisionByZero { span }
+            | EvaluationError::InvalidOperation { span }
+            | EvaluationError::UndefinedVariable { span, .. }
+            | EvaluationError::UndefinedFunction { span, .. }
+            | EvaluationError::ArityMismatch { span, .. }
+            | EvaluationError::RecursionLimit { span, .. }
+            | EvaluationError::ConstantAssignment { span, .. }
+            | EvaluationError::DomainError { span, .. }
+            | EvaluationError::Overflow { span, .. }
+            | EvaluationError::DimensionMismatch { span, .. }
+            | EvaluationError::ShapeMismatch { span, .. }
+            | EvaluationError::SingularMatrix { span }
+            | EvaluationError::EmptyList { span }
+            | EvaluationError::TypeMismatch { span, .. }
+            | EvaluationError::NonRealResult { span, .. } => *span,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            EvaluationError::DivisionByZero { .. } => "E0301",
+            EvaluationError::InvalidOperation { .. } => "E0302",
+            EvaluationError::UndefinedVariable { .. } => "E0303",
+            EvaluationError::UndefinedFunction { .. } => "E0304",
+            EvaluationError::ArityMismatch { .. } => "E0305",
+            EvaluationError::RecursionLimit { .. } => "E0306",
+            EvaluationError::ConstantAssignment { .. } => "E0307",
+            EvaluationError::DomainError { .. } => "E0308",
+            EvaluationError::Overflow { .. } => "E0309",
+            EvaluationError::DimensionMismatch { .. } => "E0310",
+            EvaluationError::ShapeMismatch { .. } => "E0311",
+            EvaluationError::SingularMatrix { .. } => "E0312",
+            EvaluationError::EmptyList { .. } => "E0313",
+            EvaluationError::TypeMismatch { .. } => "E0314",
+            EvaluationError::NonRealResult { .. } => "E0315",
+        }
+    }
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CalcError::Lex(err) => err.fmt(f),
+            CalcError::Parse(err) => err.fmt(f),
+            CalcError::Eval(err) => err.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexError::InvalidCharacter { character, .. } => {
+                write!(f, "invalid character `{}`", character)
+            }
+            LexError::MalformedNumber { text, .. } => write!(f, "malformed number `{}`", text),
+        }
+    }
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            ParseError::UnexpectedToken => write!(f, "Unexpected token"),
-            ParseError::ExpectedNumber => write!(f, "Expected number"),
+            ParseError::UnexpectedToken { found, .. } => write!(f, "unexpected token `{}`", found),
+            ParseError::UnexpectedEndOfInput { .. } => write!(f, "unexpected end of input"),
+            ParseError::ExpectedToken { expected, .. } => write!(f, "expected `{}`", expected),
+            ParseError::TrailingToken { found, .. } => {
+                write!(f, "unexpected token `{}` after expression", found)
+            }
+            ParseError::InvalidAssignmentTarget { .. } => write!(f, "invalid assignment target"),
+            ParseError::UnknownUnit { name, .. } => write!(f, "unknown unit `{}`", name),
+        }
+    }
+}
+
+impl fmt::Display for EvaluationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvaluationError::DivisionByZero { .. } => write!(f, "division by zero"),
+            EvaluationError::InvalidOperation { .. } => write!(f, "invalid operation"),
+            EvaluationError::UndefinedVariable { name, .. } => {
+                write!(f, "undefined variable `{}`", name)
+            }
+            EvaluationError::UndefinedFunction { name, .. } => {
+                write!(f, "undefined function `{}`", name)
+            }
+            EvaluationError::ArityMismatch {
+                name,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "function `{}` takes {} argument(s) but {} were supplied",
+                name, expected, found
+            ),
+            EvaluationError::RecursionLimit { name, limit, .. } => write!(
+                f,
+                "recursion limit of {} calls exceeded in `{}`",
+                limit, name
+            ),
+            EvaluationError::ConstantAssignment { name, .. } => {
+                write!(f, "cannot assign to constant `{}`", name)
+            }
+            EvaluationError::DomainError {
+                function, argument, ..
+            } => write!(f, "`{}` is not defined for {}", function, argument),
+            EvaluationError::Overflow { function, .. } => {
+                write!(f, "result of `{}` overflows", function)
+            }
+            EvaluationError::DimensionMismatch {
+                expected, found, ..
+            } => write!(
+                f,
+                "expected a quantity of dimension `{}`, found `{}`",
+                expected, found
+            ),
+            EvaluationError::ShapeMismatch {
+                expected, found, ..
+            } => write!(f, "expected {}, found `{}`", expected, found),
+            EvaluationError::SingularMatrix { .. } => write!(f, "matrix is singular"),
+            EvaluationError::EmptyList { .. } => write!(f, "list is empty"),
+            EvaluationError::TypeMismatch {
+                expected, found, ..
+            } => write!(f, "expected a {}, found a {}", expected, found),
+            EvaluationError::NonRealResult { value, .. } => {
+                write!(f, "expected a real result, found `{}`", value)
+            }
         }
     }
 }
+
+/// `Display` already writes the inner error, so it is not also reported as the source, which
+/// would make error-chain reporters print its message twice.
+impl Error for CalcError {}
+
+impl Error for LexError {}
+
+impl Error for ParseError {}
+
+impl Error for EvaluationError {}
+
+impl From<LexError> for CalcError {
+    fn from(err: LexError) -> Self {
+        CalcError::Lex(err)
+    }
+}
+
+impl From<ParseError> for CalcError {
+    fn from(err: ParseError) -> Self {
+        CalcError::Parse(err)
+    }
+}
+
+impl From<EvaluationError> for CalcError {
+    fn from(err: EvaluationError) -> Self {
+        CalcError::Eval(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_and_spans() {
+        let err = CalcError::from(EvaluationError::DivisionByZero {
+            span: Span::new(4, 11),
+        });
+        assert_eq!(err.code(), "E0301");
+        assert_eq!(err.span(), Span::new(4, 11));
+
+        let err = CalcError::from(ParseError::ExpectedToken {
+            expected: TokenKind::RightParen,
+            span: Span::new(6, 6),
+        });
+        assert_eq!(err.code(), "E0203");
+        assert_eq!(err.span(), Span::new(6, 6));
+    }
+
+    #[test]
+    fn test_display_and_source() {
+        let err = CalcError::from(EvaluationError::UndefinedVariable {
+            name: String::from("saad"),
+            span: Span::new(0, 4),
+        });
+        assert_eq!(err.to_string(), "undefined variable `saad`");
+        assert!(err.source().is_none());
+    }
+}
@@ -2,60 +2,1337 @@
 //!
 //! This module provides functionality for evaluating the abstract syntax tree (AST) and computing the result.
 //!
+//! ## Environment
+//!
+//! The `Environment` struct stores the variable bindings and user-defined functions visible
+//! to an evaluation. Evaluating an assignment such as `x = 3 * 2` binds `x` in the environment
+//! and yields the assigned value; later expressions evaluated with the same environment can
+//! refer to `x`.
+//!
+//! ## Native Functions
+//!
+//! The environment holds a `FunctionRegistry` (see the `function` module) of native functions,
+//! which starts with the built-in ones such as `sin`, `log(base, x)` or `hypot(x, y)`. A call
+//! is resolved to a user-defined function first, then to a native function; in both cases the
+//! number of arguments is checked against the function's arity before it is called.
+//!
+//! ## Constants
+//!
+//! The names `pi`, `e`, `tau`, `phi`, `inf` and `nan` refer to built-in constants (see
+//! `CONSTANTS`). They are resolved when the expression is evaluated and cannot be assigned,
+//! but a function parameter with the same name shadows the constant inside the function body.
+//!
+//! The names `i` and `j` refer to the imaginary unit, unless a variable with that name is
+//! defined.
+//!
+//! The environment also holds the `OperatorTable` used to parse the input evaluated in it,
+//! so embedders can register custom operators once and use them with `eval_str_with`.
+//!
+//! ## Operators
+//!
+//! Besides the arithmetic operators, `a % b` is the modulo of `a` by `b`, which is never
+//! negative (`-7 % 3` is `2` and `7 % -3` is `1`), and `n!` is the factorial of `n`. The
+//! factorial of a negative or fractional number is `NaN`. Custom operators are evaluated as
+//! calls to the function they name.
+//!
+//! ## Angle Modes
+//!
+//! The environment's `AngleMode` (radians by default, or degrees or gradians) is the unit of
+//! the arguments of `sin`, `cos` and `tan` and of the results of `asin`, `acos`, `atan` and
+//! `atan2`. An angle literal with a unit suffix, such as `30deg`, `1.2rad` or `50grad`, is
+//! converted to the current mode when evaluated, so `sin(30deg)` is `0.5` in every mode.
+//!
+//! ## Numeric Modes
+//!
+//! Values are `f64` numbers by default. In `NumericMode::Rational` (see the `value` module),
+//! literals are exact fractions, so `0.1 + 0.2` is exactly `0.3` and `1/3*3` is exactly `1`.
+//! Variables and function parameters keep exact values, and so do the native functions with an
+//! exact implementation (see the `function` module), such as `abs`, `pow` with an integer
+//! exponent or the statistics. The other native functions, such as `sqrt` or `sin`, and the
+//! constants compute with floats.
+//!
+//! In `NumericMode::Decimal`, values are decimals with a fixed number of significant digits
+//! (see the `decimal` module), and the constants and the standard native functions are
+//! computed to that precision. A native function without a decimal implementation, or a
+//! result that cannot be computed as a decimal such as `sqrt(-1)`, falls back to floats.
+//!
+//! ## Complex Numbers
+//!
+//! An imaginary literal such as `4i` or `0.5j` is a complex value (see the `value` module), so
+//! `3+4i` is the complex number with real part `3` and imaginary part `4`. Operators and native
+//! functions accept complex values, and in lenient mode a native function returns its principal
+//! complex value for real arguments outside of its real domain: `sqrt(-4)` is `2i` and `ln(-1)`
+//! is `pi*i`.
+//!
+//! Complex numbers have `f64` parts in every numeric mode, so in decimal mode `sqrt(-2)` is
+//! `1.4142135623730951i` whatever the precision, and in rational mode `(-8)^(1/3)` is not
+//! exact. In strict mode, a complex result of real arguments is a `DomainError` as in float
+//! mode, and in the rational and decimal modes an operator or native function given a complex
+//! value fails with a `NonRealResult` rather than computing with floats.
+//!
+//! `evaluate` and `execute` return `NaN` for a complex result; `evaluate_value` and
+//! `execute_value` return the complex `Value`. The environment's `ComplexFormat` tells the
+//! caller whether to display complex results in rectangular or polar form.
+//!
+//! ## Units
+//!
+//! The names of units (see the `unit` module) written after a number or after `in` and `to`
+//! refer to the units, so `9.81 m/s^2` is an acceleration and `3 km / 20 min` a speed;
+//! anywhere else a unit name is an ordinary variable, so `t * 2` is an undefined variable
+//! rather than two tonnes. Values with a unit are quantities (see the `value` module) and their
+//! dimensions are checked: adding, subtracting or taking the modulo of quantities requires the
+//! same dimension, an exponent or the operand of `!` must be a plain number, and so must the
+//! arguments of native functions. A product, quotient or power whose dimension has an exponent
+//! beyond 127 in a base unit is an invalid operation.
+//! `expr in unit` (or `expr to unit`) converts a quantity to a unit of the same dimension, as
+//! in `3 km / 20 min in km/h`. A quantity is displayed in SI base units unless it was
+//! converted; `evaluate` and `execute` return its magnitude in the unit it is displayed in.
+//!
+//! ## Matrices
+//!
+//! A matrix literal such as `[1, 2; 3, 4]` is a matrix value (see the `matrix` module) whose
+//! elements must be real numbers, and all of whose rows must have the same length. `+`, `-`
+//! and `%` combine matrices of the same shape element by element, `*` is the matrix product,
+//! and `.*`, `./` and `.^` work element by element; a matrix combined with a number applies
+//! the operation to every element. A square matrix raised to an integer is a matrix power,
+//! with `A^-1` the inverse, and `A \ b` solves the linear system `A x = b`. The native
+//! functions `det`, `inv`, `rank` and `transpose` take matrices, and the statistics functions
+//! such as `mean([3, 1, 4])` take lists of numbers, which are matrices too; the other
+//! functions, `!`, and the divisor of `/` and the exponent of `^` must be numbers. A list
+//! stands for its elements, which keep their exact values, so `sum([1, 2], 2i)` is
+//! `sum(1, 2, 2i)` and `mean([1/3, 1/3])` is `mean(1/3, 1/3)` in every numeric mode. `evaluate`
+//! and `execute` return `NaN` for a matrix result in lenient mode; `evaluate_value` and
+//! `execute_value` return the matrix `Value`.
+//!
+//! ## Booleans and Conditionals
+//!
+//! The comparisons `<`, `<=`, `>`, `>=`, `==` and `!=` give boolean values. The ordering
+//! comparisons take real numbers, and `==` and `!=` two values of the same type; quantities
+//! compared must have the same dimension. Values are compared exactly when both are rational
+//! or both are decimals. `and`, `or` and `not` take booleans, and `and` and `or` skip their
+//! right-hand side when the left-hand side decides the result. `cond ? a : b` evaluates `a`
+//! when `cond` is true and `b` otherwise, never both, so `x != 0 ? 1/x : 0` cannot divide by
+//! zero. Any other operator or native function given a boolean fails with a `TypeMismatch`.
+//! `evaluate` and `execute` return `1` for `true` and `0` for `false`.
+//!
+//! ## Evaluation Modes
+//!
+//! By default, evaluation is lenient and follows IEEE 754: `(-1)!` and `hypot(i, 1)` are `NaN`,
+//! and `pow(0, -1)` or `10^400` are `inf`. In `EvaluationMode::Strict`, every operator and
+//! native function whose arguments are finite must produce a finite result: a `NaN` is
+//! reported as a `DomainError` and an infinity as an `Overflow`. Real arguments must also give
+//! a real result, so `sqrt(-1)`, `asin(2)` and `(-8)^(1/3)` are domain errors rather than
+//! complex values. Operations on values that are already infinite or `NaN`, such as the
+//! constants `inf` and `nan`, are not checked. Division by zero is an error in both modes,
+//! including element-wise division by a matrix with a zero element.
+//!
+//! `evaluate` and `execute` return a float, which a complex number or a matrix does not have:
+//! their result is then `NaN` in lenient mode and a `NonRealResult` error in strict mode.
+//! `evaluate_value` and `execute_value` return every value as it is.
+//!
+//! ## User-defined Functions
+//!
+//! Executing a definition such as `f(x, y) = x * x + y` stores `f` in the environment.
+//! A call `f(3, 4)` evaluates its arguments, checks that their number matches the parameters
+//! and evaluates the body in a new scope. Scoping is lexical: the body sees its own parameters
+//! and the global variables, but not the parameters of its caller. Calls may not nest deeper
+//! than the environment's recursion limit, nor start from more than `MAX_DEPTH` nested
+//! sub-expressions, so runaway recursion is reported as an error instead of overflowing the
+//! stack. The error then gives the number of calls that were nested.
+//!
 //! ## Errors
 //!
-//! The `EvaluationError` enum represents the possible errors that can occur during evaluation:
+//! Evaluation fails with an `EvaluationError` (see the `error` module).
+//! Each variant carries the `Span` of the sub-expression responsible for the error:
 //!
-//! - `DivisionByZero`: Indicates an attempt to divide by zero.
+//! - `DivisionByZero`: Indicates an attempt to divide by zero; the span covers the divisor.
 //! - `InvalidOperation`: Indicates an invalid mathematical operation.
+//! - `UndefinedVariable`: Indicates a reference to a variable that has not been assigned.
+//! - `UndefinedFunction`: Indicates a call to a function that has not been defined.
+//! - `ArityMismatch`: Indicates a call with the wrong number of arguments.
+//! - `RecursionLimit`: Indicates calls nested deeper than the recursion limit or `MAX_DEPTH`.
+//! - `ConstantAssignment`: Indicates an attempt to assign to a built-in constant.
+//! - `DomainError`: Indicates, in strict mode, a function or operator applied outside of its
+//!   domain; the error records the first argument. A percentile whose percentage is outside of
+//!   `0` to `100` is one in every mode, recording the percentage.
+//! - `Overflow`: Indicates, in strict mode, a function or operator whose result is infinite.
+//! - `DimensionMismatch`: Indicates a quantity whose dimension does not fit, as in `1 m + 2 s`.
+//! - `ShapeMismatch`: Indicates a matrix whose shape does not fit, as in `[1, 2] + [1, 2, 3]`.
+//! - `SingularMatrix`: Indicates the inverse of a singular matrix, as in `inv([1, 2; 2, 4])`.
+//! - `EmptyList`: Indicates a statistic of no numbers, as in `mean([])`.
+//! - `TypeMismatch`: Indicates a value of the wrong type, as in `1 + true` or `1 ? 2 : 3`.
+//! - `NonRealResult`: Indicates, in strict mode, a complex or matrix result of `evaluate` or
+//!   `execute`, which return a float, or a complex operand in the rational or decimal mode.
 //!
 //! ## Functions
 //!
-//! - `evaluate(ast: Expression) -> Result<f64, EvaluationError>`: Evaluates the AST and computes the result.
-use crate::lexer::Token;
-use crate::parser::Expression;
+//! - `evaluate(ast: &Expression, env: &mut Environment) -> Result<f64, CalcError>`: Evaluates the AST and computes the result.
+//! - `execute(ast: &Expression, env: &mut Environment) -> Result<Option<f64>, CalcError>`: Executes a statement, storing function definitions in the environment.
+//! - `evaluate_value` and `execute_value`: Like `evaluate` and `execute`, returning the exact `Value`.
+//! - `constant(name: &str) -> Option<f64>`: Returns the value of a built-in constant.
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::f64::consts;
+use std::rc::Rc;
+
+use crate::decimal::{Decimal, GUARD_DIGITS};
+use crate::error::{CalcError, EvaluationError};
+use crate::function::{AngleUsage, Arity, FunctionRegistry, NativeFunction};
+use crate::lexer::{Literal, Span, TokenKind};
+use crate::matrix::{Matrix, MatrixError, Shape, ShapeRequirement};
+use crate::operator::OperatorTable;
+use crate::parser::{Expression, ExpressionKind};
+use crate::unit::{self, Dimension, Unit};
+use crate::value::{ComplexFormat, NumericMode, Value, ValueType};
+use num_complex::Complex64;
+use num_rational::BigRational;
+
+/// The default maximum number of nested calls to user-defined functions.
+pub const DEFAULT_RECURSION_LIMIT: usize = 256;
+
+/// The maximum number of nested sub-expressions a call to a user-defined function may start
+/// from, which keeps recursive calls within the 8 MiB stack of a main thread whatever their
+/// bodies.
+pub const MAX_DEPTH: usize = 1000;
+
+/// The built-in constants, as `(name, value, description)`.
+pub const CONSTANTS: &[(&str, f64, &str)] = &[
+    (
+        "pi",
+        consts::PI,
+        "ratio of a circle's circumference to its diameter",
+    ),
+    ("e", consts::E, "base of the natural logarithm"),
+    (
+        "tau",
+        consts::TAU,
+        "ratio of a circle's circumference to its radius",
+    ),
+    ("phi", 1.618_033_988_749_895, "golden ratio"),
+    ("inf", f64::INFINITY, "positive infinity"),
+    ("nan", f64::NAN, "not a number"),
+];
+
+pub fn constant(name: &str) -> Option<f64> {
+    CONSTANTS
+        .iter()
+        .find(|(constant, ..)| *constant == name)
+        .map(|&(_, value, _)| value)
+}
 
 #[derive(Debug, PartialEq)]
-pub enum EvaluationError {
-    DivisionByZero,
-    InvalidOperation,
-}
-
-pub fn evaluate(ast: Expression) -> Result<f64, EvaluationError> {
-    match ast {
-        Expression::Number(val) => Ok(val),
-        Expression::BinaryOp(left, op, right) => {
-            let left_val = evaluate(*left)?;
-            let right_val = evaluate(*right)?;
-            match op {
-                Token::Plus => Ok(left_val + right_val),
-                Token::Minus => Ok(left_val - right_val),
-                Token::Multiply => Ok(left_val * right_val),
-                Token::Divide => {
-                    if right_val == 0.0 {
-                        Err(EvaluationError::DivisionByZero)
-                    } else {
-                        Ok(left_val / right_val)
-                    }
+pub struct Function {
+    pub params: Vec<String>,
+    pub body: Expression,
+}
+
+#[derive(Debug, Clone)]
+pub struct Environment {
+    variables: HashMap<String, Value>,
+    functions: HashMap<String, Rc<Function>>,
+    frames: Vec<HashMap<String, Value>>,
+    recursion_limit: usize,
+    depth: usize,
+    operators: OperatorTable,
+    registry: FunctionRegistry,
+    mode: EvaluationMode,
+    angle_mode: AngleMode,
+    numeric_mode: NumericMode,
+    complex_format: ComplexFormat,
+}
+
+/// The unit of the angles taken by `sin`, `cos` and `tan` and returned by `asin`, `acos`
+/// and `atan`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum AngleMode {
+    #[default]
+    Radians,
+    Degrees,
+    Gradians,
+}
+
+impl AngleMode {
+    /// Converts `value`, an angle in this unit, to an angle in `target`.
+    pub fn convert(self, value: f64, target: AngleMode) -> f64 {
+        if self == target {
+            return value;
+        }
+        let radians = match self {
+            AngleMode::Radians => value,
+            AngleMode::Degrees => value.to_radians(),
+            AngleMode::Gradians => value * consts::PI / 200.0,
+        };
+        match target {
+            AngleMode::Radians => radians,
+            AngleMode::Degrees => radians.to_degrees(),
+            AngleMode::Gradians => radians * 200.0 / consts::PI,
+        }
+    }
+
+    /// The unit written as `suffix`, one of `deg`, `rad` and `grad`.
+    pub fn from_suffix(suffix: &str) -> Option<AngleMode> {
+        match suffix {
+            "deg" => Some(AngleMode::Degrees),
+            "rad" => Some(AngleMode::Radians),
+            "grad" => Some(AngleMode::Gradians),
+            _ => None,
+        }
+    }
+
+    /// The suffix of angle literals in this unit, as in `30deg`.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            AngleMode::Radians => "rad",
+            AngleMode::Degrees => "deg",
+            AngleMode::Gradians => "grad",
+        }
+    }
+}
+
+/// How operations whose result is not a finite number are handled.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum EvaluationMode {
+    /// Results follow IEEE 754: `(-1)!` is `NaN` and `pow(0, -1)` is `inf`.
+    #[default]
+    Lenient,
+    /// An operation on finite numbers that yields `NaN`, or a complex value from real numbers,
+    /// fails with a `DomainError`, and one that yields an infinity fails with an `Overflow`.
+    Strict,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment {
+            variables: HashMap::new(),
+            functions: HashMap::new(),
+            frames: Vec::new(),
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
+            depth: 0,
+            operators: OperatorTable::standard(),
+            registry: FunctionRegistry::standard(),
+            mode: EvaluationMode::default(),
+            angle_mode: AngleMode::default(),
+            numeric_mode: NumericMode::default(),
+            complex_format: ComplexFormat::default(),
+        }
+    }
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment::default()
+    }
+
+    /// Looks up a variable in the innermost call's scope, then among the constants,
+    /// then in the global scope, and finally resolves `i` and `j` to the imaginary unit.
+    pub fn value(&self, name: &str) -> Option<Value> {
+        self.frames
+            .last()
+            .and_then(|frame| frame.get(name).cloned())
+            .or_else(|| self.constant_value(name))
+            .or_else(|| self.variables.get(name).cloned())
+            .or_else(|| (name == "i" || name == "j").then(|| Value::Complex(Complex64::i())))
+    }
+
+    /// The value of a built-in constant, computed to the precision of the decimal mode.
+    fn constant_value(&self, name: &str) -> Option<Value> {
+        let value = constant(name)?;
+        if let NumericMode::Decimal { precision } = self.numeric_mode {
+            if let Some(decimal) = Decimal::constant(name, precision) {
+                return Some(Value::Decimal(decimal));
+            }
+        }
+        Some(Value::Float(value))
+    }
+
+    /// Looks up a variable like `value`, converting it to a float.
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.value(name).map(|value| value.to_f64())
+    }
+
+    /// Binds a variable in the innermost call's scope, or globally outside of any call.
+    pub fn set(&mut self, name: &str, value: impl Into<Value>) {
+        let scope = self.frames.last_mut().unwrap_or(&mut self.variables);
+        scope.insert(String::from(name), value.into());
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name).map(Rc::as_ref)
+    }
+
+    pub fn define(&mut self, name: &str, params: Vec<String>, body: Expression) {
+        self.functions
+            .insert(String::from(name), Rc::new(Function { params, body }));
+    }
+
+    pub fn recursion_limit(&self) -> usize {
+        self.recursion_limit
+    }
+
+    pub fn set_recursion_limit(&mut self, limit: usize) {
+        self.recursion_limit = limit;
+    }
+
+    pub fn operators(&self) -> &OperatorTable {
+        &self.operators
+    }
+
+    pub fn operators_mut(&mut self) -> &mut OperatorTable {
+        &mut self.operators
+    }
+
+    pub fn mode(&self) -> EvaluationMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: EvaluationMode) {
+        self.mode = mode;
+    }
+
+    pub fn angle_mode(&self) -> AngleMode {
+        self.angle_mode
+    }
+
+    pub fn set_angle_mode(&mut self, angle_mode: AngleMode) {
+        self.angle_mode = angle_mode;
+    }
+
+    pub fn numeric_mode(&self) -> NumericMode {
+        self.numeric_mode
+    }
+
+    pub fn set_numeric_mode(&mut self, numeric_mode: NumericMode) {
+        self.numeric_mode = numeric_mode;
+    }
+
+    pub fn complex_format(&self) -> ComplexFormat {
+        self.complex_format
+    }
+
+    pub fn set_complex_format(&mut self, complex_format: ComplexFormat) {
+        self.complex_format = complex_format;
+    }
+
+    pub fn registry(&self) -> &FunctionRegistry {
+        &self.registry
+    }
+
+    pub fn registry_mut(&mut self) -> &mut FunctionRegistry {
+        &mut self.registry
+    }
+
+    fn call(&mut self, name: &str, args: &[Expression], span: Span) -> Result<Value, CalcError> {
+        let function = match self.functions.get(name) {
+            Some(function) => Rc::clone(function),
+            None => return self.call_native(name, args, span),
+        };
+        self.check_call(name, &function, args.len(), span)?;
+        let frame = self.frame(&function, args)?;
+        self.frames.push(frame);
+        let result = evaluate_value(&function.body, self);
+        self.frames.pop();
+        result
+    }
+
+    /// Checks the number of arguments of a call to a user-defined function, and that it nests
+    /// neither more calls than the recursion limit nor more sub-expressions than `MAX_DEPTH`.
+    fn check_call(
+        &self,
+        name: &str,
+        function: &Function,
+        args: usize,
+        span: Span,
+    ) -> Result<(), CalcError> {
+        if args != function.params.len() {
+            return Err(EvaluationError::ArityMismatch {
+                name: String::from(name),
+                expected: Arity::Fixed(function.params.len()),
+                found: args,
+                span,
+            }
+            .into());
+        }
+        // The calls already nested are the limit, which is lower than the recursion limit when
+        // the bodies nest too many sub-expressions.
+        if self.frames.len() >= self.recursion_limit || self.depth >= MAX_DEPTH {
+            return Err(EvaluationError::RecursionLimit {
+                name: String::from(name),
+                limit: self.frames.len(),
+                span,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// The scope of a call, binding the parameters of `function` to the values of `args`.
+    fn frame(
+        &mut self,
+        function: &Function,
+        args: &[Expression],
+    ) -> Result<HashMap<String, Value>, CalcError> {
+        let mut frame = HashMap::new();
+        for (param, arg) in function.params.iter().zip(args) {
+            frame.insert(param.clone(), evaluate_value(arg, self)?);
+        }
+        Ok(frame)
+    }
+
+    fn call_native(
+        &mut self,
+        name: &str,
+        args: &[Expression],
+        span: Span,
+    ) -> Result<Value, CalcError> {
+        let Some((arity, function)) = self.registry.get(name) else {
+            return Err(EvaluationError::UndefinedFunction {
+                name: String::from(name),
+                span,
+            }
+            .into());
+        };
+        if !arity.accepts(args.len()) {
+            return Err(EvaluationError::ArityMismatch {
+                name: String::from(name),
+                expected: arity,
+                found: args.len(),
+                span,
+            }
+            .into());
+        }
+
+        let values = args
+            .iter()
+            .map(|arg| evaluate_value(arg, self))
+            .collect::<Result<Vec<Value>, CalcError>>()?;
+        self.apply_native(name, function, args, values, span)
+    }
+
+    /// Applies a native function to the values of its arguments. This is kept out of
+    /// `call_native`, whose stack frame is repeated for every nested call, as its frame is large.
+    #[inline(never)]
+    fn apply_native(
+        &self,
+        name: &str,
+        function: NativeFunction,
+        args: &[Expression],
+        values: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, CalcError> {
+        if let Some((arg, value)) = args
+            .iter()
+            .zip(&values)
+            .find(|(_, value)| value.value_type() == ValueType::Boolean)
+        {
+            return Err(type_mismatch(ValueType::Number, value, arg.span));
+        }
+        if let Some((arg, value)) = args
+            .iter()
+            .zip(&values)
+            .find(|(_, value)| value.is_quantity())
+        {
+            return Err(EvaluationError::DimensionMismatch {
+                expected: Dimension::NONE,
+                found: value.dimension(),
+                span: arg.span,
+            }
+            .into());
+        }
+        if let Some((arg, _)) = args
+            .iter()
+            .zip(&values)
+            .find(|(_, value)| value.is_complex() && self.registry.is_real(name))
+        {
+            return Err(EvaluationError::InvalidOperation { span: arg.span }.into());
+        }
+        // The arguments of a function taking lists, except its final numbers, may be matrices
+        // standing for their elements.
+        let lists = self
+            .registry
+            .list(name)
+            .map_or(0, |numbers| values.len().saturating_sub(numbers));
+        if let Some((arg, shape)) = args
+            .iter()
+            .zip(&values)
+            .skip(lists)
+            .find_map(|(arg, value)| Some((arg, value.shape()?)))
+        {
+            let Some(function) = self.registry.matrix(name) else {
+                return Err(EvaluationError::ShapeMismatch {
+                    expected: Shape::new(1, 1).into(),
+                    found: shape,
+                    span: arg.span,
+                }
+                .into());
+            };
+            let result = function(&values).map_err(|err| matrix_error(err, span))?;
+            return self.check(name, &values, result, span);
+        }
+        let values = match lists {
+            0 => values,
+            _ => self
+                .elements(values, lists)
+                .map_err(|err| matrix_error(err, span))?,
+        };
+        if values.iter().any(Value::is_complex) {
+            if let Some(result) = self.call_complex(name, &values) {
+                return self.check(name, &values, result, span);
+            }
+        } else if let Some(result) = self.call_decimal(name, &values) {
+            return Ok(Value::Decimal(result));
+        } else if let Some(result) = self.call_rational(name, &values) {
+            return Ok(Value::Rational(result));
+        }
+
+        let mut numbers: Vec<f64> = values.iter().map(Value::to_f64).collect();
+        let result = match self.registry.angle_usage(name) {
+            AngleUsage::None => function(&numbers),
+            AngleUsage::Arguments => {
+                for number in &mut numbers {
+                    *number = self.angle_mode.convert(*number, AngleMode::Radians);
                 }
-                Token::Pow => Ok(left_val.powf(right_val)),
-                _ => Err(EvaluationError::InvalidOperation),
+                function(&numbers)
+            }
+            AngleUsage::Result => AngleMode::Radians.convert(function(&numbers), self.angle_mode),
+        };
+        // A function taking lists is `NaN` for finite numbers only outside of its domain, as a
+        // percentage above `100`, which is an error in every mode like an empty list. The error
+        // records the first of its final numbers.
+        if let Some(count) = self.registry.list(name) {
+            if result.is_nan() && numbers.iter().all(|number| number.is_finite()) {
+                let argument = numbers
+                    .len()
+                    .checked_sub(count)
+                    .and_then(|index| numbers.get(index))
+                    .or(numbers.first());
+                return Err(EvaluationError::DomainError {
+                    function: String::from(name),
+                    argument: argument.map_or(f64::NAN, |&argument| argument),
+                    span,
+                }
+                .into());
+            }
+        }
+        // Outside of its real domain, a function may have a complex value, as `sqrt(-4)`, which
+        // strict mode reports as a domain error.
+        if self.mode == EvaluationMode::Lenient
+            && result.is_nan()
+            && numbers.iter().all(|number| number.is_finite())
+        {
+            if let Some(result) = self.call_complex(name, &values) {
+                return self.check(name, &values, result, span);
+            }
+        }
+        self.check(name, &values, Value::Float(result), span)
+    }
+
+    /// The first `lists` values, with the elements of the matrices in row order, which must not
+    /// be empty, followed by the other values.
+    fn elements(&self, values: Vec<Value>, lists: usize) -> Result<Vec<Value>, MatrixError> {
+        let mut elements = Vec::new();
+        for value in &values[..lists] {
+            match value {
+                Value::Matrix(matrix) => elements.extend_from_slice(matrix.elements()),
+                value => elements.push(value.clone()),
+            }
+        }
+        if elements.is_empty() {
+            return Err(MatrixError::Empty);
+        }
+        elements.extend_from_slice(&values[lists..]);
+        Ok(elements)
+    }
+
+    /// Calls the complex implementation of a native function if it has one.
+    fn call_complex(&self, name: &str, values: &[Value]) -> Option<Value> {
+        let function = self.registry.complex(name)?;
+        let mut args: Vec<Complex64> = values.iter().map(Value::to_complex).collect();
+        let result = match self.registry.angle_usage(name) {
+            AngleUsage::None => function(&args),
+            AngleUsage::Arguments => {
+                for arg in &mut args {
+                    *arg *= self.angle_mode.convert(1.0, AngleMode::Radians);
+                }
+                function(&args)
+            }
+            AngleUsage::Result => {
+                function(&args) * AngleMode::Radians.convert(1.0, self.angle_mode)
+            }
+        };
+        Some(Value::complex(result))
+    }
+
+    /// In decimal mode, calls the arbitrary-precision implementation of a native function if
+    /// it has one and no argument is a float.
+    fn call_decimal(&self, name: &str, values: &[Value]) -> Option<Decimal> {
+        let NumericMode::Decimal { precision } = self.numeric_mode else {
+            return None;
+        };
+        let function = self.registry.decimal(name)?;
+        let angles = self.registry.angle_usage(name);
+        if angles == AngleUsage::None {
+            let args = values
+                .iter()
+                .map(|value| value.to_decimal(precision))
+                .collect::<Option<Vec<Decimal>>>()?;
+            return function(&args);
+        }
+
+        // Converting angles rounds, so it is done with guard digits.
+        let mut args = values
+            .iter()
+            .map(|value| {
+                Some(
+                    value
+                        .to_decimal(precision)?
+                        .with_precision(precision + GUARD_DIGITS),
+                )
+            })
+            .collect::<Option<Vec<Decimal>>>()?;
+        let result = if angles == AngleUsage::Arguments {
+            for arg in &mut args {
+                *arg = arg.convert_angle(self.angle_mode, AngleMode::Radians);
+            }
+            function(&args)?
+        } else {
+            function(&args)?.convert_angle(AngleMode::Radians, self.angle_mode)
+        };
+        Some(result.with_precision(precision))
+    }
+
+    /// Calls the exact implementation of a native function if it has one, does not deal with
+    /// angles and every argument is a rational.
+    fn call_rational(&self, name: &str, values: &[Value]) -> Option<BigRational> {
+        let function = self.registry.rational(name)?;
+        if self.registry.angle_usage(name) != AngleUsage::None {
+            return None;
+        }
+        let args = values
+            .iter()
+            .map(|value| match value {
+                Value::Rational(value) => Some(value.clone()),
+                _ => None,
+            })
+            .collect::<Option<Vec<BigRational>>>()?;
+        function(&args)
+    }
+
+    /// In strict mode, turns a `NaN` or complex result computed from finite real arguments into
+    /// a `DomainError`, and an infinite one into an `Overflow`. In the rational and decimal
+    /// modes, it also rejects complex arguments with a `NonRealResult`.
+    fn check(
+        &self,
+        function: &str,
+        args: &[Value],
+        result: Value,
+        span: Span,
+    ) -> Result<Value, CalcError> {
+        // Complex numbers are computed with floats, which the exact modes must not fall back to.
+        if let Some(value) = args.iter().find(|value| value.is_complex()) {
+            if self.mode == EvaluationMode::Strict && self.numeric_mode != NumericMode::Float {
+                return Err(EvaluationError::NonRealResult {
+                    value: value.format(self.complex_format, self.angle_mode),
+                    span,
+                }
+                .into());
+            }
+        }
+
+        // A complex result of real arguments lies outside of the real domain, as `sqrt(-1)`.
+        let complex = result.is_complex() && !args.iter().any(Value::is_complex);
+        if self.mode == EvaluationMode::Lenient
+            || (result.is_finite() && !complex)
+            || !args.iter().all(Value::is_finite)
+        {
+            return Ok(result);
+        }
+
+        let function = String::from(function);
+        if result.is_nan() || complex {
+            Err(EvaluationError::DomainError {
+                function,
+                argument: args.first().map_or(f64::NAN, Value::to_f64),
+                span,
+            }
+            .into())
+        } else {
+            Err(EvaluationError::Overflow { function, span }.into())
+        }
+    }
+}
+
+/// Executes a statement: function definitions are stored in `env` and yield `None`,
+/// any other expression is evaluated and yields its value.
+pub fn execute(ast: &Expression, env: &mut Environment) -> Result<Option<f64>, CalcError> {
+    match execute_value(ast, env)? {
+        Some(value) => real(env, value, ast.span).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Executes a statement like `execute`, keeping the exact value of the result.
+pub fn execute_value(ast: &Expression, env: &mut Environment) -> Result<Option<Value>, CalcError> {
+    match &ast.kind {
+        ExpressionKind::FunctionDef(name, params, body) => {
+            env.define(name, params.clone(), (**body).clone());
+            Ok(None)
+        }
+        _ => evaluate_value(ast, env).map(Some),
+    }
+}
+
+pub fn evaluate(ast: &Expression, env: &mut Environment) -> Result<f64, CalcError> {
+    let value = evaluate_value(ast, env)?;
+    real(env, value, ast.span)
+}
+
+/// The result of `evaluate` or `execute` as a float. A complex number or a matrix has none,
+/// which is `NaN` in lenient mode and an error in strict mode.
+fn real(env: &Environment, value: Value, span: Span) -> Result<f64, CalcError> {
+    let is_real = match &value {
+        Value::Complex(value) => value.im == 0.0,
+        Value::Matrix(_) => false,
+        _ => true,
+    };
+    if !is_real && env.mode == EvaluationMode::Strict {
+        return Err(EvaluationError::NonRealResult {
+            value: value.format(env.complex_format, env.angle_mode),
+            span,
+        }
+        .into());
+    }
+    Ok(value.to_f64())
+}
+
+/// Evaluates the AST like `evaluate`, keeping the exact value of the result.
+pub fn evaluate_value(ast: &Expression, env: &mut Environment) -> Result<Value, CalcError> {
+    let span = ast.span;
+    env.depth += 1;
+    let result = match &ast.kind {
+        ExpressionKind::Number(val) => Ok(literal(env, val, None)),
+        ExpressionKind::Angle(val, unit) => Ok(literal(env, val, Some(*unit))),
+        ExpressionKind::Imaginary(val) => Ok(Value::complex(Complex64::new(0.0, *val))),
+        ExpressionKind::Boolean(val) => Ok(Value::Boolean(*val)),
+        ExpressionKind::BinaryOp(left, op @ (TokenKind::And | TokenKind::Or), right) => {
+            logical(env, left, op, right)
+        }
+        ExpressionKind::BinaryOp(left, op, right) => binary(env, left, op, right, span),
+        ExpressionKind::UnaryOp(op, expr) => unary(env, op, expr, span),
+        ExpressionKind::Variable(name) => variable(env, name, span),
+        ExpressionKind::Assign(name, value) => assign(env, name, value, span),
+        ExpressionKind::Call(name, args) => env.call(name, args, span),
+        ExpressionKind::Unit(name) => unit_value(name, span),
+        ExpressionKind::Convert(value, target) => convert(env, value, target),
+        ExpressionKind::Matrix(rows) => matrix(env, rows),
+        ExpressionKind::Conditional(condition, then, otherwise) => {
+            conditional(env, condition, then, otherwise)
+        }
+        ExpressionKind::FunctionDef(..) | ExpressionKind::Error => {
+            Err(EvaluationError::InvalidOperation { span }.into())
+        }
+    };
+    env.depth -= 1;
+    result
+}
+
+// Literals, variables, assignments and operators are evaluated outside of `evaluate_value` to
+// keep its stack frame, which is repeated for every level of nesting, small.
+
+/// The value of a number literal, converted to the angle mode if it is an angle in `unit`.
+fn literal(env: &Environment, val: &Literal, unit: Option<AngleMode>) -> Value {
+    let value = Value::literal(val.clone(), env.numeric_mode);
+    match unit {
+        Some(unit) => value.convert_angle(unit, env.angle_mode),
+        None => value,
+    }
+}
+
+fn variable(env: &Environment, name: &str, span: Span) -> Result<Value, CalcError> {
+    env.value(name).ok_or_else(|| {
+        EvaluationError::UndefinedVariable {
+            name: String::from(name),
+            span,
+        }
+        .into()
+    })
+}
+
+fn assign(
+    env: &mut Environment,
+    name: &str,
+    value: &Expression,
+    span: Span,
+) -> Result<Value, CalcError> {
+    if constant(name).is_some() {
+        return Err(EvaluationError::ConstantAssignment {
+            name: String::from(name),
+            span: Span::new(span.start, span.start + name.len()),
+        }
+        .into());
+    }
+    let val = evaluate_value(value, env)?;
+    env.set(name, val.clone());
+    Ok(val)
+}
+
+fn binary(
+    env: &mut Environment,
+    left: &Expression,
+    op: &TokenKind,
+    right: &Expression,
+    span: Span,
+) -> Result<Value, CalcError> {
+    let left_val = evaluate_value(left, env)?;
+    let right_val = evaluate_value(right, env)?;
+    apply_binary(env, op, left_val, right_val, span, left.span, right.span)
+}
+
+fn apply_binary(
+    env: &Environment,
+    op: &TokenKind,
+    left: Value,
+    right: Value,
+    span: Span,
+    left_span: Span,
+    right_span: Span,
+) -> Result<Value, CalcError> {
+    check_types(op, &left, &right, left_span, right_span)?;
+    check_dimensions(op, &left, &right, span, right_span)?;
+    if let Some(result) = compare(op, &left, &right) {
+        return Ok(Value::Boolean(result));
+    }
+    check_shapes(op, &left, &right, left_span, right_span)?;
+    // A matrix divisor with a zero element divides by zero too, as in `[1, 2] ./ [0, 1]`; the
+    // shapes are checked first so that an invalid divisor is reported whatever its elements.
+    let zero = match &right {
+        Value::Matrix(matrix) => matrix.elements().iter().any(Value::is_zero),
+        _ => right.is_zero(),
+    };
+    if matches!(
+        op,
+        TokenKind::Divide | TokenKind::Percent | TokenKind::DotDivide
+    ) && zero
+    {
+        return Err(EvaluationError::DivisionByZero { span: right_span }.into());
+    }
+    if *op == TokenKind::Backslash && left.is_zero() {
+        return Err(EvaluationError::DivisionByZero { span: left_span }.into());
+    }
+    let args = [left.clone(), right.clone()];
+    let result = match (op, &left) {
+        (TokenKind::Plus, _) => left + right,
+        (TokenKind::Minus, _) => left - right,
+        (TokenKind::Multiply, _) => left * right,
+        (TokenKind::Divide, _) => left / right,
+        (TokenKind::Percent, _) => left % right,
+        (TokenKind::Caret, Value::Matrix(matrix)) => matrix_power(matrix, &right, span)?,
+        (TokenKind::Caret, _) => left.pow(&right),
+        (TokenKind::DotMultiply, _) => left.mul_elementwise(right),
+        (TokenKind::DotDivide, _) => left.div_elementwise(right),
+        (TokenKind::DotCaret, _) => left.pow_elementwise(&right),
+        (TokenKind::Backslash, Value::Matrix(matrix)) => {
+            let solution = right.to_matrix().map(|b| matrix.solve(&b));
+            match solution {
+                Some(solution) => solution
+                    .map(Value::matrix)
+                    .map_err(|err| matrix_error(err, span))?,
+                None => return Err(EvaluationError::InvalidOperation { span: right_span }.into()),
+            }
+        }
+        (TokenKind::Backslash, _) => right / left,
+        _ => return Err(EvaluationError::InvalidOperation { span }.into()),
+    };
+    env.check(&op.to_string(), &args, result, span)
+}
+
+fn unary(
+    env: &mut Environment,
+    op: &TokenKind,
+    expr: &Expression,
+    span: Span,
+) -> Result<Value, CalcError> {
+    let val = evaluate_value(expr, env)?;
+    apply_unary(env, op, val, span)
+}
+
+fn apply_unary(
+    env: &Environment,
+    op: &TokenKind,
+    val: Value,
+    span: Span,
+) -> Result<Value, CalcError> {
+    match (op, &val) {
+        (TokenKind::Not, Value::Boolean(val)) => return Ok(Value::Boolean(!val)),
+        (TokenKind::Not, _) => return Err(type_mismatch(ValueType::Boolean, &val, span)),
+        (_, Value::Boolean(_)) => return Err(type_mismatch(ValueType::Number, &val, span)),
+        _ => {}
+    }
+    if *op == TokenKind::Bang && val.is_quantity() {
+        return Err(EvaluationError::DimensionMismatch {
+            expected: Dimension::NONE,
+            found: val.dimension(),
+            span,
+        }
+        .into());
+    }
+    if let (TokenKind::Bang, Some(shape)) = (op, val.shape()) {
+        return Err(EvaluationError::ShapeMismatch {
+            expected: Shape::new(1, 1).into(),
+            found: shape,
+            span,
+        }
+        .into());
+    }
+    let result = match op {
+        TokenKind::Minus => -val.clone(),
+        TokenKind::Bang => val.factorial(),
+        _ => return Err(EvaluationError::InvalidOperation { span }.into()),
+    };
+    env.check(&op.to_string(), &[val], result, span)
+}
+
+/// Evaluates `condition`, which must be a boolean.
+fn boolean(env: &mut Environment, condition: &Expression) -> Result<bool, CalcError> {
+    match evaluate_value(condition, env)? {
+        Value::Boolean(val) => Ok(val),
+        val => Err(type_mismatch(ValueType::Boolean, &val, condition.span)),
+    }
+}
+
+/// Evaluates `left and right` or `left or right`, skipping `right` when `left` decides the
+/// result.
+fn logical(
+    env: &mut Environment,
+    left: &Expression,
+    op: &TokenKind,
+    right: &Expression,
+) -> Result<Value, CalcError> {
+    let short_circuit = *op == TokenKind::Or;
+    if boolean(env, left)? == short_circuit {
+        return Ok(Value::Boolean(short_circuit));
+    }
+    boolean(env, right).map(Value::Boolean)
+}
+
+/// Evaluates `condition ? then : otherwise`, skipping the branch that is not taken.
+fn conditional(
+    env: &mut Environment,
+    condition: &Expression,
+    then: &Expression,
+    otherwise: &Expression,
+) -> Result<Value, CalcError> {
+    if boolean(env, condition)? {
+        evaluate_value(then, env)
+    } else {
+        evaluate_value(otherwise, env)
+    }
+}
+
+fn type_mismatch(expected: ValueType, val: &Value, span: Span) -> CalcError {
+    EvaluationError::TypeMismatch {
+        expected,
+        found: val.value_type(),
+        span,
+    }
+    .into()
+}
+
+/// Checks that the operands of a binary operator have compatible types: `==` and `!=`
+/// compare values of the same type, the other comparisons real numbers, and the arithmetic
+/// operators do not accept booleans.
+fn check_types(
+    op: &TokenKind,
+    left: &Value,
+    right: &Value,
+    left_span: Span,
+    right_span: Span,
+) -> Result<(), CalcError> {
+    if matches!(op, TokenKind::Equal | TokenKind::NotEqual) {
+        if left.value_type() != right.value_type() {
+            return Err(EvaluationError::TypeMismatch {
+                expected: left.value_type(),
+                found: right.value_type(),
+                span: right_span,
             }
+            .into());
+        }
+        return Ok(());
+    }
+    let ordering = matches!(
+        op,
+        TokenKind::Less | TokenKind::LessEqual | TokenKind::Greater | TokenKind::GreaterEqual
+    );
+    for (val, span) in [(left, left_span), (right, right_span)] {
+        match val.value_type() {
+            ValueType::Boolean => return Err(type_mismatch(ValueType::Number, val, span)),
+            ValueType::Matrix if ordering => {
+                return Err(type_mismatch(ValueType::Number, val, span));
+            }
+            _ => {}
+        }
+    }
+    if ordering && left.is_complex() {
+        return Err(EvaluationError::InvalidOperation { span: left_span }.into());
+    }
+    if ordering && right.is_complex() {
+        return Err(EvaluationError::InvalidOperation { span: right_span }.into());
+    }
+    Ok(())
+}
+
+/// Applies a comparison operator, or returns `None` for any other operator.
+fn compare(op: &TokenKind, left: &Value, right: &Value) -> Option<bool> {
+    let ordering = || left.compare(right);
+    match op {
+        TokenKind::Equal => Some(left.equals(right)),
+        TokenKind::NotEqual => Some(!left.equals(right)),
+        TokenKind::Less => Some(ordering().is_some_and(Ordering::is_lt)),
+        TokenKind::LessEqual => Some(ordering().is_some_and(Ordering::is_le)),
+        TokenKind::Greater => Some(ordering().is_some_and(Ordering::is_gt)),
+        TokenKind::GreaterEqual => Some(ordering().is_some_and(Ordering::is_ge)),
+        _ => None,
+    }
+}
+
+/// Checks that the operands of a binary operator have compatible dimensions: the same one for
+/// `+`, `-`, `%` and the comparisons, and for `^` a plain exponent that leaves integral powers
+/// of the base units. The dimension of a product or quotient must not overflow either.
+fn check_dimensions(
+    op: &TokenKind,
+    left: &Value,
+    right: &Value,
+    span: Span,
+    right_span: Span,
+) -> Result<(), CalcError> {
+    let combined = match op {
+        TokenKind::Multiply | TokenKind::DotMultiply => {
+            left.dimension().checked_mul(right.dimension())
+        }
+        TokenKind::Divide | TokenKind::DotDivide => left.dimension().checked_div(right.dimension()),
+        TokenKind::Backslash => right.dimension().checked_div(left.dimension()),
+        _ => Some(Dimension::NONE),
+    };
+    if combined.is_none() {
+        return Err(EvaluationError::InvalidOperation { span }.into());
+    }
+    let (expected, found) = match op {
+        TokenKind::Plus
+        | TokenKind::Minus
+        | TokenKind::Percent
+        | TokenKind::Equal
+        | TokenKind::NotEqual
+        | TokenKind::Less
+        | TokenKind::LessEqual
+        | TokenKind::Greater
+        | TokenKind::GreaterEqual => (left.dimension(), right.dimension()),
+        TokenKind::Caret | TokenKind::DotCaret => (Dimension::NONE, right.dimension()),
+        _ => return Ok(()),
+    };
+    if expected != found {
+        return Err(EvaluationError::DimensionMismatch {
+            expected,
+            found,
+            span: right_span,
         }
-        Expression::UnaryOp(op, expr) => {
-            let val = evaluate(*expr)?;
-            match op {
-                Token::Minus => Ok(-val),
-                Token::Cos => Ok(val.cos()),
-                Token::Acos => Ok(val.acos()),
-                Token::Sin => Ok(val.sin()),
-                Token::Asin => Ok(val.asin()),
-                Token::Tan => Ok(val.tan()),
-                Token::Atan => Ok(val.atan()),
-                Token::Sqrt => Ok(val.sqrt()),
-                _ => Err(EvaluationError::InvalidOperation),
+        .into());
+    }
+    if left.is_quantity()
+        && matches!(op, TokenKind::Caret | TokenKind::DotCaret)
+        && left.dimension().pow(right.to_f64()).is_none()
+    {
+        return Err(EvaluationError::InvalidOperation { span }.into());
+    }
+    Ok(())
+}
+
+/// Checks that the operands of a binary operator have compatible shapes when either is a
+/// matrix: the same shape for the element-wise operators, matching inner sizes for `*`, a
+/// square matrix with as many rows as the right-hand side for `\`, and a square matrix with
+/// a number as its exponent for `^`. A matrix cannot be combined with a complex number or a
+/// quantity, nor be a divisor or an exponent.
+fn check_shapes(
+    op: &TokenKind,
+    left: &Value,
+    right: &Value,
+    left_span: Span,
+    right_span: Span,
+) -> Result<(), CalcError> {
+    let (left_shape, right_shape) = (left.shape(), right.shape());
+    if left_shape.is_none() && right_shape.is_none() {
+        return Ok(());
+    }
+    if left.is_complex() || left.is_quantity() {
+        return Err(EvaluationError::InvalidOperation { span: left_span }.into());
+    }
+    if right.is_complex() || right.is_quantity() {
+        return Err(EvaluationError::InvalidOperation { span: right_span }.into());
+    }
+
+    let found = right_shape.unwrap_or(Shape::new(1, 1));
+    let expected = match (op, left_shape, right_shape) {
+        (TokenKind::Caret | TokenKind::Backslash, Some(left), _) if !left.is_square() => {
+            return Err(EvaluationError::ShapeMismatch {
+                expected: ShapeRequirement::Square,
+                found: left,
+                span: left_span,
             }
+            .into());
         }
+        (TokenKind::Divide | TokenKind::Caret, _, Some(_)) => Shape::new(1, 1).into(),
+        (TokenKind::Multiply, Some(left), Some(_)) => ShapeRequirement::Rows(left.cols),
+        (TokenKind::Backslash, Some(left), _) => ShapeRequirement::Rows(left.rows),
+        (_, Some(left), Some(_)) => left.into(),
+        _ => found.into(),
+    };
+    if !expected.accepts(found) {
+        return Err(EvaluationError::ShapeMismatch {
+            expected,
+            found,
+            span: right_span,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Raises a square matrix to an integer power, inverting it for a negative one.
+fn matrix_power(matrix: &Matrix, exponent: &Value, span: Span) -> Result<Value, CalcError> {
+    let exponent = exponent.to_f64();
+    if exponent.fract() != 0.0 || exponent.abs() > f64::from(i32::MAX) {
+        return Err(EvaluationError::InvalidOperation { span }.into());
+    }
+    matrix
+        .pow(exponent as i32)
+        .map(Value::matrix)
+        .map_err(|err| matrix_error(err, span))
+}
+
+fn matrix_error(err: MatrixError, span: Span) -> CalcError {
+    match err {
+        MatrixError::Shape { expected, found } => EvaluationError::ShapeMismatch {
+            expected,
+            found,
+            span,
+        },
+        MatrixError::Singular => EvaluationError::SingularMatrix { span },
+        MatrixError::Empty => EvaluationError::EmptyList { span },
+    }
+    .into()
+}
+
+/// Evaluates the rows of a matrix literal, whose elements must be real numbers.
+fn matrix(env: &mut Environment, rows: &[Vec<Expression>]) -> Result<Value, CalcError> {
+    let cols = rows.first().map_or(0, Vec::len);
+    let mut elements = Vec::with_capacity(rows.len() * cols);
+    for row in rows {
+        if row.len() != cols {
+            let start = row.first().map_or(0, |element| element.span.start);
+            let end = row.last().map_or(start, |element| element.span.end);
+            return Err(EvaluationError::ShapeMismatch {
+                expected: Shape::new(1, cols).into(),
+                found: Shape::new(1, row.len()),
+                span: Span::new(start, end),
+            }
+            .into());
+        }
+        for element in row {
+            let span = element.span;
+            let err: EvaluationError = match evaluate_value(element, env)? {
+                Value::Matrix(matrix) => EvaluationError::ShapeMismatch {
+                    expected: Shape::new(1, 1).into(),
+                    found: matrix.shape(),
+                    span,
+                },
+                Value::Quantity(quantity) => EvaluationError::DimensionMismatch {
+                    expected: Dimension::NONE,
+                    found: quantity.dimension,
+                    span,
+                },
+                Value::Complex(_) => EvaluationError::InvalidOperation { span },
+                Value::Boolean(_) => EvaluationError::TypeMismatch {
+                    expected: ValueType::Number,
+                    found: ValueType::Boolean,
+                    span,
+                },
+                value => {
+                    elements.push(value);
+                    continue;
+                }
+            };
+            return Err(err.into());
+        }
+    }
+    let shape = Shape::new(rows.len(), cols);
+    Ok(Value::matrix(Matrix::new(shape, elements)))
+}
+
+fn unit_value(name: &str, span: Span) -> Result<Value, CalcError> {
+    match unit::lookup(name) {
+        Some(unit) => Ok(Value::Quantity(unit.into())),
+        None => Err(EvaluationError::UndefinedVariable {
+            name: String::from(name),
+            span,
+        }
+        .into()),
+    }
+}
+
+/// Evaluates `value` and converts it to the unit written as `target`.
+fn convert(
+    env: &mut Environment,
+    value: &Expression,
+    target: &Expression,
+) -> Result<Value, CalcError> {
+    let span = value.span;
+    let value = evaluate_value(value, env)?;
+    let unit = evaluate_value(target, env)?;
+    if let Value::Boolean(_) = value {
+        return Err(type_mismatch(ValueType::Number, &value, span));
+    }
+    if value.dimension() != unit.dimension() {
+        return Err(EvaluationError::DimensionMismatch {
+            expected: unit.dimension(),
+            found: value.dimension(),
+            span,
+        }
+        .into());
+    }
+    Ok(value.in_unit(Unit {
+        name: unit_name(target),
+        factor: unit.to_si(),
+        dimension: unit.dimension(),
+    }))
+}
+
+/// The name of the unit written as `target`, as in `km/h` or `kg m^2/s^2`.
+fn unit_name(target: &Expression) -> String {
+    let compound = |expr: &Expression, ops: &[TokenKind]| {
+        let name = unit_name(expr);
+        match &expr.kind {
+            ExpressionKind::BinaryOp(_, op, _) if ops.contains(op) => format!("({})", name),
+            _ => name,
+        }
+    };
+    match &target.kind {
+        ExpressionKind::Unit(name) => name.clone(),
+        ExpressionKind::Number(val) => val.to_string(),
+        ExpressionKind::BinaryOp(left, TokenKind::Multiply, right) => format!(
+            "{} {}",
+            compound(left, &[TokenKind::Divide]),
+            compound(right, &[TokenKind::Divide])
+        ),
+        ExpressionKind::BinaryOp(left, TokenKind::Divide, right) => format!(
+            "{}/{}",
+            unit_name(left),
+            compound(right, &[TokenKind::Multiply, TokenKind::Divide])
+        ),
+        ExpressionKind::BinaryOp(left, op, right) => format!(
+            "{}{}{}",
+            compound(left, &[TokenKind::Multiply, TokenKind::Divide]),
+            op,
+            unit_name(right)
+        ),
+        _ => String::new(),
     }
 }
 
@@ -63,51 +1340,996 @@ pub fn evaluate(ast: Expression) -> Result<f64, EvaluationError> {
 mod tests {
     use crate::lexer::tokenize;
     use crate::parser::parse;
+    use crate::parser::parse_program;
+    use std::thread;
 
     use super::*;
 
     #[test]
     fn test_evaluate_number() {
         let input = "42";
-        let tokens = tokenize(input);
+        let tokens = tokenize(input).unwrap();
         let ast = parse(&tokens).unwrap();
-        let result = evaluate(ast);
+        let result = evaluate(&ast, &mut Environment::new());
         assert_eq!(result, Ok(42.0));
     }
 
     #[test]
     fn test_evaluate_binary_ops() {
         let input = "2 + 3 * 4 - 10 / 5";
-        let tokens = tokenize(input);
+        let tokens = tokenize(input).unwrap();
         let ast = parse(&tokens).unwrap();
-        let result = evaluate(ast);
+        let result = evaluate(&ast, &mut Environment::new());
         assert_eq!(result, Ok(12.0));
     }
 
     #[test]
     fn test_evaluate_unary_ops() {
         let input = "sin(0)";
-        let tokens = tokenize(input);
+        let tokens = tokenize(input).unwrap();
         let ast = parse(&tokens).unwrap();
-        let result = evaluate(ast);
+        let result = evaluate(&ast, &mut Environment::new());
         assert_eq!(result.unwrap(), 0.0);
     }
 
     #[test]
     fn test_evaluate_division_by_zero() {
         let input = "1 / (2 - 2)";
-        let tokens = tokenize(input);
+        let tokens = tokenize(input).unwrap();
         let ast = parse(&tokens).unwrap();
-        let result = evaluate(ast);
-        assert_eq!(result, Err(EvaluationError::DivisionByZero));
+        let result = evaluate(&ast, &mut Environment::new());
+        assert_eq!(
+            result,
+            Err(CalcError::Eval(EvaluationError::DivisionByZero {
+                span: Span::new(4, 11)
+            }))
+        );
     }
 
     #[test]
     fn test_evaluate_unary_op_minus() {
         let input = "-2.0";
-        let tokens = tokenize(input);
+        let tokens = tokenize(input).unwrap();
         let ast = parse(&tokens).unwrap();
-        let result = evaluate(ast);
+        let result = evaluate(&ast, &mut Environment::new());
         assert_eq!(result.unwrap(), -2.0);
     }
+
+    #[test]
+    fn test_evaluate_power_operator() {
+        let cases = [
+            ("2^3", 8.0),
+            ("2**3", 8.0),
+            ("-2^2", -4.0),
+            ("(-2)^2", 4.0),
+            ("2^3^2", 512.0),
+            ("2 * 3^2", 18.0),
+            ("2^-1", 0.5),
+            ("-2^-2", -0.25),
+            ("pow(2, 3)^2", 64.0),
+        ];
+        for (input, expected) in cases {
+            let tokens = tokenize(input).unwrap();
+            let ast = parse(&tokens).unwrap();
+            assert_eq!(
+                evaluate(&ast, &mut Environment::new()),
+                Ok(expected),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_evaluate_modulo_and_factorial() {
+        let cases = [
+            ("7 % 3", 1.0),
+            ("-7 % 3", 2.0),
+            ("7 % -3", 1.0),
+            ("-7 % -3", 2.0),
+            ("2 + 10 % 4 * 3", 8.0),
+            ("5!", 120.0),
+            ("0!", 1.0),
+            ("-3!", -6.0),
+            ("2^3!", 64.0),
+            ("171!", f64::INFINITY),
+        ];
+        for (input, expected) in cases {
+            let tokens = tokenize(input).unwrap();
+            let ast = parse(&tokens).unwrap();
+            assert_eq!(
+                evaluate(&ast, &mut Environment::new()),
+                Ok(expected),
+                "{}",
+                input
+            );
+        }
+
+        let tokens = tokenize("(-1)!").unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert!(evaluate(&ast, &mut Environment::new()).unwrap().is_nan());
+
+        let tokens = tokenize("5 % (1 - 1)").unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            evaluate(&ast, &mut Environment::new()),
+            Err(CalcError::Eval(EvaluationError::DivisionByZero {
+                span: Span::new(4, 11)
+            }))
+        );
+    }
+
+    #[test]
+    fn test_evaluate_variables() {
+        let mut env = Environment::new();
+        let input = "x = 3 * 2";
+        let tokens = tokenize(input).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(evaluate(&ast, &mut env), Ok(6.0));
+        assert_eq!(env.get("x"), Some(6.0));
+
+        let input = "x / 4 + y";
+        let tokens = tokenize(input).unwrap();
+        let ast = parse(&tokens).unwrap();
+        env.set("y", 1.0);
+        assert_eq!(evaluate(&ast, &mut env), Ok(2.5));
+    }
+
+    #[test]
+    fn test_evaluate_undefined_variable() {
+        let input = "1 + rate * 2";
+        let tokens = tokenize(input).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let result = evaluate(&ast, &mut Environment::new());
+        assert_eq!(
+            result,
+            Err(CalcError::Eval(EvaluationError::UndefinedVariable {
+                name: String::from("rate"),
+                span: Span::new(4, 8),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_evaluate_constants() {
+        let mut env = Environment::new();
+        assert_eq!(run("2 * pi", &mut env), Ok(Some(std::f64::consts::TAU)));
+        assert_eq!(run("tau / 2 - pi", &mut env), Ok(Some(0.0)));
+        let golden = run("phi^2 - phi", &mut env).unwrap().unwrap();
+        assert!((golden - 1.0).abs() < 1e-12);
+        assert_eq!(run("-inf", &mut env), Ok(Some(f64::NEG_INFINITY)));
+        assert!(run("nan", &mut env).unwrap().unwrap().is_nan());
+        assert_eq!(run("f(e) = e * 2; f(3) + e * 0", &mut env), Ok(Some(6.0)));
+
+        assert_eq!(
+            run("x = 1; pi = 3", &mut env),
+            Err(CalcError::Eval(EvaluationError::ConstantAssignment {
+                name: String::from("pi"),
+                span: Span::new(7, 9),
+            }))
+        );
+        assert_eq!(env.get("pi"), Some(std::f64::consts::PI));
+    }
+
+    #[test]
+    fn test_evaluate_builtin_functions() {
+        let cases = [
+            ("ln(e)", 1.0),
+            ("log10(1000)", 3.0),
+            ("log2(8)", 3.0),
+            ("log(3, 81)", 4.0),
+            ("exp(0)", 1.0),
+            ("sinh(0) + cosh(0) + tanh(0)", 1.0),
+            ("asinh(0) + acosh(1) + atanh(0)", 0.0),
+            ("cbrt(-27)", -3.0),
+            ("atan2(1, 1) * 4", std::f64::consts::PI),
+            ("hypot(3, 4)", 5.0),
+        ];
+        let mut env = Environment::new();
+        for (input, expected) in cases {
+            assert_eq!(run(input, &mut env), Ok(Some(expected)), "{}", input);
+        }
+
+        assert_eq!(
+            run("2 * hypot(3)", &mut env),
+            Err(CalcError::Eval(EvaluationError::ArityMismatch {
+                name: String::from("hypot"),
+                expected: Arity::Fixed(2),
+                found: 1,
+                span: Span::new(4, 12),
+            }))
+        );
+        assert_eq!(run("ln(x) = 2 * x; ln(3)", &mut env), Ok(Some(6.0)));
+    }
+
+    #[test]
+    fn test_evaluate_strict_mode() {
+        let mut env = Environment::new();
+        assert!(run("sqrt(-1)", &mut env).unwrap().unwrap().is_nan());
+        assert!(run("(-1)!", &mut env).unwrap().unwrap().is_nan());
+        assert_eq!(run("pow(0, -1)", &mut env), Ok(Some(f64::INFINITY)));
+
+        env.set_mode(EvaluationMode::Strict);
+        assert_eq!(
+            run("1 + sqrt(-4)", &mut env),
+            Err(CalcError::Eval(EvaluationError::DomainError {
+                function: String::from("sqrt"),
+                argument: -4.0,
+                span: Span::new(4, 12),
+            }))
+        );
+        assert_eq!(
+            run("asin(2)", &mut env),
+            Err(CalcError::Eval(EvaluationError::DomainError {
+                function: String::from("asin"),
+                argument: 2.0,
+                span: Span::new(0, 7),
+            }))
+        );
+        assert_eq!(
+            run("(-8)^(1/3)", &mut env),
+            Err(CalcError::Eval(EvaluationError::DomainError {
+                function: String::from("^"),
+                argument: -8.0,
+                span: Span::new(0, 10),
+            }))
+        );
+        assert_eq!(
+            run("1 + (-4)!", &mut env),
+            Err(CalcError::Eval(EvaluationError::DomainError {
+                function: String::from("!"),
+                argument: -4.0,
+                span: Span::new(4, 9),
+            }))
+        );
+        assert_eq!(
+            run("log(2, 8) + 0.5!", &mut env),
+            Err(CalcError::Eval(EvaluationError::DomainError {
+                function: String::from("!"),
+                argument: 0.5,
+                span: Span::new(12, 16),
+            }))
+        );
+        // A complex argument may give a complex result.
+        assert_eq!(run("abs(sqrt(-4i))", &mut env), Ok(Some(2.0)));
+        assert_eq!(
+            run("pow(0, -1)", &mut env),
+            Err(CalcError::Eval(EvaluationError::Overflow {
+                function: String::from("pow"),
+                span: Span::new(0, 10),
+            }))
+        );
+        assert_eq!(
+            run("2 * 10^400", &mut env),
+            Err(CalcError::Eval(EvaluationError::Overflow {
+                function: String::from("^"),
+                span: Span::new(4, 10),
+            }))
+        );
+        assert!(matches!(
+            run("171!", &mut env),
+            Err(CalcError::Eval(EvaluationError::Overflow { .. }))
+        ));
+        assert_eq!(run("sqrt(16) + acos(1)", &mut env), Ok(Some(4.0)));
+        assert_eq!(run("inf - 1", &mut env), Ok(Some(f64::INFINITY)));
+        assert!(run("nan * 0", &mut env).unwrap().unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_evaluate_angle_modes() {
+        let approx = |result: Result<Option<f64>, CalcError>, expected: f64| {
+            let value = result.unwrap().unwrap();
+            assert!((value - expected).abs() < 1e-9, "{} != {}", value, expected);
+        };
+
+        let mut env = Environment::new();
+        approx(run("sin(pi / 6)", &mut env), 0.5);
+        approx(run("sin(30deg)", &mut env), 0.5);
+        approx(run("cos(100grad)", &mut env), 0.0);
+        approx(run("180deg", &mut env), consts::PI);
+
+        env.set_angle_mode(AngleMode::Degrees);
+        approx(run("sin(30)", &mut env), 0.5);
+        approx(run("tan(45)", &mut env), 1.0);
+        approx(run("asin(1)", &mut env), 90.0);
+        approx(run("atan2(1, 1)", &mut env), 45.0);
+        approx(run("sin(pi / 2 * 1rad)", &mut env), 1.0);
+        approx(run("1rad", &mut env), 180.0 / consts::PI);
+        approx(run("sinh(1)", &mut env), 1f64.sinh());
+
+        env.set_angle_mode(AngleMode::Gradians);
+        approx(run("cos(200)", &mut env), -1.0);
+        approx(run("acos(0)", &mut env), 100.0);
+        approx(run("90deg", &mut env), 100.0);
+    }
+
+    #[test]
+    fn test_evaluate_rational_mode() {
+        let mut env = Environment::new();
+        env.set_numeric_mode(NumericMode::Rational);
+        let mut exact = |input: &str| {
+            let statements = parse_program(&tokenize(input).unwrap()).unwrap();
+            let mut result = None;
+            for statement in &statements {
+                result = execute_value(statement, &mut env).unwrap();
+            }
+            result.unwrap().to_string()
+        };
+
+        assert_eq!(exact("0.1 + 0.2"), "0.3");
+        assert_eq!(exact("1/3*3"), "1");
+        assert_eq!(exact("2/6 - 1"), "-2/3");
+        assert_eq!(exact("(2/3)^-2"), "2.25");
+        assert_eq!(exact("(2/3)^3 + 2^64"), "498062089990157893640/27");
+        assert_eq!(exact("-7.5 % 2"), "0.5");
+        assert_eq!(exact("25!"), "15511210043330985984000000");
+        assert_eq!(exact("x = 1/7; f(a) = a * 7; f(x)"), "1");
+        assert_eq!(
+            exact("1.00000000000000000001 - 1"),
+            "0.00000000000000000001"
+        );
+        assert_eq!(
+            exact("12345678901234567890123 + 1"),
+            "12345678901234567890124"
+        );
+        assert_eq!(
+            exact("0xFFFF_FFFF_FFFF_FFFF_FFFF"),
+            "1208925819614629174706175"
+        );
+        assert_eq!(exact("2e-30 * 5e29"), "1");
+        assert_eq!(exact("1e20000"), "inf");
+        assert_eq!(exact("4^0.5"), "2");
+        assert_eq!(exact("1/3 + sqrt(0)"), (1.0f64 / 3.0).to_string());
+        assert_eq!(exact("1/8 * pi"), (consts::PI / 8.0).to_string());
+
+        // Native functions with an exact implementation keep rationals exact.
+        assert_eq!(exact("pow(1/3, 2)"), "1/9");
+        assert_eq!(exact("pow(4, 0.5)"), "2");
+        assert_eq!(exact("pow(0, -1)"), "inf");
+        assert_eq!(exact("abs(-1/3) + re(1/3) + im(1/3)"), "2/3");
+        assert_eq!(exact("conj(-1/3)"), "-1/3");
+        assert_eq!(exact("max(1/3, 1/4) - min(1/3, 1/4)"), "1/12");
+        assert_eq!(exact("sum(0.1, 0.2)"), "0.3");
+        assert_eq!(exact("mean(1/3, 1/6) + median(1/3, 1/6)"), "0.5");
+        assert_eq!(exact("stddev(1, 3, 5)"), "2");
+        assert_eq!(exact("stddev(0, 1)"), 0.5f64.sqrt().to_string());
+        assert_eq!(
+            execute_value(&parse(&tokenize("1 / (1 - 1)").unwrap()).unwrap(), &mut env),
+            Err(CalcError::Eval(EvaluationError::DivisionByZero {
+                span: Span::new(4, 11)
+            }))
+        );
+        assert_eq!(env.get("x"), Some(1.0 / 7.0));
+    }
+
+    #[test]
+    fn test_evaluate_decimal_mode() {
+        let mut env = Environment::new();
+        env.set_numeric_mode(NumericMode::Decimal { precision: 30 });
+
+        assert_eq!(display("1/3", &mut env), "0.333333333333333333333333333333");
+        assert_eq!(display("0.1 + 0.2", &mut env), "0.3");
+        assert_eq!(display("pi", &mut env), "3.14159265358979323846264338328");
+        assert_eq!(display("sqrt(2) * sqrt(2)", &mut env), "2");
+        assert_eq!(
+            display("2^0.5", &mut env),
+            "1.41421356237309504880168872421"
+        );
+        assert_eq!(display("ln(e^3)", &mut env), "3");
+        assert_eq!(display("sin(30deg)", &mut env), "0.5");
+        assert_eq!(display("sqrt(-1)", &mut env), "i");
+        assert_eq!(
+            display("0.1234567890123456789012345 * 10", &mut env),
+            "1.234567890123456789012345"
+        );
+        assert_eq!(
+            display("12345678901234567890123 + 1", &mut env),
+            "12345678901234567890124"
+        );
+        assert_eq!(
+            display("3.14159265358979323846264338327950288", &mut env),
+            "3.14159265358979323846264338328"
+        );
+
+        // Matrices and lists keep their elements at the precision.
+        assert_eq!(
+            display("[1, 2] / 3", &mut env),
+            "[0.333333333333333333333333333333, 0.666666666666666666666666666667]"
+        );
+        assert_eq!(
+            display("[2, 1; 1, 2] \\ [1; 0]", &mut env),
+            "[0.666666666666666666666666666667; -0.333333333333333333333333333333]"
+        );
+        assert_eq!(
+            display("[1, 2] .^ 0.5", &mut env),
+            "[1, 1.41421356237309504880168872421]"
+        );
+        assert_eq!(display("det([1, 2; 3, 4])", &mut env), "-2");
+        assert_eq!(display("sum([1/3, 2/3])", &mut env), "1");
+
+        env.set_angle_mode(AngleMode::Degrees);
+        assert_eq!(display("atan(1)", &mut env), "45");
+        assert_eq!(display("cos(60)", &mut env), "0.5");
+
+        env.registry_mut()
+            .register("double", Arity::Fixed(1), |args| args[0] * 2.0);
+        let value = evaluate_value(&parse(&tokenize("double(0.5)").unwrap()).unwrap(), &mut env);
+        assert_eq!(value, Ok(Value::Float(1.0)));
+    }
+
+    #[test]
+    fn test_evaluate_complex() {
+        let mut env = Environment::new();
+        assert_eq!(display("(3+4i) * (1-2j)", &mut env), "11-2i");
+        assert_eq!(display("i^2 + j*j", &mut env), "-2");
+        assert_eq!(display("sqrt(-4)", &mut env), "2i");
+        assert_eq!(display("ln(-1)", &mut env), format!("{}i", consts::PI));
+        assert_eq!(display("abs(3-4i) + arg(-2) / pi", &mut env), "6");
+        assert_eq!(display("conj(1+i) + re(2-3i) * im(2-3i)", &mut env), "-5-i");
+        assert_eq!(
+            display("exp(1+2i) / exp(2i)", &mut env),
+            consts::E.to_string()
+        );
+        assert_eq!(display("hypot(i, 1)", &mut env), "NaN");
+
+        assert_eq!(run("f(z) = z * conj(z); f(3+4i)", &mut env), Ok(Some(25.0)));
+        assert!(run("3 + 4i", &mut env).unwrap().unwrap().is_nan());
+        assert_eq!(run("i = 2; i * 3", &mut env), Ok(Some(6.0)));
+        assert_eq!(run("(j + 1) * (j - 1)", &mut env), Ok(Some(-2.0)));
+
+        env.set_angle_mode(AngleMode::Degrees);
+        let value = evaluate_value(&parse(&tokenize("arg(1+j)").unwrap()).unwrap(), &mut env);
+        assert_eq!(value, Ok(Value::Float(45.0)));
+
+        env.set_mode(EvaluationMode::Strict);
+        let result = run("(1+j) % 2", &mut env);
+        assert!(matches!(
+            result,
+            Err(CalcError::Eval(EvaluationError::DomainError { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_evaluate_complex_exact_modes() {
+        let mut env = Environment::new();
+        env.set_numeric_mode(NumericMode::Decimal { precision: 30 });
+        let value = |input: &str, env: &mut Environment| {
+            evaluate_value(&parse(&tokenize(input).unwrap()).unwrap(), env)
+        };
+        assert_eq!(display("sqrt(-2)", &mut env), "1.4142135623730951i");
+
+        env.set_mode(EvaluationMode::Strict);
+        assert!(matches!(
+            value("sqrt(-2)", &mut env),
+            Err(CalcError::Eval(EvaluationError::DomainError { .. }))
+        ));
+        assert_eq!(
+            value("2i * 0.1", &mut env),
+            Err(CalcError::Eval(EvaluationError::NonRealResult {
+                value: String::from("2i"),
+                span: Span::new(0, 8),
+            }))
+        );
+        assert!(matches!(
+            value("abs(3+4i)", &mut env),
+            Err(CalcError::Eval(EvaluationError::NonRealResult { .. }))
+        ));
+
+        env.set_numeric_mode(NumericMode::Rational);
+        assert!(matches!(
+            value("(-8)^(1/3)", &mut env),
+            Err(CalcError::Eval(EvaluationError::DomainError { .. }))
+        ));
+        assert!(matches!(
+            value("(1+i) * 2", &mut env),
+            Err(CalcError::Eval(EvaluationError::NonRealResult { .. }))
+        ));
+        assert_eq!(value("abs(-1/3)", &mut env).unwrap().to_string(), "1/3");
+    }
+
+    fn run(input: &str, env: &mut Environment) -> Result<Option<f64>, CalcError> {
+        let tokens = tokenize(input).unwrap();
+        let mut result = Ok(None);
+        for ast in parse_program(&tokens).unwrap() {
+            result = execute(&ast, env);
+        }
+        result
+    }
+
+    fn display(input: &str, env: &mut Environment) -> String {
+        let ast = parse(&tokenize(input).unwrap()).unwrap();
+        evaluate_value(&ast, env).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_evaluate_user_function() {
+        let mut env = Environment::new();
+        assert_eq!(run("f(x, y) = x * x + y", &mut env), Ok(None));
+        assert_eq!(run("f(3, 4)", &mut env), Ok(Some(13.0)));
+        assert_eq!(run("g() = f(1, 1) * 2; g() + 1", &mut env), Ok(Some(5.0)));
+        assert_eq!(env.function("f").map(|f| f.params.len()), Some(2));
+    }
+
+    #[test]
+    fn test_evaluate_function_lexical_scope() {
+        let mut env = Environment::new();
+        let input = "x = 10; y = 1; g(a) = a + y; f(y) = g(2) + x";
+        assert_eq!(run(input, &mut env), Ok(None));
+        assert_eq!(run("f(100)", &mut env), Ok(Some(13.0)));
+        assert_eq!(run("h(x) = x * 2; h(3) + x", &mut env), Ok(Some(16.0)));
+        assert_eq!(env.get("x"), Some(10.0));
+    }
+
+    #[test]
+    fn test_evaluate_function_errors() {
+        let mut env = Environment::new();
+        assert_eq!(
+            run("f(1)", &mut env),
+            Err(CalcError::Eval(EvaluationError::UndefinedFunction {
+                name: String::from("f"),
+                span: Span::new(0, 4),
+            }))
+        );
+        assert_eq!(
+            run("f(x, y) = x + y; 1 + f(1)", &mut env),
+            Err(CalcError::Eval(EvaluationError::ArityMismatch {
+                name: String::from("f"),
+                expected: Arity::Fixed(2),
+                found: 1,
+                span: Span::new(21, 25),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_evaluate_recursion_limit() {
+        let mut env = Environment::new();
+        env.set_recursion_limit(50);
+        assert_eq!(
+            run("f(n) = f(n - 1) + 1; f(3)", &mut env),
+            Err(CalcError::Eval(EvaluationError::RecursionLimit {
+                name: String::from("f"),
+                limit: 50,
+                span: Span::new(7, 15),
+            }))
+        );
+
+        let mut env = Environment::new();
+        let result = run("f(n) = f(n - 1) + 1; f(3)", &mut env);
+        assert!(matches!(
+            result,
+            Err(CalcError::Eval(EvaluationError::RecursionLimit { limit, .. }))
+                if limit == DEFAULT_RECURSION_LIMIT
+        ));
+    }
+
+    #[test]
+    fn test_evaluate_recursion_depth() {
+        // Bodies nesting many sub-expressions reach `MAX_DEPTH` before the recursion limit. The
+        // evaluation runs with the stack of a main thread, as test threads have a smaller one.
+        let nested = format!("{}f(n - 1){}", "abs(".repeat(30), ")".repeat(30));
+        let inputs = [
+            format!("f(n) = n <= 0 ? 0 : 1 + {}; f(250)", nested),
+            String::from("f(n) = n <= 0 ? 0 : 1 + sqrt(abs(max(1, 2, f(n - 1)))); f(200)"),
+            String::from("f(n) = n <= 0 ? 0 : [[-f(n - 1)]] in m; f(300)"),
+        ];
+        let results = thread::Builder::new()
+            .stack_size(8 << 20)
+            .spawn(move || inputs.map(|input| run(&input, &mut Environment::new())))
+            .unwrap()
+            .join()
+            .unwrap();
+        for result in results {
+            assert!(matches!(
+                result,
+                Err(CalcError::Eval(EvaluationError::RecursionLimit { limit, .. }))
+                    if limit < DEFAULT_RECURSION_LIMIT
+            ));
+        }
+
+        let mut env = Environment::new();
+        let input = "f(n) = n <= 0 ? 0 : 1 + abs(max(0, f(n - 1))); f(100)";
+        assert_eq!(run(input, &mut env), Ok(Some(100.0)));
+    }
+
+    #[test]
+    fn test_evaluate_units() {
+        let mut env = Environment::new();
+        assert_eq!(display("5 m", &mut env), "5 m");
+        assert_eq!(display("9.81 m/s^2", &mut env), "9.81 m/s^2");
+        assert_eq!(display("3 km / 20 min", &mut env), "2.5 m/s");
+        assert_eq!(display("3 km / 20 min in km/h", &mut env), "9 km/h");
+        assert_eq!(display("72 km/h to m/s", &mut env), "20 m/s");
+        assert_eq!(display("1 mi in ft", &mut env), "5280 ft");
+        assert_eq!(display("(4 m^2)^0.5", &mut env), "2 m");
+        assert_eq!(display("2 N * 3 m in kg m^2/s^2", &mut env), "6 kg m^2/s^2");
+        assert_eq!(display("1 km / 1 m", &mut env), "1000");
+        assert_eq!(display("-(1 W h in J)", &mut env), "-3600 J");
+        assert_eq!(run("2 kWh in MJ", &mut env), Ok(Some(7.2)));
+
+        // A variable shadows a unit, except right after a number, and a name that is not
+        // after a number is not a unit.
+        assert_eq!(run("m = 3", &mut env), Ok(Some(3.0)));
+        assert_eq!(display("2 m * m", &mut env), "6 m");
+        assert_eq!(
+            run("t * 2", &mut env),
+            Err(CalcError::Eval(EvaluationError::UndefinedVariable {
+                name: String::from("t"),
+                span: Span::new(0, 1),
+            }))
+        );
+        assert_eq!(
+            run("area(w) = w * h; area(2)", &mut env),
+            Err(CalcError::Eval(EvaluationError::UndefinedVariable {
+                name: String::from("h"),
+                span: Span::new(14, 15),
+            }))
+        );
+
+        assert_eq!(
+            run("1 m + 2 s", &mut env),
+            Err(CalcError::Eval(EvaluationError::DimensionMismatch {
+                expected: unit::lookup("m").unwrap().dimension,
+                found: unit::lookup("s").unwrap().dimension,
+                span: Span::new(6, 9),
+            }))
+        );
+        assert_eq!(
+            run("5 kg in N", &mut env),
+            Err(CalcError::Eval(EvaluationError::DimensionMismatch {
+                expected: unit::lookup("N").unwrap().dimension,
+                found: unit::lookup("kg").unwrap().dimension,
+                span: Span::new(0, 4),
+            }))
+        );
+        assert_eq!(
+            run("1 + sqrt(4 s)", &mut env),
+            Err(CalcError::Eval(EvaluationError::DimensionMismatch {
+                expected: Dimension::NONE,
+                found: unit::lookup("s").unwrap().dimension,
+                span: Span::new(9, 12),
+            }))
+        );
+        assert_eq!(
+            run("2 m ^ 0.5", &mut env),
+            Err(CalcError::Eval(EvaluationError::InvalidOperation {
+                span: Span::new(2, 9),
+            }))
+        );
+        assert!(matches!(
+            run("2^(3 s)", &mut env),
+            Err(CalcError::Eval(EvaluationError::DimensionMismatch { .. }))
+        ));
+        // The exponents of a product or quotient must fit the dimension.
+        for (input, span) in [
+            ("(1 m)^100 * (1 m)^100", Span::new(0, 21)),
+            ("(1 m)^127 * 1 m", Span::new(0, 15)),
+            ("(1 m)^100 / (1 m)^-100", Span::new(0, 22)),
+            ("(1 m)^100 .* (1 m)^100", Span::new(0, 22)),
+        ] {
+            assert_eq!(
+                run(input, &mut env),
+                Err(CalcError::Eval(EvaluationError::InvalidOperation { span }))
+            );
+        }
+    }
+
+    #[test]
+    fn test_evaluate_matrix_products() {
+        let mut env = Environment::new();
+        assert_eq!(display("[1, 2; 3, 4] * [5; 6]", &mut env), "[17; 39]");
+        assert_eq!(display("[1, 2; 3, 4]^2", &mut env), "[7, 10; 15, 22]");
+        assert_eq!(display("[1, 2] + 2 * [3, 4]", &mut env), "[7, 10]");
+        assert_eq!(run("[1, 2] * [3; 4]", &mut env), Ok(Some(11.0)));
+        assert!(run("[1, 2]", &mut env).unwrap().unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_evaluate_matrix_elementwise() {
+        let mut env = Environment::new();
+        assert_eq!(
+            display("[1, 2; 3, 4] .* [1, 2; 3, 4]", &mut env),
+            "[1, 4; 9, 16]"
+        );
+        assert_eq!(display("[1, 2] ./ [4, 8]", &mut env), "[0.25, 0.25]");
+        assert_eq!(display("2./[1, 2]", &mut env), "[2, 1]");
+        assert!(run("x = [1, 2]", &mut env).is_ok());
+        assert_eq!(display("2.^x", &mut env), "[2, 4]");
+        assert_eq!(display("[1, 2; 3, 4] .^ 2", &mut env), "[1, 4; 9, 16]");
+        assert_eq!(display("-[1, 2]", &mut env), "[-1, -2]");
+        assert_eq!(
+            run("[1, 2] ./ [0, 1]", &mut env),
+            Err(CalcError::Eval(EvaluationError::DivisionByZero {
+                span: Span::new(10, 16),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_evaluate_matrix_solve_and_inverse() {
+        let mut env = Environment::new();
+        assert_eq!(display("[1, 2; 3, 4]^-1", &mut env), "[-2, 1; 1.5, -0.5]");
+        assert_eq!(display("[1, 2; 3, 4] \\ [5; 6]", &mut env), "[-4; 4.5]");
+        assert_eq!(display("transpose([1, 2, 3])", &mut env), "[1; 2; 3]");
+        assert_eq!(run("det([1, 2; 3, 4])", &mut env), Ok(Some(-2.0)));
+        assert_eq!(run("rank([1, 2; 2, 4])", &mut env), Ok(Some(1.0)));
+        assert_eq!(run("det(3) + inv(4)", &mut env), Ok(Some(3.25)));
+        assert_eq!(run("2 \\ 6", &mut env), Ok(Some(3.0)));
+        assert_eq!(
+            run("inv([1, 2; 2, 4])", &mut env),
+            Err(CalcError::Eval(EvaluationError::SingularMatrix {
+                span: Span::new(0, 17),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_evaluate_matrix_shape_errors() {
+        let mut env = Environment::new();
+        let mismatch = |expected: ShapeRequirement, cols: usize, start: usize, end: usize| {
+            Err(CalcError::Eval(EvaluationError::ShapeMismatch {
+                expected,
+                found: Shape::new(1, cols),
+                span: Span::new(start, end),
+            }))
+        };
+        let row = |cols: usize| Shape::new(1, cols).into();
+        assert_eq!(
+            run("[1, 2] + [1, 2, 3]", &mut env),
+            mismatch(row(2), 3, 9, 18)
+        );
+        assert_eq!(run("[1, 2; 3]", &mut env), mismatch(row(2), 1, 7, 8));
+        assert_eq!(run("sin([1, 2])", &mut env), mismatch(row(1), 2, 4, 10));
+        let square = ShapeRequirement::Square;
+        assert_eq!(run("[1, 2] \\ [1; 2]", &mut env), mismatch(square, 2, 0, 6));
+        let rows = ShapeRequirement::Rows(3);
+        assert_eq!(
+            run("[1, 2, 3] * [1, 2, 3]", &mut env),
+            mismatch(rows, 3, 12, 21)
+        );
+
+        // The shapes are checked before the divisor's elements.
+        assert_eq!(run("1 / [0, 1]", &mut env), mismatch(row(1), 2, 4, 10));
+        assert_eq!(
+            run("[1, 2] ./ [0, 1, 2]", &mut env),
+            mismatch(row(2), 3, 10, 19)
+        );
+    }
+
+    #[test]
+    fn test_evaluate_matrix_element_errors() {
+        let mut env = Environment::new();
+        assert!(matches!(
+            run("[1 m, 2]", &mut env),
+            Err(CalcError::Eval(EvaluationError::DimensionMismatch { .. }))
+        ));
+        assert!(matches!(
+            run("[1, 2] + 1i", &mut env),
+            Err(CalcError::Eval(EvaluationError::InvalidOperation { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_evaluate_statistics() {
+        let mut env = Environment::new();
+        assert_eq!(run("max(1, 5, 2)", &mut env), Ok(Some(5.0)));
+        assert_eq!(run("min([3, 1, 4], 2)", &mut env), Ok(Some(1.0)));
+        assert_eq!(
+            run("sum([1, 2; 3, 4]) + prod(2, 3)", &mut env),
+            Ok(Some(16.0))
+        );
+        assert_eq!(run("mean([3, 1, 4], 4)", &mut env), Ok(Some(3.0)));
+        assert_eq!(run("median(3, 1, 4, 1)", &mut env), Ok(Some(2.0)));
+        assert_eq!(
+            run("var(1, 3) + stddev([2, 4])", &mut env),
+            Ok(Some(2.0 + 2f64.sqrt()))
+        );
+        assert_eq!(run("mode([1, 2, 2, 3])", &mut env), Ok(Some(2.0)));
+        assert!(run("max(nan, 1)", &mut env).unwrap().unwrap().is_nan());
+        assert!(run("min(1, nan)", &mut env).unwrap().unwrap().is_nan());
+
+        // `min` is a unit after a number and a function before `(`.
+        assert_eq!(run("min(2 min / 1 s, 100)", &mut env), Ok(Some(100.0)));
+    }
+
+    #[test]
+    fn test_evaluate_statistics_exact_modes() {
+        let mut env = Environment::new();
+        env.set_numeric_mode(NumericMode::Decimal { precision: 20 });
+        assert_eq!(display("mean(0.1, 0.2)", &mut env), "0.15");
+        assert_eq!(display("sum(1, 2i)", &mut env), "1+2i");
+
+        // A list stands for its elements in every numeric mode, and alongside complex numbers.
+        assert_eq!(display("mean([0.1, 0.2, 0.3])", &mut env), "0.2");
+        assert_eq!(display("mean(0.1, 0.2, 0.3)", &mut env), "0.2");
+        assert_eq!(display("sum([0.1, 0.2], 0.3)", &mut env), "0.6");
+        assert_eq!(display("sum([1, 2], 2i)", &mut env), "3+2i");
+        assert_eq!(
+            display("mean([1/3, 1/3, 1/3])", &mut env),
+            display("mean(1/3, 1/3, 1/3)", &mut env)
+        );
+        assert_eq!(display("max([1e18 + 1, 1e18]) - 1e18", &mut env), "1");
+        assert_eq!(
+            display("mean([2, 4], 3i)", &mut env),
+            display("mean(2, 4, 3i)", &mut env)
+        );
+
+        env.set_numeric_mode(NumericMode::Rational);
+        assert_eq!(display("sum([0.1, 0.2])", &mut env), "0.3");
+        assert_eq!(display("median(2 * [1/3, 1/6])", &mut env), "0.5");
+    }
+
+    #[test]
+    fn test_evaluate_statistics_errors() {
+        let mut env = Environment::new();
+        assert_eq!(
+            run("mean([])", &mut env),
+            Err(CalcError::Eval(EvaluationError::EmptyList {
+                span: Span::new(0, 8),
+            }))
+        );
+        assert_eq!(
+            run("max(1+i, 2)", &mut env),
+            Err(CalcError::Eval(EvaluationError::InvalidOperation {
+                span: Span::new(4, 7),
+            }))
+        );
+        assert_eq!(
+            run("median([1, 2], 3i)", &mut env),
+            Err(CalcError::Eval(EvaluationError::InvalidOperation {
+                span: Span::new(15, 17),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_evaluate_percentile() {
+        let mut env = Environment::new();
+        assert_eq!(
+            run("percentile([1, 2, 3, 4], 25)", &mut env),
+            Ok(Some(1.75))
+        );
+        assert_eq!(run("percentile(4, 1, 3, 2, 100)", &mut env), Ok(Some(4.0)));
+        assert_eq!(
+            run("percentile([1, 2], [50, 60])", &mut env),
+            Err(CalcError::Eval(EvaluationError::ShapeMismatch {
+                expected: Shape::new(1, 1).into(),
+                found: Shape::new(1, 2),
+                span: Span::new(19, 27),
+            }))
+        );
+        assert!(matches!(
+            run("percentile(50)", &mut env),
+            Err(CalcError::Eval(EvaluationError::ArityMismatch { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_evaluate_percentile_bounds() {
+        let mut env = Environment::new();
+        assert_eq!(
+            run("percentile(1, 2, 150)", &mut env),
+            Err(CalcError::Eval(EvaluationError::DomainError {
+                function: String::from("percentile"),
+                argument: 150.0,
+                span: Span::new(0, 21),
+            }))
+        );
+
+        // A percentage outside of `0` to `100` is a domain error in every mode.
+        for mode in [EvaluationMode::Lenient, EvaluationMode::Strict] {
+            env.set_mode(mode);
+            for (input, argument) in [
+                ("percentile([1, 2, 3], 101)", 101.0),
+                ("percentile(1, 2, 3, -1)", -1.0),
+            ] {
+                assert!(matches!(
+                    run(input, &mut env),
+                    Err(CalcError::Eval(EvaluationError::DomainError { argument: found, .. }))
+                        if found == argument
+                ));
+            }
+        }
+        for numeric_mode in [
+            NumericMode::Rational,
+            NumericMode::Decimal { precision: 20 },
+        ] {
+            env.set_numeric_mode(numeric_mode);
+            assert!(matches!(
+                run("percentile([1/3, 2/3], 100.5)", &mut env),
+                Err(CalcError::Eval(EvaluationError::DomainError { .. }))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_evaluate_booleans() {
+        let mut env = Environment::new();
+        assert_eq!(run("1 < 2 and 2 <= 2", &mut env), Ok(Some(1.0)));
+        assert_eq!(run("3 > 4 or not 5 >= 5", &mut env), Ok(Some(0.0)));
+        assert_eq!(run("(1 == 1) != false", &mut env), Ok(Some(1.0)));
+        assert_eq!(run("[1, 2] == [1, 2] and 2i != 2", &mut env), Ok(Some(1.0)));
+        assert_eq!(run("1 km == 1000 m", &mut env), Ok(Some(1.0)));
+
+        // The right-hand side and the untaken branch are not evaluated.
+        assert_eq!(run("1 > 0 or 1/0 > 1", &mut env), Ok(Some(1.0)));
+        assert_eq!(run("false and 1/0 > 1", &mut env), Ok(Some(0.0)));
+        assert_eq!(run("x = 0; x != 0 ? 1/x : -1", &mut env), Ok(Some(-1.0)));
+        assert_eq!(
+            run("fact(n) = n <= 1 ? 1 : n * fact(n - 1); fact(6)", &mut env),
+            Ok(Some(720.0))
+        );
+
+        assert_eq!(
+            run("1 + true", &mut env),
+            Err(CalcError::Eval(EvaluationError::TypeMismatch {
+                expected: ValueType::Number,
+                found: ValueType::Boolean,
+                span: Span::new(4, 8),
+            }))
+        );
+        assert_eq!(
+            run("true + 1", &mut env),
+            Err(CalcError::Eval(EvaluationError::TypeMismatch {
+                expected: ValueType::Number,
+                found: ValueType::Boolean,
+                span: Span::new(0, 4),
+            }))
+        );
+        assert_eq!(
+            run("1 < 2 < 3", &mut env),
+            Err(CalcError::Eval(EvaluationError::TypeMismatch {
+                expected: ValueType::Number,
+                found: ValueType::Boolean,
+                span: Span::new(0, 5),
+            }))
+        );
+        assert_eq!(
+            run("[1, 2] < 3", &mut env),
+            Err(CalcError::Eval(EvaluationError::TypeMismatch {
+                expected: ValueType::Number,
+                found: ValueType::Matrix,
+                span: Span::new(0, 6),
+            }))
+        );
+        assert_eq!(
+            run("1 ? 2 : 3", &mut env),
+            Err(CalcError::Eval(EvaluationError::TypeMismatch {
+                expected: ValueType::Boolean,
+                found: ValueType::Number,
+                span: Span::new(0, 1),
+            }))
+        );
+        assert_eq!(
+            run("[1, 2] == 1", &mut env),
+            Err(CalcError::Eval(EvaluationError::TypeMismatch {
+                expected: ValueType::Matrix,
+                found: ValueType::Number,
+                span: Span::new(10, 11),
+            }))
+        );
+        assert!(matches!(
+            run("sqrt(not false)", &mut env),
+            Err(CalcError::Eval(EvaluationError::TypeMismatch { .. }))
+        ));
+        assert!(matches!(
+            run("1 m < 2 s", &mut env),
+            Err(CalcError::Eval(EvaluationError::DimensionMismatch { .. }))
+        ));
+        assert!(matches!(
+            run("i < 1", &mut env),
+            Err(CalcError::Eval(EvaluationError::InvalidOperation { .. }))
+        ));
+        assert_eq!(
+            run("2 * i < 1", &mut env),
+            Err(CalcError::Eval(EvaluationError::InvalidOperation {
+                span: Span::new(0, 5),
+            }))
+        );
+
+        // Rationals are compared exactly.
+        assert_eq!(run("0.1 + 0.2 == 0.3", &mut env), Ok(Some(0.0)));
+        env.set_numeric_mode(NumericMode::Rational);
+        assert_eq!(run("0.1 + 0.2 == 0.3", &mut env), Ok(Some(1.0)));
+    }
 }
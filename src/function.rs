@@ -0,0 +1,694 @@
+//! # Function Module
+//!
+//! This module provides the registry of native functions that expressions can call by name.
+//!
+//! ## Registry
+//!
+//! A `FunctionRegistry` maps names to Rust closures taking the evaluated arguments, together
+//! with their `Arity`: either a fixed number of arguments or a variadic list with a minimum
+//! length. The arity is checked before the closure is called, so a closure can index its
+//! arguments freely. The standard registry contains:
+//!
+//! - `cos`, `acos`, `sin`, `asin`, `tan`, `atan`, `sqrt` and `pow(x, y)`.
+//! - `ln`, `log10`, `log2`, `log(base, x)` and `exp`.
+//! - `sinh`, `cosh`, `tanh`, `asinh`, `acosh` and `atanh`.
+//! - `cbrt`, `atan2(y, x)` and `hypot(x, y)`.
+//! - `re`, `im`, `abs`, `arg` and `conj`, the real part, imaginary part, magnitude, argument
+//!   and conjugate of a complex number.
+//! - `det`, `inv`, `rank` and `transpose`, the determinant, inverse, rank and transpose of a
+//!   matrix; a number is treated as a `1x1` matrix.
+//! - `sum`, `prod`, `min`, `max`, `mean`, `median`, `var`, `stddev` and `mode`, which take
+//!   any number of arguments, and `percentile(x..., p)`, whose last argument is the
+//!   percentage; a list such as `[3, 1, 4]` stands for its elements (see the `statistics`
+//!   module).
+//!
+//! The trigonometric functions take or return angles: the registry records this as an
+//! `AngleUsage`, and the evaluator converts their arguments to radians, or their result from
+//! radians, according to its angle mode. Their closures always work in radians.
+//!
+//! A function can also have an arbitrary-precision implementation, used instead of its `f64`
+//! closure when the environment computes with decimals (see the `decimal` module). Every
+//! standard function has one; a function without one computes with floats in every mode.
+//!
+//! Similarly, a function can have an exact implementation on rationals, used instead of its `f64`
+//! closure when every argument is a rational (see the `value` module). It returns `None` when
+//! the result is not a rational, in which case the function computes with floats. `pow`,
+//! `abs`, `re`, `im`, `conj`, the matrix functions and the statistics have one, so that
+//! `pow(1/3, 2)` is exactly `1/9`; the transcendental functions such as `sqrt` or `sin` have
+//! none.
+//!
+//! Likewise, a function can have a complex implementation, used when an argument is complex
+//! or when the `f64` closure returns `NaN` for finite arguments: `sqrt(-4)` is then `2i`. It
+//! computes the principal value, with the branch cuts of `num_complex`. Every standard function
+//! except `atan2`, `hypot` and the statistics other than `sum`, `prod` and `mean` has one; a
+//! function without one is `NaN` for complex arguments, unless it is registered as real-only
+//! like those statistics, in which case a complex argument is an error.
+//!
+//! Finally, a function can have a matrix implementation, which takes the argument values as
+//! they are and is used when an argument is a matrix (see the `matrix` module). It fails with
+//! a `MatrixError` for an argument of the wrong shape. A function without one does not accept
+//! matrices, unless it is registered as taking lists, like the statistics: a matrix argument
+//! then stands for its elements, which go through the same implementations as numbers.
+//!
+//! The parser consults the registry for identifiers: a registered name must be followed by
+//! its arguments in parentheses, unless it is also a unit, like `min`. A user-defined function
+//! with the same name takes precedence over the registered one.
+//!
+//! ## Custom Functions
+//!
+//! Embedders register domain functions on the registry of an `Environment`:
+//!
+//! ```
+//! use calcrs::{eval_str_with, Arity, Environment};
+//!
+//! let mut env = Environment::new();
+//! env.registry_mut().register("margin", Arity::Fixed(2), |args| {
+//!     (args[0] - args[1]) / args[0]
+//! });
+//! env.registry_mut().register("sumsq", Arity::Variadic { min: 1 }, |args| {
+//!     args.iter().map(|x| x * x).sum()
+//! });
+//! assert_eq!(eval_str_with("margin(200, 150) * 100", &mut env)?, Some(25.0));
+//! assert_eq!(eval_str_with("sumsq(1, 2, 3, 4)", &mut env)?, Some(30.0));
+//! # Ok::<(), calcrs::CalcError>(())
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::{One, Signed, Zero};
+
+use crate::decimal::Decimal;
+use crate::matrix::{Matrix, MatrixError};
+use crate::statistics;
+use crate::value::{self, Value};
+
+/// A native function, applied to its already evaluated arguments.
+pub type NativeFunction = Rc<dyn Fn(&[f64]) -> f64>;
+
+/// The arbitrary-precision implementation of a native function, which returns `None` when
+/// the result cannot be computed as a decimal.
+pub type DecimalFunction = Rc<dyn Fn(&[Decimal]) -> Option<Decimal>>;
+
+/// The exact implementation of a native function on rationals, which returns `None` when
+/// the result is not a rational.
+pub type RationalFunction = Rc<dyn Fn(&[BigRational]) -> Option<BigRational>>;
+
+/// The complex implementation of a native function.
+pub type ComplexFunction = Rc<dyn Fn(&[Complex64]) -> Complex64>;
+
+/// The matrix implementation of a native function.
+pub type MatrixFunction = Rc<dyn Fn(&[Value]) -> Result<Value, MatrixError>>;
+
+/// The number of arguments a function accepts.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Arity {
+    Fixed(usize),
+    Variadic { min: usize },
+}
+
+impl Arity {
+    pub fn accepts(self, count: usize) -> bool {
+        match self {
+            Arity::Fixed(expected) => count == expected,
+            Arity::Variadic { min } => count >= min,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Arity::Fixed(expected) => write!(f, "{}", expected),
+            Arity::Variadic { min } => write!(f, "at least {}", min),
+        }
+    }
+}
+
+/// Whether the arguments or the result of a function are angles.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum AngleUsage {
+    /// The function does not deal with angles.
+    #[default]
+    None,
+    /// The arguments are angles, passed to the closure in radians.
+    Arguments,
+    /// The result is an angle, returned by the closure in radians.
+    Result,
+}
+
+#[derive(Clone)]
+struct Entry {
+    arity: Arity,
+    angles: AngleUsage,
+    function: NativeFunction,
+    decimal: Option<DecimalFunction>,
+    rational: Option<RationalFunction>,
+    complex: Option<ComplexFunction>,
+    matrix: Option<MatrixFunction>,
+    real: bool,
+    list: Option<usize>,
+}
+
+#[derive(Clone)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, Entry>,
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> Self {
+        FunctionRegistry::standard()
+    }
+}
+
+impl fmt::Debug for FunctionRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.names()).finish()
+    }
+}
+
+impl FunctionRegistry {
+    /// A registry without any function.
+    pub fn empty() -> Self {
+        FunctionRegistry {
+            functions: HashMap::new(),
+        }
+    }
+
+    /// The registry of built-in functions.
+    pub fn standard() -> Self {
+        let mut registry = FunctionRegistry::empty();
+        for (name, angles, function) in [
+            ("cos", AngleUsage::Arguments, f64::cos as fn(f64) -> f64),
+            ("sin", AngleUsage::Arguments, f64::sin),
+            ("tan", AngleUsage::Arguments, f64::tan),
+            ("acos", AngleUsage::Result, f64::acos),
+            ("asin", AngleUsage::Result, f64::asin),
+            ("atan", AngleUsage::Result, f64::atan),
+        ] {
+            registry.register_angular(name, Arity::Fixed(1), angles, move |args| function(args[0]));
+        }
+        registry.register_angular("atan2", Arity::Fixed(2), AngleUsage::Result, |args| {
+            args[0].atan2(args[1])
+        });
+        for (name, function) in [
+            ("sqrt", f64::sqrt as fn(f64) -> f64),
+            ("cbrt", f64::cbrt),
+            ("ln", f64::ln),
+            ("log10", f64::log10),
+            ("log2", f64::log2),
+            ("exp", f64::exp),
+            ("sinh", f64::sinh),
+            ("cosh", f64::cosh),
+            ("tanh", f64::tanh),
+            ("asinh", f64::asinh),
+            ("acosh", f64::acosh),
+            ("atanh", f64::atanh),
+        ] {
+            registry.register(name, Arity::Fixed(1), move |args| function(args[0]));
+        }
+        registry.register("pow", Arity::Fixed(2), |args| args[0].powf(args[1]));
+        registry.register("log", Arity::Fixed(2), |args| args[1].log(args[0]));
+        registry.register("hypot", Arity::Fixed(2), |args| args[0].hypot(args[1]));
+        registry.register("re", Arity::Fixed(1), |args| args[0]);
+        registry.register("im", Arity::Fixed(1), |_| 0.0);
+        registry.register("abs", Arity::Fixed(1), |args| args[0].abs());
+        registry.register_angular("arg", Arity::Fixed(1), AngleUsage::Result, |args| {
+            0f64.atan2(args[0])
+        });
+        registry.register("conj", Arity::Fixed(1), |args| args[0]);
+        registry.register("det", Arity::Fixed(1), |args| args[0]);
+        registry.register("inv", Arity::Fixed(1), |args| 1.0 / args[0]);
+        registry.register("rank", Arity::Fixed(1), |args| {
+            if args[0] == 0.0 {
+                0.0
+            } else {
+                1.0
+            }
+        });
+        registry.register("transpose", Arity::Fixed(1), |args| args[0]);
+        for (name, function) in [
+            ("sum", statistics::sum as fn(&[f64]) -> Option<f64>),
+            ("prod", statistics::prod),
+            ("min", statistics::min),
+            ("max", statistics::max),
+            ("mean", statistics::mean),
+            ("median", statistics::median),
+            ("var", statistics::var),
+            ("stddev", statistics::stddev),
+            ("mode", statistics::mode),
+        ] {
+            registry.register(name, Arity::Variadic { min: 1 }, move |args| {
+                function(args).unwrap_or(f64::NAN)
+            });
+            registry.register_list(name, 0);
+        }
+        registry.register("percentile", Arity::Variadic { min: 2 }, |args| {
+            let (p, values) = args.split_last().unwrap_or((&f64::NAN, &[]));
+            statistics::percentile(values, p).unwrap_or(f64::NAN)
+        });
+        registry.register_list("percentile", 1);
+
+        for (name, function) in [
+            ("cos", Decimal::cos as fn(&Decimal) -> Option<Decimal>),
+            ("sin", Decimal::sin),
+            ("tan", Decimal::tan),
+            ("acos", Decimal::acos),
+            ("asin", Decimal::asin),
+            ("atan", Decimal::atan),
+            ("sqrt", Decimal::sqrt),
+            ("cbrt", Decimal::cbrt),
+            ("ln", Decimal::ln),
+            ("log10", Decimal::log10),
+            ("log2", Decimal::log2),
+            ("exp", Decimal::exp),
+            ("sinh", Decimal::sinh),
+            ("cosh", Decimal::cosh),
+            ("tanh", Decimal::tanh),
+            ("asinh", Decimal::asinh),
+            ("acosh", Decimal::acosh),
+            ("atanh", Decimal::atanh),
+            ("re", |x| Some(x.clone())),
+            ("im", |x| Decimal::from_f64(0.0, x.precision())),
+            ("abs", |x| Some(x.abs())),
+            ("arg", |x| Decimal::from_f64(0.0, x.precision())?.atan2(x)),
+            ("conj", |x| Some(x.clone())),
+            ("det", |x| Some(x.clone())),
+            ("inv", |x| {
+                let one = Decimal::from_f64(1.0, x.precision())?;
+                (!x.is_zero()).then(|| one / x.clone())
+            }),
+            ("rank", |x| {
+                Decimal::from_f64(if x.is_zero() { 0.0 } else { 1.0 }, x.precision())
+            }),
+            ("transpose", |x| Some(x.clone())),
+        ] {
+            registry.register_decimal(name, move |args| function(&args[0]));
+        }
+        for (name, function) in [
+            (
+                "atan2",
+                Decimal::atan2 as fn(&Decimal, &Decimal) -> Option<Decimal>,
+            ),
+            ("pow", Decimal::pow),
+            ("log", Decimal::log),
+            ("hypot", Decimal::hypot),
+        ] {
+            registry.register_decimal(name, move |args| function(&args[0], &args[1]));
+        }
+        for (name, function) in [
+            ("sum", statistics::sum as fn(&[Decimal]) -> Option<Decimal>),
+            ("prod", statistics::prod),
+            ("min", statistics::min),
+            ("max", statistics::max),
+            ("mean", statistics::mean),
+            ("median", statistics::median),
+            ("var", statistics::var),
+            ("stddev", statistics::stddev),
+            ("mode", statistics::mode),
+        ] {
+            registry.register_decimal(name, function);
+        }
+        registry.register_decimal("percentile", |args| {
+            let (p, values) = args.split_last()?;
+            statistics::percentile(values, p)
+        });
+
+        for (name, function) in [
+            (
+                "re",
+                (|x| Some(x.clone())) as fn(&BigRational) -> Option<BigRational>,
+            ),
+            ("im", |_| Some(BigRational::zero())),
+            ("abs", |x| Some(x.abs())),
+            ("conj", |x| Some(x.clone())),
+            ("det", |x| Some(x.clone())),
+            ("inv", |x| (!x.is_zero()).then(|| x.recip())),
+            ("rank", |x| {
+                Some(if x.is_zero() {
+                    BigRational::zero()
+                } else {
+                    BigRational::one()
+                })
+            }),
+            ("transpose", |x| Some(x.clone())),
+        ] {
+            registry.register_rational(name, move |args| function(&args[0]));
+        }
+        // An exact power is computed like the `^` operator, which falls back to floats for a
+        // fractional exponent or a power too large to compute.
+        registry.register_rational("pow", |args| {
+            let base = Value::Rational(args[0].clone());
+            match base.pow(&Value::Rational(args[1].clone())) {
+                Value::Rational(power) => Some(power),
+                _ => None,
+            }
+        });
+        for (name, function) in [
+            (
+                "sum",
+                statistics::sum as fn(&[BigRational]) -> Option<BigRational>,
+            ),
+            ("prod", statistics::prod),
+            ("min", statistics::min),
+            ("max", statistics::max),
+            ("mean", statistics::mean),
+            ("median", statistics::median),
+            ("var", statistics::var),
+            ("stddev", statistics::stddev),
+            ("mode", statistics::mode),
+        ] {
+            registry.register_rational(name, function);
+        }
+        registry.register_rational("percentile", |args| {
+            let (p, values) = args.split_last()?;
+            statistics::percentile(values, p)
+        });
+
+        for (name, function) in [
+            ("cos", Complex64::cos as fn(Complex64) -> Complex64),
+            ("sin", Complex64::sin),
+            ("tan", Complex64::tan),
+            ("acos", Complex64::acos),
+            ("asin", Complex64::asin),
+            ("atan", Complex64::atan),
+            ("sqrt", Complex64::sqrt),
+            ("cbrt", Complex64::cbrt),
+            ("ln", Complex64::ln),
+            ("log10", Complex64::log10),
+            ("log2", Complex64::log2),
+            ("exp", Complex64::exp),
+            ("sinh", Complex64::sinh),
+            ("cosh", Complex64::cosh),
+            ("tanh", Complex64::tanh),
+            ("asinh", Complex64::asinh),
+            ("acosh", Complex64::acosh),
+            ("atanh", Complex64::atanh),
+            ("re", |z| Complex64::from(z.re)),
+            ("im", |z| Complex64::from(z.im)),
+            ("abs", |z| Complex64::from(z.norm())),
+            ("arg", |z| Complex64::from(z.arg())),
+            ("conj", |z| z.conj()),
+            ("det", |z| z),
+            ("inv", |z| z.inv()),
+            ("rank", |z| {
+                Complex64::from(if z.norm_sqr() == 0.0 { 0.0 } else { 1.0 })
+            }),
+            ("transpose", |z| z),
+        ] {
+            registry.register_complex(name, move |args| function(args[0]));
+        }
+        registry.register_complex("pow", |args| value::powc(args[0], args[1]));
+        registry.register_complex("log", |args| args[1].ln() / args[0].ln());
+        registry.register_complex("sum", |args| args.iter().sum());
+        registry.register_complex("prod", |args| args.iter().product());
+        registry.register_complex("mean", |args| {
+            args.iter().sum::<Complex64>() / args.len() as f64
+        });
+        // The other statistics order their arguments or square their deviations, which is
+        // only meaningful for real numbers.
+        for name in [
+            "min",
+            "max",
+            "median",
+            "var",
+            "stddev",
+            "mode",
+            "percentile",
+        ] {
+            registry.register_real(name);
+        }
+
+        for (name, function) in [
+            (
+                "det",
+                Matrix::det as fn(&Matrix) -> Result<Value, MatrixError>,
+            ),
+            ("inv", |m| m.inverse().map(Value::matrix)),
+            ("rank", |m| {
+                let rank = m.rank() as i64;
+                Ok(m.elements()
+                    .first()
+                    .map_or(Value::Float(0.0), |first| first.integer(rank)))
+            }),
+            ("transpose", |m| Ok(Value::matrix(m.transpose()))),
+        ] {
+            registry.register_matrix(name, move |args| match args[0].to_matrix() {
+                Some(matrix) => function(&matrix),
+                None => Ok(Value::Float(f64::NAN)),
+            });
+        }
+        registry
+    }
+
+    /// Registers `function` under `name`, replacing any function with the same name.
+    pub fn register<F>(&mut self, name: &str, arity: Arity, function: F)
+    where
+        F: Fn(&[f64]) -> f64 + 'static,
+    {
+        self.register_angular(name, arity, AngleUsage::None, function);
+    }
+
+    /// Registers a function whose arguments or result are angles, expressed in radians.
+    pub fn register_angular<F>(&mut self, name: &str, arity: Arity, angles: AngleUsage, function: F)
+    where
+        F: Fn(&[f64]) -> f64 + 'static,
+    {
+        let entry = Entry {
+            arity,
+            angles,
+            function: Rc::new(function),
+            decimal: None,
+            rational: None,
+            complex: None,
+            matrix: None,
+            real: false,
+            list: None,
+        };
+        self.functions.insert(String::from(name), entry);
+    }
+
+    /// Gives the function registered under `name` an arbitrary-precision implementation,
+    /// taking and returning angles in radians like its `f64` closure. Returns `false` if no
+    /// function is registered under `name`.
+    pub fn register_decimal<F>(&mut self, name: &str, function: F) -> bool
+    where
+        F: Fn(&[Decimal]) -> Option<Decimal> + 'static,
+    {
+        match self.functions.get_mut(name) {
+            Some(entry) => {
+                entry.decimal = Some(Rc::new(function));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Gives the function registered under `name` an exact implementation on rationals.
+    /// Returns `false` if no function is registered under `name`.
+    pub fn register_rational<F>(&mut self, name: &str, function: F) -> bool
+    where
+        F: Fn(&[BigRational]) -> Option<BigRational> + 'static,
+    {
+        match self.functions.get_mut(name) {
+            Some(entry) => {
+                entry.rational = Some(Rc::new(function));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Gives the function registered under `name` a complex implementation, taking and
+    /// returning angles in radians like its `f64` closure. Returns `false` if no function is
+    /// registered under `name`.
+    pub fn register_complex<F>(&mut self, name: &str, function: F) -> bool
+    where
+        F: Fn(&[Complex64]) -> Complex64 + 'static,
+    {
+        match self.functions.get_mut(name) {
+            Some(entry) => {
+                entry.complex = Some(Rc::new(function));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Gives the function registered under `name` a matrix implementation. Returns `false` if
+    /// no function is registered under `name`.
+    pub fn register_matrix<F>(&mut self, name: &str, function: F) -> bool
+    where
+        F: Fn(&[Value]) -> Result<Value, MatrixError> + 'static,
+    {
+        match self.functions.get_mut(name) {
+            Some(entry) => {
+                entry.matrix = Some(Rc::new(function));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Marks the function registered under `name` as defined for real numbers only, so that a
+    /// complex argument is an error rather than `NaN`. Returns `false` if no function is
+    /// registered under `name`.
+    pub fn register_real(&mut self, name: &str) -> bool {
+        match self.functions.get_mut(name) {
+            Some(entry) => {
+                entry.real = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Marks the function registered under `name` as taking lists, so that a matrix among its
+    /// arguments, except the last `numbers` ones, stands for its elements. Returns `false` if no
+    /// function is registered under `name`.
+    pub fn register_list(&mut self, name: &str, numbers: usize) -> bool {
+        match self.functions.get_mut(name) {
+            Some(entry) => {
+                entry.list = Some(numbers);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<(Arity, NativeFunction)> {
+        self.functions
+            .get(name)
+            .map(|entry| (entry.arity, Rc::clone(&entry.function)))
+    }
+
+    pub fn decimal(&self, name: &str) -> Option<DecimalFunction> {
+        self.functions.get(name)?.decimal.clone()
+    }
+
+    pub fn rational(&self, name: &str) -> Option<RationalFunction> {
+        self.functions.get(name)?.rational.clone()
+    }
+
+    pub fn complex(&self, name: &str) -> Option<ComplexFunction> {
+        self.functions.get(name)?.complex.clone()
+    }
+
+    pub fn matrix(&self, name: &str) -> Option<MatrixFunction> {
+        self.functions.get(name)?.matrix.clone()
+    }
+
+    pub fn is_real(&self, name: &str) -> bool {
+        self.functions.get(name).is_some_and(|entry| entry.real)
+    }
+
+    /// The number of final arguments that are not lists, if the function takes lists.
+    pub fn list(&self, name: &str) -> Option<usize> {
+        self.functions.get(name)?.list
+    }
+
+    pub fn angle_usage(&self, name: &str) -> AngleUsage {
+        self.functions
+            .get(name)
+            .map_or(AngleUsage::None, |entry| entry.angles)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+
+    /// The registered names, in alphabetical order.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.functions.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arity() {
+        assert!(Arity::Fixed(2).accepts(2));
+        assert!(!Arity::Fixed(2).accepts(3));
+        assert!(Arity::Variadic { min: 1 }.accepts(5));
+        assert!(!Arity::Variadic { min: 1 }.accepts(0));
+        assert_eq!(Arity::Variadic { min: 1 }.to_string(), "at least 1");
+    }
+
+    #[test]
+    fn test_register_and_get() {
+        let mut registry = FunctionRegistry::empty();
+        assert!(!registry.contains("double"));
+        registry.register("double", Arity::Fixed(1), |args| args[0] * 2.0);
+        let (arity, function) = registry.get("double").unwrap();
+        assert_eq!(arity, Arity::Fixed(1));
+        assert_eq!(function(&[21.0]), 42.0);
+
+        registry.register("double", Arity::Variadic { min: 0 }, |args| {
+            args.len() as f64
+        });
+        let (arity, function) = registry.get("double").unwrap();
+        assert_eq!(arity, Arity::Variadic { min: 0 });
+        assert_eq!(function(&[1.0, 2.0]), 2.0);
+    }
+
+    #[test]
+    fn test_standard_registry() {
+        let registry = FunctionRegistry::standard();
+        let (_, log) = registry.get("log").unwrap();
+        assert_eq!(log(&[2.0, 8.0]), 3.0);
+        assert!(registry.names().starts_with(&["abs", "acos", "acosh"]));
+        assert_eq!(registry.angle_usage("sin"), AngleUsage::Arguments);
+        assert_eq!(registry.angle_usage("atan2"), AngleUsage::Result);
+        assert_eq!(registry.angle_usage("sinh"), AngleUsage::None);
+
+        let two = Decimal::from_f64(2.0, 10).unwrap();
+        let sqrt = registry.decimal("sqrt").unwrap();
+        assert_eq!(sqrt(&[two]).unwrap().to_string(), "1.414213562");
+        assert!(registry
+            .names()
+            .iter()
+            .all(|name| registry.decimal(name).is_some()));
+
+        let sqrt = registry.complex("sqrt").unwrap();
+        assert_eq!(sqrt(&[Complex64::from(-4.0)]), Complex64::new(0.0, 2.0));
+        let (_, arg) = registry.get("arg").unwrap();
+        assert_eq!(arg(&[-1.0]), std::f64::consts::PI);
+        assert_eq!(registry.angle_usage("arg"), AngleUsage::Result);
+        assert!(registry.complex("hypot").is_none());
+
+        let third = BigRational::new(1.into(), 3.into());
+        let pow = registry.rational("pow").unwrap();
+        assert_eq!(
+            pow(&[third.clone(), BigRational::from_integer(2.into())]),
+            Some(third.clone() * third.clone())
+        );
+        assert_eq!(pow(&[third.clone(), third]), None);
+        assert!(registry.rational("sqrt").is_none());
+
+        assert_eq!(registry.list("mean"), Some(0));
+        assert_eq!(registry.list("percentile"), Some(1));
+        assert_eq!(registry.list("det"), None);
+        assert!(registry.matrix("mean").is_none());
+    }
+
+    #[test]
+    fn test_register_decimal() {
+        let mut registry = FunctionRegistry::standard();
+        let double = |args: &[Decimal]| Some(args[0].clone() + args[0].clone());
+        assert!(!registry.register_decimal("double", double));
+        registry.register("double", Arity::Fixed(1), |args| args[0] * 2.0);
+        assert!(registry.register_decimal("double", double));
+        assert!(registry.decimal("double").is_some());
+
+        // Replacing a function drops its decimal implementation.
+        registry.register("sqrt", Arity::Fixed(1), |args| args[0].sqrt());
+        assert!(registry.decimal("sqrt").is_none());
+    }
+}
@@ -4,142 +4,854 @@
 //!
 //! ## Tokens
 //!
-//! The following tokens are supported:
+//! Each `Token` pairs a `TokenKind` with the `Span` it was read from.
+//! The following token kinds are supported:
 //!
-//! - `Number`: Represents a numeric value.
+//! - `Number`: Represents a numeric value, written as a decimal literal (`42`, `3.14`, `.5`,
+//!   `6.02e23`, `1e-9`) or as a hexadecimal, octal or binary integer (`0x1F`, `0o17`, `0b101`).
+//!   Digits may be grouped with `_`, as in `1_000_000`. The value is a `Literal`, which keeps
+//!   the exact number written besides the nearest `f64`.
+//! - `Angle`: Represents an angle, written as a number immediately followed by the unit
+//!   `deg`, `rad` or `grad`, such as `30deg` or `1.2rad`.
+//! - `Imaginary`: Represents an imaginary number, written as a number immediately followed by
+//!   `i` or `j`, such as `4i`, `0.5j` or `0x1Fi`. Its value is the nearest `f64`, as complex
+//!   numbers are not exact.
 //! - `Plus`: Represents the addition operator (`+`).
 //! - `Minus`: Represents the subtraction operator (`-`).
 //! - `Multiply`: Represents the multiplication operator (`*`).
 //! - `Divide`: Represents the division operator (`/`).
+//! - `Caret`: Represents the exponent operator (`^` or `**`).
+//! - `Percent`: Represents the modulo operator (`%`).
+//! - `Bang`: Represents the factorial operator (`!`).
+//! - `DotMultiply`, `DotDivide` and `DotCaret`: Represent the element-wise operators on
+//!   matrices (`.*`, `./` and `.^`). Their `.` is never part of a preceding number, so `2./A`
+//!   divides `2` by `A` element by element.
+//! - `Backslash`: Represents the left division operator (`\`), which solves linear systems.
+//! - `Less`, `LessEqual`, `Greater`, `GreaterEqual`, `Equal` and `NotEqual`: Represent the
+//!   comparison operators (`<`, `<=`, `>`, `>=`, `==` and `!=`).
+//! - `And`, `Or` and `Not`: Represent the boolean operators, written as the keywords `and`,
+//!   `or` and `not`.
+//! - `Boolean`: Represents the boolean literals `true` and `false`.
+//! - `Question` and `Colon`: Represent the two halves of the conditional operator (`?` and
+//!   `:`).
+//! - `Operator`: Represents a custom operator registered in an `OperatorTable`.
 //! - `LeftParen`: Represents a left parenthesis (`(`).
 //! - `RightParen`: Represents a right parenthesis (`)`).
-//! - `Cos`: Represents the cosine function.
-//! - `Acos`: Represents the arccosine function.
-//! - `Sin`: Represents the sine function.
-//! - `Asin`: Represents the arcsine function.
-//! - `Tan`: Represents the tangent function.
-//! - `Atan`: Represents the arctangent function.
-//! - `Sqrt`: Represents the square root function.
-//! - `Pow`: Represents the exponentiation function.
+//! - `LeftBracket` and `RightBracket`: Represent the brackets around a matrix (`[` and `]`).
+//! - `Identifier`: Represents a variable or function name, such as `x`, `rate_2` or `sin`;
+//!   the keywords `and`, `or`, `not`, `true` and `false` are not names.
+//! - `Assign`: Represents the assignment operator (`=`).
 //! - `Comma`: Represents a comma separator (`,`).
+//! - `Semicolon`: Represents a statement separator (`;`).
+//!
+//! ## Errors
+//!
+//! Tokenization fails with a `LexError` (see the `error` module) that records the offending
+//! text and its byte range (`Span`) in the input:
+//!
+//! - `InvalidCharacter`: Indicates a character that cannot start any token.
+//! - `MalformedNumber`: Indicates a numeric literal that is not valid, such as `1.2.3`, `0x`,
+//!   `1__0`, `1e5e5` or `0x1F.5`.
 //!
 //! ## Functions
 //!
-//! - `tokenize(input: &str) -> Vec<Token>`: Tokenizes the input expression into a vector of tokens.
+//! - `tokenize(input: &str) -> Result<Vec<Token>, CalcError>`: Tokenizes the input expression into a vector of tokens.
+//! - `tokenize_with(input: &str, operators: &OperatorTable) -> Result<Vec<Token>, CalcError>`: Tokenizes the input,
+//!   also recognising the symbols of the custom operators in `operators`.
+
+use std::fmt;
+use std::iter::Peekable;
+use std::ops::Neg;
+use std::str::{CharIndices, FromStr};
+
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
+use crate::error::{CalcError, LexError};
+use crate::evaluator::AngleMode;
+use crate::operator::OperatorTable;
+
+/// A byte range `start..end` in the input string.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
 
+/// The value of a number literal: the nearest `f64`, and the exact decimal it denotes unless
+/// its exponent is too large to be represented, as in `1e99999999999999999999`.
 #[derive(Debug, PartialEq, Clone)]
-pub enum Token {
-    Number(f64),
+pub struct Literal {
+    pub value: f64,
+    pub exact: Option<BigDecimal>,
+}
+
+/// The literal of the shortest decimal that parses to `value`.
+impl From<f64> for Literal {
+    fn from(value: f64) -> Self {
+        // `{:e}` writes the shortest digits that round-trip, as in `-1.25e-3`.
+        let exact = value
+            .is_finite()
+            .then(|| BigDecimal::from_str(&format!("{:e}", value)).ok())
+            .flatten();
+        Literal { value, exact }
+    }
+}
+
+impl Neg for Literal {
+    type Output = Literal;
+
+    fn neg(self) -> Literal {
+        Literal {
+            value: -self.value,
+            exact: self.exact.map(|exact| -exact),
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl Token {
+    pub fn new(kind: TokenKind, start: usize, end: usize) -> Self {
+        Token {
+            kind,
+            span: Span::new(start, end),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TokenKind {
+    Number(Literal),
+    Angle(Literal, AngleMode),
+    Imaginary(f64),
     Plus,
     Minus,
     Multiply,
     Divide,
+    Caret,
+    Percent,
+    Bang,
+    DotMultiply,
+    DotDivide,
+    DotCaret,
+    Backslash,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+    Not,
+    Boolean(bool),
+    Question,
+    Colon,
+    Operator(String),
     LeftParen,
     RightParen,
-    Cos,
-    Acos,
-    Sin,
-    Asin,
-    Tan,
-    Atan,
-    Sqrt,
-    Pow,
+    LeftBracket,
+    RightBracket,
+    Identifier(String),
+    Assign,
     Comma,
+    Semicolon,
 }
 
-pub fn tokenize(input: &str) -> Vec<Token> {
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Number(val) => write!(f, "{}", val),
+            TokenKind::Angle(val, unit) => write!(f, "{}{}", val, unit.suffix()),
+            TokenKind::Imaginary(val) => write!(f, "{}i", val),
+            TokenKind::Plus => write!(f, "+"),
+            TokenKind::Minus => write!(f, "-"),
+            TokenKind::Multiply => write!(f, "*"),
+            TokenKind::Divide => write!(f, "/"),
+            TokenKind::Caret => write!(f, "^"),
+            TokenKind::DotMultiply => write!(f, ".*"),
+            TokenKind::DotDivide => write!(f, "./"),
+            TokenKind::DotCaret => write!(f, ".^"),
+            TokenKind::Backslash => write!(f, "\\"),
+            TokenKind::Percent => write!(f, "%"),
+            TokenKind::Bang => write!(f, "!"),
+            TokenKind::Less => write!(f, "<"),
+            TokenKind::LessEqual => write!(f, "<="),
+            TokenKind::Greater => write!(f, ">"),
+            TokenKind::GreaterEqual => write!(f, ">="),
+            TokenKind::Equal => write!(f, "=="),
+            TokenKind::NotEqual => write!(f, "!="),
+            TokenKind::And => write!(f, "and"),
+            TokenKind::Or => write!(f, "or"),
+            TokenKind::Not => write!(f, "not"),
+            TokenKind::Boolean(value) => write!(f, "{}", value),
+            TokenKind::Question => write!(f, "?"),
+            TokenKind::Colon => write!(f, ":"),
+            TokenKind::Operator(symbol) => write!(f, "{}", symbol),
+            TokenKind::LeftParen => write!(f, "("),
+            TokenKind::RightParen => write!(f, ")"),
+            TokenKind::LeftBracket => write!(f, "["),
+            TokenKind::RightBracket => write!(f, "]"),
+            TokenKind::Identifier(name) => write!(f, "{}", name),
+            TokenKind::Assign => write!(f, "="),
+            TokenKind::Comma => write!(f, ","),
+            TokenKind::Semicolon => write!(f, ";"),
+        }
+    }
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, CalcError> {
+    tokenize_with(input, &OperatorTable::standard())
+}
+
+pub fn tokenize_with(input: &str, operators: &OperatorTable) -> Result<Vec<Token>, CalcError> {
+    let symbols = operators.symbols();
     let mut tokens = Vec::new();
-    let mut chars = input.chars().peekable();
-
-    while let Some(c) = chars.next() {
-        match c {
-            '0'..='9' => {
-                let mut number = String::from(c);
-                while let Some(&next) = chars.peek() {
-                    if next.is_digit(10) || next == '.' {
-                        number.push(chars.next().unwrap());
-                    } else {
-                        break;
-                    }
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        if let Some(symbol) = symbols
+            .iter()
+            .find(|symbol| input[start..].starts_with(**symbol) && !is_identifier(symbol))
+        {
+            // The first character of the symbol has already been consumed.
+            for _ in 1..symbol.chars().count() {
+                chars.next();
+            }
+            tokens.push(Token::new(
+                TokenKind::Operator(symbol.to_string()),
+                start,
+                start + symbol.len(),
+            ));
+            continue;
+        }
+
+        let kind = match c {
+            '0'..='9' => literal(number(input, start, c, &mut chars)?, &mut chars),
+            '.' if chars.peek().is_some_and(|&(_, next)| next.is_ascii_digit()) => {
+                literal(number(input, start, c, &mut chars)?, &mut chars)
+            }
+            '.' if chars.next_if(|&(_, next)| next == '*').is_some() => TokenKind::DotMultiply,
+            '.' if chars.next_if(|&(_, next)| next == '/').is_some() => TokenKind::DotDivide,
+            '.' if chars.next_if(|&(_, next)| next == '^').is_some() => TokenKind::DotCaret,
+            '+' => TokenKind::Plus,
+            '-' => TokenKind::Minus,
+            '*' => {
+                if chars.next_if(|&(_, next)| next == '*').is_some() {
+                    TokenKind::Caret
+                } else {
+                    TokenKind::Multiply
                 }
-                tokens.push(Token::Number(number.parse().unwrap()));
             }
-            '+' => tokens.push(Token::Plus),
-            '-' => tokens.push(Token::Minus),
-            '*' => tokens.push(Token::Multiply),
-            '/' => tokens.push(Token::Divide),
-            '(' => tokens.push(Token::LeftParen),
-            ')' => tokens.push(Token::RightParen),
-            ',' => tokens.push(Token::Comma),
-            'a'..='z' => {
+            '^' => TokenKind::Caret,
+            '/' => TokenKind::Divide,
+            '%' => TokenKind::Percent,
+            '!' if chars.next_if(|&(_, next)| next == '=').is_some() => TokenKind::NotEqual,
+            '!' => TokenKind::Bang,
+            '<' if chars.next_if(|&(_, next)| next == '=').is_some() => TokenKind::LessEqual,
+            '<' => TokenKind::Less,
+            '>' if chars.next_if(|&(_, next)| next == '=').is_some() => TokenKind::GreaterEqual,
+            '>' => TokenKind::Greater,
+            '?' => TokenKind::Question,
+            ':' => TokenKind::Colon,
+            '\\' => TokenKind::Backslash,
+            '(' => TokenKind::LeftParen,
+            ')' => TokenKind::RightParen,
+            '[' => TokenKind::LeftBracket,
+            ']' => TokenKind::RightBracket,
+            ',' => TokenKind::Comma,
+            ';' => TokenKind::Semicolon,
+            '=' if chars.next_if(|&(_, next)| next == '=').is_some() => TokenKind::Equal,
+            '=' => TokenKind::Assign,
+            c if c.is_alphabetic() || c == '_' => {
                 let mut identifier = String::from(c);
-                while let Some(&next) = chars.peek() {
-                    if next.is_alphabetic() {
-                        identifier.push(chars.next().unwrap());
+                while let Some(&(_, next)) = chars.peek() {
+                    if next.is_alphanumeric() || next == '_' {
+                        identifier.push(next);
+                        chars.next();
                     } else {
                         break;
                     }
                 }
 
                 match identifier.as_str() {
-                    "cos" => tokens.push(Token::Cos),
-                    "sin" => tokens.push(Token::Sin),
-                    "tan" => tokens.push(Token::Tan),
-                    "acos" => tokens.push(Token::Acos),
-                    "asin" => tokens.push(Token::Asin),
-                    "atan" => tokens.push(Token::Atan),
-                    "sqrt" => tokens.push(Token::Sqrt),
-                    "pow" => tokens.push(Token::Pow),
-                    _ => panic!("Unknown identifier: {}", identifier),
+                    name if symbols.contains(&name) => TokenKind::Operator(identifier),
+                    "and" => TokenKind::And,
+                    "or" => TokenKind::Or,
+                    "not" => TokenKind::Not,
+                    "true" => TokenKind::Boolean(true),
+                    "false" => TokenKind::Boolean(false),
+                    _ => TokenKind::Identifier(identifier),
+                }
+            }
+            c if c.is_whitespace() => continue,
+            _ => {
+                return Err(LexError::InvalidCharacter {
+                    character: c,
+                    span: Span::new(start, start + c.len_utf8()),
                 }
+                .into())
+            }
+        };
+
+        let end = chars.peek().map_or(input.len(), |&(next, _)| next);
+        tokens.push(Token::new(kind, start, end));
+    }
+
+    Ok(tokens)
+}
+
+/// Reads the rest of a numeric literal whose first character, `first`, is at `start`.
+fn number(
+    input: &str,
+    start: usize,
+    first: char,
+    chars: &mut Peekable<CharIndices>,
+) -> Result<Literal, LexError> {
+    let radix = match (first, chars.peek()) {
+        ('0', Some((_, 'x' | 'X'))) => 16,
+        ('0', Some((_, 'o' | 'O'))) => 8,
+        ('0', Some((_, 'b' | 'B'))) => 2,
+        _ => 10,
+    };
+
+    let digit = |c: char| c.is_ascii_digit() || c == '.' || c == '_';
+    let word = |c: char| c.is_ascii_alphanumeric() || c == '.' || c == '_';
+    if radix == 10 {
+        advance(chars, run(chars, digit).len());
+        // An `e` only starts an exponent when a digit or a sign follows, so `2e` stays `2` and
+        // `e`. A second exponent, as in `1e5e5`, makes the rest of the word part of the number.
+        let exponent = |&(_, c): &(usize, char)| c == 'e' || c == 'E';
+        let mut lookahead = chars.clone();
+        if lookahead.next_if(exponent).is_some()
+            && lookahead
+                .peek()
+                .is_some_and(|&(_, next)| digit(next) || matches!(next, '+' | '-'))
+        {
+            chars.next();
+            chars.next_if(|&(_, c)| c == '+' || c == '-');
+            advance(chars, run(chars, digit).len());
+            if chars.peek().is_some_and(exponent) {
+                advance(chars, run(chars, word).len());
             }
-            ' ' => continue,
-            _ => panic!("Invalid character: {}", c),
         }
+    } else {
+        chars.next();
+        // A final `i` or `j` after a digit, neither being a digit in any of these radixes, is
+        // left as the suffix of an imaginary number, as in `0x1Fi`.
+        let run = run(chars, word);
+        let imaginary = matches!(run[..], [.., c, 'i' | 'j'] if c.is_digit(radix));
+        advance(chars, run.len() - usize::from(imaginary));
     }
 
-    tokens
+    let end = chars.peek().map_or(input.len(), |&(next, _)| next);
+    let text = &input[start..end];
+    parse_number(text, radix).ok_or_else(|| LexError::MalformedNumber {
+        text: String::from(text),
+        span: Span::new(start, end),
+    })
+}
+
+/// The characters ahead in `chars` that `accept` takes, up to a `.` that starts an element-wise
+/// operator, so `2./A` is `2` followed by `./`.
+fn run(chars: &Peekable<CharIndices>, accept: impl Fn(char) -> bool) -> Vec<char> {
+    let mut lookahead = chars.clone();
+    let mut run = Vec::new();
+    while let Some((_, c)) = lookahead.next_if(|&(_, c)| accept(c)) {
+        if c == '.'
+            && lookahead
+                .peek()
+                .is_some_and(|&(_, next)| matches!(next, '*' | '/' | '^'))
+        {
+            break;
+        }
+        run.push(c);
+    }
+    run
+}
+
+/// Moves `chars` forward by `count` characters.
+fn advance(chars: &mut Peekable<CharIndices>, count: usize) {
+    for _ in 0..count {
+        chars.next();
+    }
+}
+
+/// Turns a number into an angle if it is immediately followed by `deg`, `rad` or `grad`, and
+/// into an imaginary number if it is immediately followed by `i` or `j`.
+fn literal(value: Literal, chars: &mut Peekable<CharIndices>) -> TokenKind {
+    let mut suffix = chars.clone();
+    let mut name = String::new();
+    while let Some((_, c)) = suffix.next_if(|&(_, c)| c.is_alphanumeric() || c == '_') {
+        name.push(c);
+    }
+
+    let kind = match AngleMode::from_suffix(&name) {
+        Some(unit) => TokenKind::Angle(value, unit),
+        None if name == "i" || name == "j" => TokenKind::Imaginary(value.value),
+        None => return TokenKind::Number(value),
+    };
+    *chars = suffix;
+    kind
+}
+
+/// Parses a numeric literal, including its `0x`, `0o` or `0b` prefix when `radix` is not 10.
+fn parse_number(text: &str, radix: u32) -> Option<Literal> {
+    // A `_` separator must sit between two digits.
+    let chars: Vec<char> = text.chars().collect();
+    for (i, &c) in chars.iter().enumerate() {
+        let is_digit = |index: Option<usize>| {
+            index
+                .and_then(|index| chars.get(index))
+                .is_some_and(|c| c.is_digit(radix))
+        };
+        if c == '_' && !(is_digit(i.checked_sub(1)) && is_digit(Some(i + 1))) {
+            return None;
+        }
+    }
+
+    let digits: String = chars.into_iter().filter(|&c| c != '_').collect();
+    if radix == 10 {
+        return Some(Literal {
+            value: digits.parse().ok()?,
+            exact: BigDecimal::from_str(&digits).ok(),
+        });
+    }
+    let integer = BigInt::parse_bytes(&digits.as_bytes()[2..], radix)?;
+    Some(Literal {
+        value: integer.to_f64().unwrap_or(f64::INFINITY),
+        exact: Some(BigDecimal::from(integer)),
+    })
+}
+
+/// Whether `symbol` is spelled like a name, in which case it only matches a whole identifier.
+fn is_identifier(symbol: &str) -> bool {
+    symbol
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::operator::{Associativity, Operator};
 
     #[test]
     fn test_correct_tokenization() {
         let input = "1 + (2 * 3 - 10.5) / sin(0.5)";
         let expected_tokens = vec![
-            Token::Number(1.0),
-            Token::Plus,
-            Token::LeftParen,
-            Token::Number(2.0),
-            Token::Multiply,
-            Token::Number(3.0),
-            Token::Minus,
-            Token::Number(10.5),
-            Token::RightParen,
-            Token::Divide,
-            Token::Sin,
-            Token::LeftParen,
-            Token::Number(0.5),
-            Token::RightParen,
+            TokenKind::Number(1.0.into()),
+            TokenKind::Plus,
+            TokenKind::LeftParen,
+            TokenKind::Number(2.0.into()),
+            TokenKind::Multiply,
+            TokenKind::Number(3.0.into()),
+            TokenKind::Minus,
+            TokenKind::Number(10.5.into()),
+            TokenKind::RightParen,
+            TokenKind::Divide,
+            TokenKind::Identifier(String::from("sin")),
+            TokenKind::LeftParen,
+            TokenKind::Number(0.5.into()),
+            TokenKind::RightParen,
+        ];
+
+        let tokens: Vec<TokenKind> = tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.kind)
+            .collect();
+        assert_eq!(tokens, expected_tokens);
+    }
+
+    #[test]
+    fn test_exponent_operators() {
+        let input = "2^3 ** 4*5";
+        let expected_tokens = vec![
+            Token::new(TokenKind::Number(2.0.into()), 0, 1),
+            Token::new(TokenKind::Caret, 1, 2),
+            Token::new(TokenKind::Number(3.0.into()), 2, 3),
+            Token::new(TokenKind::Caret, 4, 6),
+            Token::new(TokenKind::Number(4.0.into()), 7, 8),
+            Token::new(TokenKind::Multiply, 8, 9),
+            Token::new(TokenKind::Number(5.0.into()), 9, 10),
+        ];
+
+        let tokens = tokenize(input).unwrap();
+        assert_eq!(tokens, expected_tokens);
+    }
+
+    #[test]
+    fn test_matrix_tokens() {
+        let input = "[a.*b; c./2] \\ d.^2";
+        let kinds: Vec<TokenKind> = tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.kind)
+            .collect();
+        let name = |name: &str| TokenKind::Identifier(String::from(name));
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::LeftBracket,
+                name("a"),
+                TokenKind::DotMultiply,
+                name("b"),
+                TokenKind::Semicolon,
+                name("c"),
+                TokenKind::DotDivide,
+                TokenKind::Number(2.0.into()),
+                TokenKind::RightBracket,
+                TokenKind::Backslash,
+                name("d"),
+                TokenKind::DotCaret,
+                TokenKind::Number(2.0.into()),
+            ]
+        );
+
+        // The `.` of an element-wise operator after a number is not part of the number.
+        let kinds: Vec<TokenKind> = tokenize("2./A 2.^A 2.5.*A 0x1F./A")
+            .unwrap()
+            .into_iter()
+            .map(|token| token.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Number(2.0.into()),
+                TokenKind::DotDivide,
+                name("A"),
+                TokenKind::Number(2.0.into()),
+                TokenKind::DotCaret,
+                name("A"),
+                TokenKind::Number(2.5.into()),
+                TokenKind::DotMultiply,
+                name("A"),
+                TokenKind::Number(31.0.into()),
+                TokenKind::DotDivide,
+                name("A"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_logic_tokens() {
+        let input = "x<=1 or not y!=2 and z==true ? a>b : c>=3<4";
+        let kinds: Vec<TokenKind> = tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.kind)
+            .collect();
+        let name = |name: &str| TokenKind::Identifier(String::from(name));
+        assert_eq!(
+            kinds,
+            vec![
+                name("x"),
+                TokenKind::LessEqual,
+                TokenKind::Number(1.0.into()),
+                TokenKind::Or,
+                TokenKind::Not,
+                name("y"),
+                TokenKind::NotEqual,
+                TokenKind::Number(2.0.into()),
+                TokenKind::And,
+                name("z"),
+                TokenKind::Equal,
+                TokenKind::Boolean(true),
+                TokenKind::Question,
+                name("a"),
+                TokenKind::Greater,
+                name("b"),
+                TokenKind::Colon,
+                name("c"),
+                TokenKind::GreaterEqual,
+                TokenKind::Number(3.0.into()),
+                TokenKind::Less,
+                TokenKind::Number(4.0.into()),
+            ]
+        );
+
+        // `!` followed by `=` is `!=`, and a keyword is only matched as a whole name.
+        let kinds: Vec<TokenKind> = tokenize("3! = android")
+            .unwrap()
+            .into_iter()
+            .map(|token| token.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Number(3.0.into()),
+                TokenKind::Bang,
+                TokenKind::Assign,
+                name("android"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_custom_operators() {
+        let mut operators = OperatorTable::standard();
+        operators
+            .add(
+                Operator::infix(
+                    TokenKind::Operator(String::from("<>")),
+                    5,
+                    Associativity::Left,
+                )
+                .calling("avg"),
+            )
+            .unwrap();
+        operators
+            .add(
+                Operator::infix(
+                    TokenKind::Operator(String::from("mod")),
+                    5,
+                    Associativity::Left,
+                )
+                .calling("modulo"),
+            )
+            .unwrap();
+
+        let input = "5 % 3! <> 7 mod model";
+        let expected_tokens = vec![
+            Token::new(TokenKind::Number(5.0.into()), 0, 1),
+            Token::new(TokenKind::Percent, 2, 3),
+            Token::new(TokenKind::Number(3.0.into()), 4, 5),
+            Token::new(TokenKind::Bang, 5, 6),
+            Token::new(TokenKind::Operator(String::from("<>")), 7, 9),
+            Token::new(TokenKind::Number(7.0.into()), 10, 11),
+            Token::new(TokenKind::Operator(String::from("mod")), 12, 15),
+            Token::new(TokenKind::Identifier(String::from("model")), 16, 21),
+        ];
+
+        let tokens = tokenize_with(input, &operators).unwrap();
+        assert_eq!(tokens, expected_tokens);
+        let tokens = tokenize("1 <> 2").unwrap();
+        assert_eq!(tokens[1].kind, TokenKind::Less);
+        assert_eq!(tokens[2].kind, TokenKind::Greater);
+    }
+
+    #[test]
+    fn test_whitespace() {
+        let input = "1\t+\n 2";
+        let expected_tokens = vec![
+            Token::new(TokenKind::Number(1.0.into()), 0, 1),
+            Token::new(TokenKind::Plus, 2, 3),
+            Token::new(TokenKind::Number(2.0.into()), 5, 6),
         ];
 
-        let tokens = tokenize(input);
+        let tokens = tokenize(input).unwrap();
         assert_eq!(tokens, expected_tokens);
     }
 
     #[test]
-    #[should_panic(expected = "Unknown identifier: saad")]
-    fn test_panic_incorrect_identifier() {
-        let input = "saad(10)";
-        tokenize(input);
+    fn test_token_spans() {
+        let input = "12.5 +sqrt( 4)";
+        let expected_tokens = vec![
+            Token::new(TokenKind::Number(12.5.into()), 0, 4),
+            Token::new(TokenKind::Plus, 5, 6),
+            Token::new(TokenKind::Identifier(String::from("sqrt")), 6, 10),
+            Token::new(TokenKind::LeftParen, 10, 11),
+            Token::new(TokenKind::Number(4.0.into()), 12, 13),
+            Token::new(TokenKind::RightParen, 13, 14),
+        ];
+
+        let tokens = tokenize(input).unwrap();
+        assert_eq!(tokens, expected_tokens);
+    }
+
+    #[test]
+    fn test_identifiers_and_assignment() {
+        let input = "rate_2 = x1 + sinx";
+        let expected_tokens = vec![
+            Token::new(TokenKind::Identifier(String::from("rate_2")), 0, 6),
+            Token::new(TokenKind::Assign, 7, 8),
+            Token::new(TokenKind::Identifier(String::from("x1")), 9, 11),
+            Token::new(TokenKind::Plus, 12, 13),
+            Token::new(TokenKind::Identifier(String::from("sinx")), 14, 18),
+        ];
+
+        let tokens = tokenize(input).unwrap();
+        assert_eq!(tokens, expected_tokens);
+    }
+
+    #[test]
+    fn test_numeric_literals() {
+        let cases = [
+            ("42", 42.0),
+            ("2.75", 2.75),
+            (".5", 0.5),
+            ("5.", 5.0),
+            ("1e-9", 1e-9),
+            ("6.02E23", 6.02e23),
+            ("2.5e+3", 2500.0),
+            ("0x1F", 31.0),
+            ("0XfF", 255.0),
+            ("0o17", 15.0),
+            ("0b101", 5.0),
+            ("1_000_000", 1_000_000.0),
+            ("0b1010_1010", 170.0),
+            ("1_0.2_5e1_0", 10.25e10),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                tokenize(input),
+                Ok(vec![Token::new(
+                    TokenKind::Number(expected.into()),
+                    0,
+                    input.len()
+                )]),
+                "{}",
+                input
+            );
+        }
+
+        // The exact value keeps the digits that do not fit in an `f64`.
+        let tokens = tokenize("0.1234567890123456789012345 0x1_0000_0000_0000_0001").unwrap();
+        let exact = |token: &Token| match &token.kind {
+            TokenKind::Number(literal) => literal.exact.clone(),
+            _ => None,
+        };
+        assert_eq!(
+            exact(&tokens[0]),
+            BigDecimal::from_str("0.1234567890123456789012345").ok()
+        );
+        assert_eq!(exact(&tokens[1]), Some(BigDecimal::from((1u128 << 64) + 1)));
+
+        let tokens: Vec<TokenKind> = tokenize("2e + 1")
+            .unwrap()
+            .into_iter()
+            .map(|token| token.kind)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                TokenKind::Number(2.0.into()),
+                TokenKind::Identifier(String::from("e")),
+                TokenKind::Plus,
+                TokenKind::Number(1.0.into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_angle_literals() {
+        let input = "sin(30deg) + 1.5rad*2 + 50grad + 2 deg + 3degrees";
+        let tokens = tokenize(input).unwrap();
+        assert_eq!(
+            tokens[2],
+            Token::new(TokenKind::Angle(30.0.into(), AngleMode::Degrees), 4, 9)
+        );
+        assert_eq!(
+            tokens[5],
+            Token::new(TokenKind::Angle(1.5.into(), AngleMode::Radians), 13, 19)
+        );
+        assert_eq!(tokens[6].kind, TokenKind::Multiply);
+        assert_eq!(
+            tokens[9].kind,
+            TokenKind::Angle(50.0.into(), AngleMode::Gradians)
+        );
+        assert_eq!(tokens[11].kind, TokenKind::Number(2.0.into()));
+        assert_eq!(tokens[12].kind, TokenKind::Identifier(String::from("deg")));
+        assert_eq!(tokens[14].kind, TokenKind::Number(3.0.into()));
+        assert_eq!(
+            tokens[15].kind,
+            TokenKind::Identifier(String::from("degrees"))
+        );
+    }
+
+    #[test]
+    fn test_imaginary_literals() {
+        let input = "3+4i - 0.5j*2 + 1e3i + 2 i + 5in";
+        let tokens = tokenize(input).unwrap();
+        assert_eq!(tokens[2], Token::new(TokenKind::Imaginary(4.0), 2, 4));
+        assert_eq!(tokens[4], Token::new(TokenKind::Imaginary(0.5), 7, 11));
+        assert_eq!(tokens[8].kind, TokenKind::Imaginary(1000.0));
+        assert_eq!(tokens[10].kind, TokenKind::Number(2.0.into()));
+        assert_eq!(tokens[11].kind, TokenKind::Identifier(String::from("i")));
+        assert_eq!(tokens[13].kind, TokenKind::Number(5.0.into()));
+        assert_eq!(tokens[14].kind, TokenKind::Identifier(String::from("in")));
+
+        let tokens = tokenize("0x1Fi + 0b11j").unwrap();
+        assert_eq!(tokens[0], Token::new(TokenKind::Imaginary(31.0), 0, 5));
+        assert_eq!(tokens[2].kind, TokenKind::Imaginary(3.0));
     }
 
     #[test]
-    #[should_panic(expected = "Invalid character: #")]
-    fn test_panic_invalid_character() {
+    fn test_error_malformed_number() {
+        let cases = [
+            ("1 + 1.2.3", 4, 9),
+            ("0x", 0, 2),
+            ("0b102", 0, 5),
+            ("0xG", 0, 3),
+            ("1__0", 0, 4),
+            ("1_", 0, 2),
+            ("1_.5", 0, 4),
+            ("0x_1", 0, 4),
+            ("2 * 1e_5", 4, 8),
+            ("1e+", 0, 3),
+            ("1e5e5", 0, 5),
+            ("2 * 1.5e-3.2", 4, 12),
+            ("0x1F.5", 0, 6),
+            ("0b1.1", 0, 5),
+            ("0x1Fij", 0, 6),
+        ];
+        for (input, start, end) in cases {
+            assert_eq!(
+                tokenize(input),
+                Err(CalcError::Lex(LexError::MalformedNumber {
+                    text: String::from(&input[start..end]),
+                    span: Span::new(start, end),
+                })),
+                "{}",
+                input
+            );
+        }
+        assert!(tokenize(". 5").is_err());
+    }
+
+    #[test]
+    fn test_error_invalid_character() {
         let input = "10 # 5";
-        tokenize(input);
+        let result = tokenize(input);
+        assert_eq!(
+            result,
+            Err(CalcError::Lex(LexError::InvalidCharacter {
+                character: '#',
+                span: Span::new(3, 4),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_error_span_is_in_bytes() {
+        let input = "é + 1 × 2";
+        let result = tokenize(input);
+        assert_eq!(
+            result,
+            Err(CalcError::Lex(LexError::InvalidCharacter {
+                character: '×',
+                span: Span::new(7, 9),
+            }))
+        );
     }
 }
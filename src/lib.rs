@@ -0,0 +1,186 @@
+//! # CalcRS
+//!
+//! A library for tokenizing, parsing and evaluating mathematical expressions.
+//! The `calcrs` command line application is a thin consumer of this crate.
+//!
+//! ## Usage
+//!
+//! The quickest way to evaluate an expression is `eval_str`:
+//!
+//! ```
+//! assert_eq!(calcrs::eval_str("2 + 3 * 4"), Ok(14.0));
+//! ```
+//!
+//! Each stage can also be run on its own, for example to inspect the AST:
+//!
+//! ```
+//! use calcrs::{evaluate, parse, tokenize, Environment};
+//!
+//! let tokens = tokenize("sqrt(16) / 2")?;
+//! let ast = parse(&tokens)?;
+//! assert_eq!(evaluate(&ast, &mut Environment::new())?, 2.0);
+//! # Ok::<(), calcrs::CalcError>(())
+//! ```
+//!
+//! Variables and user-defined functions live in an `Environment` that is kept between evaluations:
+//!
+//! ```
+//! use calcrs::{eval_str_with, Environment};
+//!
+//! let mut env = Environment::new();
+//! eval_str_with("x = 3 * 2", &mut env)?;
+//! eval_str_with("f(a, b) = a * b + x", &mut env)?;
+//! assert_eq!(eval_str_with("f(2, 3)", &mut env)?, Some(12.0));
+//! # Ok::<(), calcrs::CalcError>(())
+//! ```
+//!
+//! The grammar is driven by an `OperatorTable`, to which custom operators can be added, and
+//! native functions are looked up in a `FunctionRegistry`, to which Rust closures can be
+//! added; see the `operator` and `function` modules.
+//!
+//! Evaluation uses `f64` by default. In `NumericMode::Rational`, literals are exact fractions,
+//! and in `NumericMode::Decimal` they are decimals of a chosen precision. Complex numbers such
+//! as `3+4i` are supported in every mode. `eval_value_with` returns the resulting `Value`:
+//!
+//! ```
+//! use calcrs::{eval_value_with, Environment, NumericMode};
+//!
+//! let mut env = Environment::new();
+//! env.set_numeric_mode(NumericMode::Rational);
+//! let value = eval_value_with("0.1 + 0.2", &mut env)?.unwrap();
+//! assert_eq!(value.to_string(), "0.3");
+//!
+//! env.set_numeric_mode(NumericMode::Decimal { precision: 25 });
+//! let value = eval_value_with("sqrt(2)", &mut env)?.unwrap();
+//! assert_eq!(value.to_string(), "1.414213562373095048801689");
+//!
+//! let value = eval_value_with("sqrt(-4) * (1 + i)", &mut env)?.unwrap();
+//! assert_eq!(value.to_string(), "-2+2i");
+//! # Ok::<(), calcrs::CalcError>(())
+//! ```
+//!
+//! Numbers can carry physical units, whose dimensions are checked, and be converted between
+//! units of the same dimension with `in`; see the `unit` module:
+//!
+//! ```
+//! use calcrs::{eval_value_with, Environment};
+//!
+//! let mut env = Environment::new();
+//! let value = eval_value_with("3 km / 20 min in km/h", &mut env)?.unwrap();
+//! assert_eq!(value.to_string(), "9 km/h");
+//! assert_eq!(eval_value_with("1 m + 2 s", &mut env).unwrap_err().code(), "E0310");
+//! # Ok::<(), calcrs::CalcError>(())
+//! ```
+//!
+//! Matrices such as `[1, 2; 3, 4]` support element-wise operators, the matrix product and
+//! linear algebra; see the `matrix` module:
+//!
+//! ```
+//! use calcrs::{eval_str_with, eval_value_with, Environment};
+//!
+//! let mut env = Environment::new();
+//! let value = eval_value_with("[1, 2; 3, 4] \\ [5; 6]", &mut env)?.unwrap();
+//! assert_eq!(value.to_string(), "[-4; 4.5]");
+//! assert_eq!(eval_str_with("det([1, 2; 3, 4])", &mut env)?, Some(-2.0));
+//! # Ok::<(), calcrs::CalcError>(())
+//! ```
+//!
+//! Comparisons give booleans, and the conditional `cond ? a : b` evaluates only the branch
+//! that is taken:
+//!
+//! ```
+//! use calcrs::{eval_str_with, eval_value_with, Environment};
+//!
+//! let mut env = Environment::new();
+//! let value = eval_value_with("1 < 2 and not 3 == 4", &mut env)?.unwrap();
+//! assert_eq!(value.to_string(), "true");
+//! eval_str_with("fact(n) = n <= 1 ? 1 : n * fact(n - 1)", &mut env)?;
+//! assert_eq!(eval_str_with("fact(5)", &mut env)?, Some(120.0));
+//! # Ok::<(), calcrs::CalcError>(())
+//! ```
+//!
+//! ## Errors
+//!
+//! Every public function returns a `CalcError` on failure. It carries the `Span` of the input
+//! responsible for the error and a stable error code, and can be turned into a rustc-style
+//! `Diagnostic`:
+//!
+//! ```
+//! use calcrs::{eval_str, CalcError, Diagnostic, EvaluationError, Span};
+//!
+//! let err = eval_str("1 / (2 - 2)").unwrap_err();
+//! assert_eq!(
+//!     err,
+//!     CalcError::Eval(EvaluationError::DivisionByZero { span: Span::new(4, 11) })
+//! );
+//! assert_eq!(err.code(), "E0301");
+//! print!("{}", Diagnostic::from(&err).render("1 / (2 - 2)", false));
+//! ```
+//!
+//! ## Modules
+//!
+//! - `lexer`: Tokenizes the input expression into individual tokens.
+//! - `parser`: Parses the tokens into an abstract syntax tree (AST).
+//! - `operator`: Defines the operator table that drives the parser.
+//! - `function`: Defines the registry of native functions callable by name.
+//! - `evaluator`: Evaluates the AST and computes the result.
+//! - `value`: Defines the floating-point, exact rational, decimal, complex, quantity, matrix and boolean values computed by the evaluator.
+//! - `decimal`: Implements arbitrary-precision decimals and the built-in functions on them.
+//! - `matrix`: Implements matrices and the linear algebra on them.
+//! - `statistics`: Implements the aggregate and statistics functions over lists of numbers.
+//! - `unit`: Defines the physical units, their dimensions and the quantities built from them.
+//! - `error`: Defines the `CalcError` type returned by every stage.
+//! - `diagnostic`: Renders errors with the offending span underlined.
+
+pub mod decimal;
+pub mod diagnostic;
+pub mod error;
+pub mod evaluator;
+pub mod function;
+pub mod lexer;
+pub mod matrix;
+pub mod operator;
+pub mod parser;
+pub mod statistics;
+pub mod unit;
+pub mod value;
+
+pub use diagnostic::Diagnostic;
+pub use error::{CalcError, EvaluationError, LexError, ParseError};
+pub use evaluator::{
+    evaluate, evaluate_value, execute, execute_value, AngleMode, Environment, EvaluationMode,
+};
+pub use function::{AngleUsage, Arity, FunctionRegistry};
+pub use lexer::{tokenize, tokenize_with, Literal, Span, Token, TokenKind};
+pub use matrix::{Matrix, MatrixError, Shape, ShapeRequirement};
+pub use operator::{Operator, OperatorError, OperatorTable};
+pub use parser::{
+    parse, parse_program, parse_with, parse_with_recovery, Expression, ExpressionKind, ParseOutput,
+};
+pub use unit::{Dimension, Quantity, Unit};
+pub use value::{ComplexFormat, NumericMode, Value, ValueType};
+
+/// Tokenizes, parses and evaluates a single expression.
+pub fn eval_str(input: &str) -> Result<f64, CalcError> {
+    let tokens = tokenize(input)?;
+    let ast = parse(&tokens)?;
+    evaluate(&ast, &mut Environment::new())
+}
+
+/// Tokenizes, parses and executes a single statement with the variables and functions of `env`.
+///
+/// The input is parsed with the operators of `env`. Function definitions are stored in `env`
+/// and yield `None`.
+pub fn eval_str_with(input: &str, env: &mut Environment) -> Result<Option<f64>, CalcError> {
+    let tokens = tokenize_with(input, env.operators())?;
+    let ast = parser::parse_statement_with(&tokens, env.operators(), env.registry())?;
+    execute(&ast, env)
+}
+
+/// Tokenizes, parses and executes a single statement like `eval_str_with`, keeping the exact
+/// rational, decimal or complex value of the result.
+pub fn eval_value_with(input: &str, env: &mut Environment) -> Result<Option<Value>, CalcError> {
+    let tokens = tokenize_with(input, env.operators())?;
+    let ast = parser::parse_statement_with(&tokens, env.operators(), env.registry())?;
+    execute_value(&ast, env)
+}
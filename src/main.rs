@@ -6,10 +6,24 @@
 //! ## Usage
 //!
 //! ```
-//! calcrs <expression>
+//! calcrs [--rational | --precision <digits>] [--polar] [expression]
 //! ```
 //!
-//! - `<expression>`: The mathematical expression to evaluate.
+//! - `[expression]`: The mathematical expression to evaluate. Several expressions can be
+//!   separated by `;`, in which case each result is printed on its own line.
+//! - `--rational`: Computes with exact fractions instead of floating-point numbers, so
+//!   `0.1 + 0.2` prints `0.3` and `1/3` prints `1/3`.
+//! - `--precision <digits>`: Computes with decimals of that many significant digits, including
+//!   the built-in functions and constants.
+//! - `--polar`: Prints complex results in polar form, as in `5∠0.9272952180016122rad`, instead
+//!   of the rectangular form `3+4i`.
+//!
+//! Without an expression, `calcrs` starts an interactive session (REPL) with line editing
+//! and a history that persists across sessions. Variables and functions defined on one line
+//! remain available on the following ones. An input with unclosed parentheses continues on
+//! the next line. Press `Ctrl-C` to discard the current input and `Ctrl-D` (or type `exit`)
+//! to quit; type `help` to list the commands, functions and constants. The REPL is part of
+//! the default `repl` feature.
 //!
 //! ## Examples
 //!
@@ -17,6 +31,14 @@
 //! calcrs "2 + 3 * 4"
 //! calcrs "sin(0.5) * (1 + 2)"
 //! calcrs "sqrt(16) / 2"
+//! calcrs "1 + 2; 3 * 4"
+//! calcrs "r = 2; pi * r * r"
+//! calcrs "area(r) = pi * r * r; area(2)"
+//! calcrs "5! % 7"
+//! calcrs --rational "1/3 + 1/6"
+//! calcrs --precision 50 "sqrt(2)"
+//! calcrs "(3+4i) * (1-2i)"
+//! calcrs --polar "sqrt(-4) + 2"
 //! ```
 //!
 //! ## Error Handling
@@ -24,58 +46,115 @@
 //! The application handles the following error cases:
 //!
 //! - Invalid number of command line arguments
+//! - Invalid characters in the expression
 //! - Parsing errors in the expression
 //! - Division by zero during evaluation
+//! - References to undefined variables or functions
+//! - Calls with the wrong number of arguments or recursing too deeply
+//! - Assignments to built-in constants such as `pi`
 //! - Invalid mathematical operations
 //!
-//! In case of an error, an appropriate error message is displayed, and the application exits with a non-zero status code.
-//!
-//! ## Modules
+//! In case of an error, a diagnostic is displayed that shows the expression with the offending part
+//! underlined, and the application exits with a non-zero status code. All syntax errors of the input
+//! are reported at once. Diagnostics are coloured when standard error is a terminal and the `NO_COLOR`
+//! environment variable is not set.
 //!
-//! The application consists of the following modules:
+//! ## Library
 //!
-//! - `lexer`: Tokenizes the input expression into individual tokens.
-//! - `parser`: Parses the tokens into an abstract syntax tree (AST).
-//! - `evaluator`: Evaluates the AST and computes the result.
+//! The lexer, parser and evaluator live in the `calcrs` library crate; this binary only reads
+//! the command line or the REPL input, evaluates it through the library and prints the results
+//! or diagnostics.
 
 use std::env;
+use std::io::{self, IsTerminal};
 use std::process;
 
-mod evaluator;
-mod lexer;
-mod parser;
-fn main() {
-    let args: Vec<String> = env::args().collect();
+use calcrs::{
+    execute_value, parse_with, tokenize_with, CalcError, ComplexFormat, Diagnostic, Environment,
+    NumericMode,
+};
 
-    if args.len() != 2 {
-        eprintln!("Usage: calcrs <expression>");
-        process::exit(1);
-    }
+#[cfg(feature = "repl")]
+mod repl;
 
-    let expression = &args[1];
-
-    let tokens = lexer::tokenize(expression);
-    let ast = match parser::parse(&tokens) {
-        Ok(ast) => ast,
+/// Executes every statement of `source` in `env`, printing each result as it is computed.
+///
+/// Syntax errors are all reported before anything is executed; execution stops at the first
+/// evaluation error. Returns whether `source` ran without errors.
+fn run(source: &str, env: &mut Environment) -> bool {
+    let tokens = match tokenize_with(source, env.operators()) {
+        Ok(tokens) => tokens,
         Err(err) => {
-            eprintln!("Parsing error: {}", err);
-            process::exit(1);
+            report(source, &[err]);
+            return false;
         }
     };
 
-    let result = match evaluator::evaluate(ast) {
-        Ok(val) => val,
-        Err(err) => match err {
-            evaluator::EvaluationError::DivisionByZero => {
-                eprintln!("Evaluation error: Division by zero");
+    let output = parse_with(&tokens, env.operators(), env.registry());
+    if !output.errors.is_empty() {
+        report(source, &output.errors);
+        return false;
+    }
+
+    for ast in &output.statements {
+        match execute_value(ast, env) {
+            Ok(Some(result)) => {
+                println!("{}", result.format(env.complex_format(), env.angle_mode()))
+            }
+            Ok(None) => {}
+            Err(err) => {
+                report(source, &[err]);
+                return false;
+            }
+        }
+    }
+    true
+}
+
+fn report(source: &str, errors: &[CalcError]) {
+    let color = io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none();
+    for err in errors {
+        eprint!("{}", Diagnostic::from(err).render(source, color));
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: calcrs [--rational | --precision <digits>] [--polar] [expression]");
+    process::exit(1);
+}
+
+fn main() {
+    let mut environment = Environment::new();
+    let mut args = env::args().skip(1).peekable();
+    while let Some(option) = args.next_if(|arg| arg.starts_with("--")) {
+        match option.as_str() {
+            "--rational" => environment.set_numeric_mode(NumericMode::Rational),
+            "--precision" => {
+                let precision = args
+                    .next()
+                    .and_then(|digits| digits.parse().ok())
+                    .filter(|&digits| digits > 0)
+                    .unwrap_or_else(|| usage());
+                environment.set_numeric_mode(NumericMode::Decimal { precision });
+            }
+            "--polar" => environment.set_complex_format(ComplexFormat::Polar),
+            _ => usage(),
+        }
+    }
+
+    match args.collect::<Vec<String>>().as_slice() {
+        [expression] => {
+            if !run(expression, &mut environment) {
                 process::exit(1);
             }
-            evaluator::EvaluationError::InvalidOperation => {
-                eprintln!("Evaluation error: Invalid operation");
+        }
+        #[cfg(feature = "repl")]
+        [] => {
+            if let Err(err) = repl::start(environment) {
+                eprintln!("REPL error: {}", err);
                 process::exit(1);
             }
-        },
-    };
-
-    println!("{}", result);
+        }
+        _ => usage(),
+    }
 }
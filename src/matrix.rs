@@ -0,0 +1,542 @@
+//! # Matrix Module
+//!
+//! This module provides the matrices of real numbers computed by the evaluator and the linear
+//! algebra on them.
+//!
+//! ## Matrices
+//!
+//! A `Matrix` stores its elements row by row, as values that are floats, rationals or decimals
+//! (see the `value` module), and computes with them like the operators do, so a matrix of
+//! rationals stays exact. Matrices are written `[1, 2; 3, 4]`, with `,`
+//! between the elements of a row and `;` between rows, so `[1, 2, 3]` is a row vector and
+//! `[1; 2; 3]` a column vector. Operations that need operands of compatible shapes, such as
+//! `product` or `zip_with`, return a `MatrixError` otherwise, with the `ShapeRequirement` that
+//! the operand did not meet: an exact shape, a number of rows or a square shape.
+//!
+//! ## Linear Algebra
+//!
+//! - `det`: The determinant of a square matrix.
+//! - `inverse`: The inverse of a square matrix, if it is not singular.
+//! - `solve`: The solution `x` of `A x = b` for a square, non-singular `A`, as `A \ b`.
+//! - `rank`: The number of linearly independent rows.
+//! - `transpose`: The matrix with its rows and columns swapped.
+//! - `pow`: The matrix raised to an integer power.
+//!
+//! The determinant, the inverse and the solutions are computed by fraction-free (Bareiss)
+//! elimination, which is exact on rationals and on floats that are small integers: the
+//! determinant of `[1, 2; 3, 4]` is exactly `-2` and its inverse exactly
+//! `[-2, 1; 1.5, -0.5]`. A pivot that is zero up to the rounding errors of the floats or
+//! decimals makes the matrix singular; a matrix of rationals is singular only if a pivot is
+//! exactly zero.
+
+use std::fmt;
+
+use crate::value::Value;
+
+/// The number of rows and columns of a matrix.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Shape {
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl Shape {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        Shape { rows, cols }
+    }
+
+    pub fn is_square(self) -> bool {
+        self.rows == self.cols
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.rows, self.cols)
+    }
+}
+
+/// The shape required of a matrix operand.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ShapeRequirement {
+    Exact(Shape),
+    /// A number of rows, with any number of columns, like the right operand of a product.
+    Rows(usize),
+    Square,
+}
+
+impl ShapeRequirement {
+    pub fn accepts(self, shape: Shape) -> bool {
+        match self {
+            ShapeRequirement::Exact(expected) => shape == expected,
+            ShapeRequirement::Rows(rows) => shape.rows == rows,
+            ShapeRequirement::Square => shape.is_square(),
+        }
+    }
+}
+
+impl From<Shape> for ShapeRequirement {
+    fn from(shape: Shape) -> Self {
+        ShapeRequirement::Exact(shape)
+    }
+}
+
+impl fmt::Display for ShapeRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShapeRequirement::Exact(shape) => write!(f, "a matrix of shape `{}`", shape),
+            ShapeRequirement::Rows(1) => write!(f, "a matrix with 1 row"),
+            ShapeRequirement::Rows(rows) => write!(f, "a matrix with {} rows", rows),
+            ShapeRequirement::Square => write!(f, "a square matrix"),
+        }
+    }
+}
+
+/// Why a matrix operation failed.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MatrixError {
+    /// An operand of shape `found` where one meeting `expected` was needed.
+    Shape {
+        expected: ShapeRequirement,
+        found: Shape,
+    },
+    /// The inverse of a singular matrix was needed.
+    Singular,
+    /// A list of numbers with no elements was given where one element at least was needed.
+    Empty,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Matrix {
+    shape: Shape,
+    elements: Vec<Value>,
+}
+
+impl Matrix {
+    /// A matrix of the given shape with `elements` listed row by row, which must be real
+    /// numbers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of elements does not match the shape.
+    pub fn new(shape: Shape, elements: Vec<Value>) -> Self {
+        assert_eq!(shape.rows * shape.cols, elements.len());
+        Matrix { shape, elements }
+    }
+
+    /// The identity matrix of floats.
+    pub fn identity(size: usize) -> Self {
+        Matrix::identity_of(size, &Value::Float(1.0))
+    }
+
+    /// The identity matrix whose elements are of the same kind as `one`.
+    fn identity_of(size: usize, one: &Value) -> Self {
+        let elements = (0..size * size)
+            .map(|index| one.integer(i64::from(index % (size + 1) == 0)))
+            .collect();
+        Matrix::new(Shape::new(size, size), elements)
+    }
+
+    pub fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    /// The elements, row by row.
+    pub fn elements(&self) -> &[Value] {
+        &self.elements
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> &Value {
+        &self.elements[row * self.shape.cols + col]
+    }
+
+    pub fn map(&self, f: impl Fn(Value) -> Value) -> Matrix {
+        let elements = self.elements.iter().map(|x| f(x.clone())).collect();
+        Matrix::new(self.shape, elements)
+    }
+
+    /// Combines the elements of two matrices of the same shape pairwise.
+    pub fn zip_with(
+        &self,
+        other: &Matrix,
+        f: impl Fn(Value, Value) -> Value,
+    ) -> Result<Matrix, MatrixError> {
+        if self.shape != other.shape {
+            return Err(MatrixError::Shape {
+                expected: self.shape.into(),
+                found: other.shape,
+            });
+        }
+        let elements = self
+            .elements
+            .iter()
+            .zip(&other.elements)
+            .map(|(a, b)| f(a.clone(), b.clone()))
+            .collect();
+        Ok(Matrix::new(self.shape, elements))
+    }
+
+    /// The matrix product, which needs as many rows in `other` as there are columns here.
+    pub fn product(&self, other: &Matrix) -> Result<Matrix, MatrixError> {
+        let (rows, inner, cols) = (self.shape.rows, self.shape.cols, other.shape.cols);
+        if other.shape.rows != inner {
+            return Err(MatrixError::Shape {
+                expected: ShapeRequirement::Rows(inner),
+                found: other.shape,
+            });
+        }
+        let mut elements = Vec::with_capacity(rows * cols);
+        for i in 0..rows {
+            for j in 0..cols {
+                let element = (0..inner)
+                    .map(|k| self.get(i, k).clone() * other.get(k, j).clone())
+                    .reduce(|sum, x| sum + x);
+                elements.push(element.unwrap_or(Value::Float(0.0)));
+            }
+        }
+        Ok(Matrix::new(Shape::new(rows, cols), elements))
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        let Shape { rows, cols } = self.shape;
+        let elements = (0..cols)
+            .flat_map(|j| (0..rows).map(move |i| (i, j)))
+            .map(|(i, j)| self.get(i, j).clone())
+            .collect();
+        Matrix::new(Shape::new(cols, rows), elements)
+    }
+
+    pub fn det(&self) -> Result<Value, MatrixError> {
+        self.check_square()?;
+        let size = self.shape.rows;
+        if size == 0 {
+            return Ok(Value::Float(1.0));
+        }
+        let mut rows = self.rows();
+        let mut negative = false;
+        let mut previous = None;
+        for k in 0..size {
+            let pivot = pivot_row(&rows, k);
+            if rows[pivot][k].is_zero() {
+                return Ok(rows[pivot][k].integer(0));
+            }
+            if pivot != k {
+                rows.swap(pivot, k);
+                negative = !negative;
+            }
+            for i in k + 1..size {
+                for j in k + 1..size {
+                    rows[i][j] = eliminate(&rows, k, i, j, previous.as_ref());
+                }
+            }
+            previous = Some(rows[k][k].clone());
+        }
+        let det = rows[size - 1][size - 1].clone();
+        Ok(if negative { -det } else { det })
+    }
+
+    pub fn inverse(&self) -> Result<Matrix, MatrixError> {
+        self.check_square()?;
+        self.solve(&Matrix::identity_of(self.shape.rows, &self.one()))
+    }
+
+    /// The solution `x` of `self * x = b`, for a square matrix and as many rows in `b`.
+    pub fn solve(&self, b: &Matrix) -> Result<Matrix, MatrixError> {
+        self.check_square()?;
+        let size = self.shape.rows;
+        if b.shape.rows != size {
+            return Err(MatrixError::Shape {
+                expected: ShapeRequirement::Rows(size),
+                found: b.shape,
+            });
+        }
+
+        // Fraction-free Gauss-Jordan elimination of `[self | b]` leaves the determinant on
+        // the diagonal and the determinant times the solution on the right.
+        let width = size + b.shape.cols;
+        let mut rows: Vec<Vec<Value>> = self
+            .rows()
+            .into_iter()
+            .zip(b.rows())
+            .map(|(mut row, right)| {
+                row.extend(right);
+                row
+            })
+            .collect();
+        let (scale, epsilon) = (self.scale(), self.epsilon());
+        let mut previous = None;
+        for k in 0..size {
+            let pivot = pivot_row(&rows, k);
+            let tolerance = epsilon * size as f64 * scale.powi(k as i32 + 1);
+            if negligible(&rows[pivot][k], tolerance) {
+                return Err(MatrixError::Singular);
+            }
+            rows.swap(pivot, k);
+            for i in (0..size).filter(|&i| i != k) {
+                for j in (0..width).filter(|&j| j != k) {
+                    rows[i][j] = eliminate(&rows, k, i, j, previous.as_ref());
+                }
+                rows[i][k] = rows[i][k].integer(0);
+            }
+            previous = Some(rows[k][k].clone());
+        }
+
+        let elements = rows
+            .iter()
+            .flat_map(|row| row[size..].iter())
+            .map(|x| match &previous {
+                Some(det) => x.clone() / det.clone(),
+                None => x.clone(),
+            })
+            .collect();
+        Ok(Matrix::new(b.shape, elements))
+    }
+
+    /// The number of linearly independent rows, ignoring rounding errors.
+    pub fn rank(&self) -> usize {
+        let Shape { rows: height, cols } = self.shape;
+        let mut rows = self.rows();
+        let tolerance = self.epsilon() * height.max(cols) as f64 * self.scale();
+        let mut rank = 0;
+        for col in 0..cols {
+            if rank == height {
+                break;
+            }
+            let pivot = (rank..height)
+                .max_by(|&a, &b| magnitude(&rows[a][col]).total_cmp(&magnitude(&rows[b][col])))
+                .unwrap_or(rank);
+            if negligible(&rows[pivot][col], tolerance) {
+                continue;
+            }
+            rows.swap(pivot, rank);
+            let (top, bottom) = rows.split_at_mut(rank + 1);
+            let pivot = &top[rank];
+            for row in bottom {
+                let factor = row[col].clone() / pivot[col].clone();
+                for (x, p) in row[col..].iter_mut().zip(&pivot[col..]) {
+                    *x = x.clone() - factor.clone() * p.clone();
+                }
+            }
+            rank += 1;
+        }
+        rank
+    }
+
+    /// The matrix raised to `exponent`, by repeated squaring; a negative exponent raises the
+    /// inverse.
+    pub fn pow(&self, exponent: i32) -> Result<Matrix, MatrixError> {
+        self.check_square()?;
+        let mut base = if exponent < 0 {
+            self.inverse()?
+        } else {
+            self.clone()
+        };
+        let mut result = Matrix::identity_of(self.shape.rows, &self.one());
+        let mut exponent = exponent.unsigned_abs();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.product(&base)?;
+            }
+            base = base.product(&base)?;
+            exponent >>= 1;
+        }
+        Ok(result)
+    }
+
+    fn check_square(&self) -> Result<(), MatrixError> {
+        if self.shape.is_square() {
+            Ok(())
+        } else {
+            Err(MatrixError::Shape {
+                expected: ShapeRequirement::Square,
+                found: self.shape,
+            })
+        }
+    }
+
+    fn rows(&self) -> Vec<Vec<Value>> {
+        if self.shape.cols == 0 {
+            return vec![Vec::new(); self.shape.rows];
+        }
+        self.elements
+            .chunks(self.shape.cols)
+            .map(<[Value]>::to_vec)
+            .collect()
+    }
+
+    /// The number one, of the same kind as the first element.
+    fn one(&self) -> Value {
+        self.elements
+            .first()
+            .map_or(Value::Float(1.0), |first| first.integer(1))
+    }
+
+    /// The largest magnitude of an element.
+    fn scale(&self) -> f64 {
+        self.elements
+            .iter()
+            .fold(0f64, |max, x| max.max(magnitude(x)))
+    }
+
+    /// The largest relative rounding error of an operation on the elements, which is zero if
+    /// they are all rationals.
+    fn epsilon(&self) -> f64 {
+        self.elements
+            .iter()
+            .fold(0f64, |max, x| max.max(x.epsilon()))
+    }
+}
+
+/// The element `(i, j)` after eliminating column `k` with the pivot row `k`; dividing by the
+/// previous pivot keeps the elements small, and is exact for integers (Bareiss).
+fn eliminate(rows: &[Vec<Value>], k: usize, i: usize, j: usize, previous: Option<&Value>) -> Value {
+    let x = rows[k][k].clone() * rows[i][j].clone() - rows[i][k].clone() * rows[k][j].clone();
+    match previous {
+        Some(previous) => x / previous.clone(),
+        None => x,
+    }
+}
+
+fn magnitude(x: &Value) -> f64 {
+    x.to_f64().abs()
+}
+
+/// Whether a pivot is zero up to the rounding errors bounded by `tolerance`.
+fn negligible(x: &Value, tolerance: f64) -> bool {
+    x.is_zero() || (tolerance > 0.0 && magnitude(x) <= tolerance)
+}
+
+/// The row from `k` down whose element in column `k` is the largest in magnitude.
+fn pivot_row(rows: &[Vec<Value>], k: usize) -> usize {
+    (k..rows.len())
+        .max_by(|&a, &b| magnitude(&rows[a][k]).total_cmp(&magnitude(&rows[b][k])))
+        .unwrap_or(k)
+}
+
+impl fmt::Display for Matrix {
+    /// Writes the matrix like a literal, as in `[1, 2; 3, 4]`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        for row in 0..self.shape.rows {
+            if row > 0 {
+                write!(f, "; ")?;
+            }
+            for col in 0..self.shape.cols {
+                if col > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", self.get(row, col))?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::NumericMode;
+
+    fn matrix(rows: usize, cols: usize, elements: &[f64]) -> Matrix {
+        let elements = elements.iter().map(|&x| Value::Float(x)).collect();
+        Matrix::new(Shape::new(rows, cols), elements)
+    }
+
+    fn rational(rows: usize, cols: usize, elements: &[f64]) -> Matrix {
+        let elements = elements
+            .iter()
+            .map(|&x| Value::literal(x, NumericMode::Rational))
+            .collect();
+        Matrix::new(Shape::new(rows, cols), elements)
+    }
+
+    #[test]
+    fn test_product_and_transpose() {
+        let a = matrix(2, 3, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let b = matrix(3, 1, &[1.0, 0.0, -1.0]);
+        assert_eq!(a.product(&b), Ok(matrix(2, 1, &[-2.0, -2.0])));
+        assert_eq!(
+            b.product(&a),
+            Err(MatrixError::Shape {
+                expected: ShapeRequirement::Rows(1),
+                found: Shape::new(2, 3),
+            })
+        );
+        assert_eq!(a.transpose().to_string(), "[1, 4; 2, 5; 3, 6]");
+        assert_eq!(
+            a.zip_with(&a, |x, y| x * y).unwrap().to_string(),
+            "[1, 4, 9; 16, 25, 36]"
+        );
+    }
+
+    #[test]
+    fn test_det_and_inverse() {
+        let a = matrix(2, 2, &[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(a.det(), Ok(Value::Float(-2.0)));
+        assert_eq!(a.inverse(), Ok(matrix(2, 2, &[-2.0, 1.0, 1.5, -0.5])));
+        let b = matrix(3, 3, &[2.0, -1.0, 0.0, -1.0, 2.0, -1.0, 0.0, -1.0, 2.0]);
+        assert_eq!(b.det(), Ok(Value::Float(4.0)));
+        assert_eq!(b.inverse().unwrap().product(&b), Ok(Matrix::identity(3)));
+
+        let singular = matrix(2, 2, &[0.1, 0.2, 0.3, 0.6]);
+        assert_eq!(singular.inverse(), Err(MatrixError::Singular));
+        assert_eq!(
+            matrix(2, 2, &[1.0, 2.0, 2.0, 4.0]).det(),
+            Ok(Value::Float(0.0))
+        );
+        assert!(matches!(
+            matrix(1, 2, &[1.0, 2.0]).det(),
+            Err(MatrixError::Shape { .. })
+        ));
+    }
+
+    #[test]
+    fn test_solve_rank_and_pow() {
+        let a = matrix(3, 3, &[2.0, 1.0, -1.0, -3.0, -1.0, 2.0, -2.0, 1.0, 2.0]);
+        let b = matrix(3, 1, &[8.0, -11.0, -3.0]);
+        assert_eq!(a.solve(&b), Ok(matrix(3, 1, &[2.0, 3.0, -1.0])));
+
+        assert_eq!(a.rank(), 3);
+        assert_eq!(matrix(2, 3, &[1.0, 2.0, 3.0, 2.0, 4.0, 6.0]).rank(), 1);
+        assert_eq!(matrix(2, 2, &[0.0; 4]).rank(), 0);
+
+        let fibonacci = matrix(2, 2, &[1.0, 1.0, 1.0, 0.0]);
+        assert_eq!(fibonacci.pow(10).unwrap().get(0, 1), &Value::Float(55.0));
+        assert_eq!(fibonacci.pow(0), Ok(Matrix::identity(2)));
+        assert_eq!(
+            fibonacci.pow(-1).unwrap().product(&fibonacci),
+            Ok(Matrix::identity(2))
+        );
+    }
+
+    #[test]
+    fn test_rational_matrices() {
+        let a = rational(2, 2, &[2.0, 1.0, 1.0, 2.0]);
+        assert_eq!(a.det().unwrap().to_string(), "3");
+        assert_eq!(a.inverse().unwrap().to_string(), "[2/3, -1/3; -1/3, 2/3]");
+        assert_eq!(
+            a.solve(&rational(2, 1, &[0.1, 0.2])).unwrap().to_string(),
+            "[0; 0.1]"
+        );
+        assert_eq!(
+            a.pow(-2).unwrap().product(&a.pow(2).unwrap()),
+            Ok(rational(2, 2, &[1.0, 0.0, 0.0, 1.0]))
+        );
+
+        // Only an exactly zero pivot makes a matrix of rationals singular.
+        let (one, tiny) = (
+            Value::literal(1.0, NumericMode::Rational),
+            Value::literal(1e-30, NumericMode::Rational),
+        );
+        let nearly = Matrix::new(
+            Shape::new(2, 2),
+            vec![one.clone(), one.clone(), one.clone(), one + tiny],
+        );
+        assert_eq!(nearly.rank(), 2);
+        assert!(nearly.inverse().is_ok());
+        assert_eq!(
+            rational(2, 2, &[0.1, 0.2, 0.3, 0.6]).inverse(),
+            Err(MatrixError::Singular)
+        );
+    }
+}
@@ -0,0 +1,368 @@
+//! # Operator Module
+//!
+//! This module provides the declarative operator table that drives the parser.
+//!
+//! ## Operators
+//!
+//! An `Operator` ties a token to its `Fixity` (prefix, infix or postfix), its precedence and,
+//! for infix operators, its `Associativity`. Higher precedences bind tighter. The standard
+//! table contains:
+//!
+//! | Token                       | Fixity  | Precedence | Associativity |
+//! |-----------------------------|---------|------------|---------------|
+//! | `or`                        | infix   | 2          | left          |
+//! | `and`                       | infix   | 3          | left          |
+//! | `not`                       | prefix  | 4          |               |
+//! | `<` `<=` `>` `>=` `==` `!=` | infix   | 6          | left          |
+//! | `+` `-`                     | infix   | 10         | left          |
+//! | `*` `/` `%` `.*` `./` `\`   | infix   | 20         | left          |
+//! | `-`                         | prefix  | 30         |               |
+//! | `^` `**` `.^`               | infix   | 40         | right         |
+//! | `!`                         | postfix | 50         |               |
+//!
+//! The conditional operator `c ? a : b` and the conversions with `in` are not in the table:
+//! the parser handles them at the loosest level.
+//!
+//! ## Custom Operators
+//!
+//! Embedders can register operators written with any symbol, as `TokenKind::Operator`.
+//! A custom operator is evaluated by calling the function it names with its operands,
+//! so it must be paired with `Operator::calling`. `OperatorTable::add` returns an
+//! `OperatorError` for an operator without a function, or whose symbol is empty, contains
+//! whitespace or starts with a digit, which the lexer could not read:
+//!
+//! ```
+//! use calcrs::operator::{Associativity, Operator};
+//! use calcrs::{eval_str_with, Environment, TokenKind};
+//!
+//! let mut env = Environment::new();
+//! env.operators_mut().add(
+//!     Operator::infix(TokenKind::Operator(String::from("<>")), 15, Associativity::Left)
+//!         .calling("avg"),
+//! )
+//! .unwrap();
+//! eval_str_with("avg(a, b) = (a + b) / 2", &mut env)?;
+//! assert_eq!(eval_str_with("2 * 3 <> 5", &mut env)?, Some(5.5));
+//! # Ok::<(), calcrs::CalcError>(())
+//! ```
+//!
+//! The lexer reads the symbols of custom operators before the built-in ones, so `tokenize_with`
+//! and `parse_with` must be given the same table.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::lexer::TokenKind;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Fixity {
+    Prefix,
+    Infix(Associativity),
+    Postfix,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Operator {
+    pub token: TokenKind,
+    pub fixity: Fixity,
+    pub precedence: u8,
+    /// The function called with the operands; `None` for built-in operators.
+    pub function: Option<String>,
+}
+
+impl Operator {
+    pub fn prefix(token: TokenKind, precedence: u8) -> Self {
+        Operator {
+            token,
+            fixity: Fixity::Prefix,
+            precedence,
+            function: None,
+        }
+    }
+
+    pub fn infix(token: TokenKind, precedence: u8, associativity: Associativity) -> Self {
+        Operator {
+            token,
+            fixity: Fixity::Infix(associativity),
+            precedence,
+            function: None,
+        }
+    }
+
+    pub fn postfix(token: TokenKind, precedence: u8) -> Self {
+        Operator {
+            token,
+            fixity: Fixity::Postfix,
+            precedence,
+            function: None,
+        }
+    }
+
+    /// Evaluates the operator by calling `function` with its operands.
+    pub fn calling(mut self, function: &str) -> Self {
+        self.function = Some(String::from(function));
+        self
+    }
+}
+
+/// Why `OperatorTable::add` rejected a custom operator.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OperatorError {
+    /// The symbol is empty, so it would match at every position of the input.
+    EmptySymbol,
+    /// The symbol contains whitespace, which separates tokens.
+    Whitespace,
+    /// The symbol starts with a digit, which starts a number.
+    LeadingDigit,
+    /// The operator has no function to call with its operands.
+    NoFunction,
+}
+
+impl fmt::Display for OperatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OperatorError::EmptySymbol => write!(f, "the operator symbol is empty"),
+            OperatorError::Whitespace => write!(f, "the operator symbol contains whitespace"),
+            OperatorError::LeadingDigit => write!(f, "the operator symbol starts with a digit"),
+            OperatorError::NoFunction => write!(f, "the operator does not call a function"),
+        }
+    }
+}
+
+impl Error for OperatorError {}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct OperatorTable {
+    operators: Vec<Operator>,
+}
+
+impl Default for OperatorTable {
+    fn default() -> Self {
+        OperatorTable::standard()
+    }
+}
+
+impl OperatorTable {
+    /// A table without any operator.
+    pub fn empty() -> Self {
+        OperatorTable {
+            operators: Vec::new(),
+        }
+    }
+
+    /// The table of built-in operators.
+    pub fn standard() -> Self {
+        let mut table = OperatorTable::empty();
+        table.insert(Operator::infix(TokenKind::Or, 2, Associativity::Left));
+        table.insert(Operator::infix(TokenKind::And, 3, Associativity::Left));
+        table.insert(Operator::prefix(TokenKind::Not, 4));
+        for comparison in [
+            TokenKind::Less,
+            TokenKind::LessEqual,
+            TokenKind::Greater,
+            TokenKind::GreaterEqual,
+            TokenKind::Equal,
+            TokenKind::NotEqual,
+        ] {
+            table.insert(Operator::infix(comparison, 6, Associativity::Left));
+        }
+        table.insert(Operator::infix(TokenKind::Plus, 10, Associativity::Left));
+        table.insert(Operator::infix(TokenKind::Minus, 10, Associativity::Left));
+        table.insert(Operator::infix(
+            TokenKind::Multiply,
+            20,
+            Associativity::Left,
+        ));
+        table.insert(Operator::infix(TokenKind::Divide, 20, Associativity::Left));
+        table.insert(Operator::infix(TokenKind::Percent, 20, Associativity::Left));
+        table.insert(Operator::infix(
+            TokenKind::DotMultiply,
+            20,
+            Associativity::Left,
+        ));
+        table.insert(Operator::infix(
+            TokenKind::DotDivide,
+            20,
+            Associativity::Left,
+        ));
+        table.insert(Operator::infix(
+            TokenKind::Backslash,
+            20,
+            Associativity::Left,
+        ));
+        table.insert(Operator::prefix(TokenKind::Minus, 30));
+        table.insert(Operator::infix(TokenKind::Caret, 40, Associativity::Right));
+        table.insert(Operator::infix(
+            TokenKind::DotCaret,
+            40,
+            Associativity::Right,
+        ));
+        table.insert(Operator::postfix(TokenKind::Bang, 50));
+        table
+    }
+
+    /// Adds an operator, replacing any operator with the same token in the same position.
+    /// A custom operator is rejected, leaving the table unchanged, if the lexer could not read
+    /// its symbol or if it has no function to call, as it could not be evaluated.
+    ///
+    /// A token can have both a prefix meaning and an infix or postfix meaning, like `-`.
+    pub fn add(&mut self, operator: Operator) -> Result<(), OperatorError> {
+        if let TokenKind::Operator(symbol) = &operator.token {
+            if symbol.is_empty() {
+                return Err(OperatorError::EmptySymbol);
+            }
+            if symbol.chars().any(char::is_whitespace) {
+                return Err(OperatorError::Whitespace);
+            }
+            if symbol.starts_with(|c: char| c.is_ascii_digit()) {
+                return Err(OperatorError::LeadingDigit);
+            }
+            if operator.function.is_none() {
+                return Err(OperatorError::NoFunction);
+            }
+        }
+        self.insert(operator);
+        Ok(())
+    }
+
+    fn insert(&mut self, operator: Operator) {
+        let is_prefix = operator.fixity == Fixity::Prefix;
+        self.operators.retain(|existing| {
+            existing.token != operator.token || (existing.fixity == Fixity::Prefix) != is_prefix
+        });
+        self.operators.push(operator);
+    }
+
+    /// The operator that `token` starts when it appears before an operand.
+    pub fn prefix(&self, token: &TokenKind) -> Option<&Operator> {
+        self.operators
+            .iter()
+            .find(|operator| &operator.token == token && operator.fixity == Fixity::Prefix)
+    }
+
+    /// The infix or postfix operator that `token` denotes when it follows an operand.
+    pub fn infix_or_postfix(&self, token: &TokenKind) -> Option<&Operator> {
+        self.operators
+            .iter()
+            .find(|operator| &operator.token == token && operator.fixity != Fixity::Prefix)
+    }
+
+    /// The symbols of the custom operators, longest first.
+    pub fn symbols(&self) -> Vec<&str> {
+        let mut symbols: Vec<&str> = self
+            .operators
+            .iter()
+            .filter_map(|operator| match &operator.token {
+                TokenKind::Operator(symbol) => Some(symbol.as_str()),
+                _ => None,
+            })
+            .collect();
+        symbols.sort_by_key(|symbol| std::cmp::Reverse(symbol.len()));
+        symbols
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standard_table_lookup() {
+        let table = OperatorTable::standard();
+        assert_eq!(table.prefix(&TokenKind::Minus).unwrap().precedence, 30);
+        assert_eq!(
+            table.infix_or_postfix(&TokenKind::Minus).unwrap().fixity,
+            Fixity::Infix(Associativity::Left)
+        );
+        assert_eq!(
+            table.infix_or_postfix(&TokenKind::Caret).unwrap().fixity,
+            Fixity::Infix(Associativity::Right)
+        );
+        assert!(table.prefix(&TokenKind::Plus).is_none());
+        assert_eq!(table.prefix(&TokenKind::Not).unwrap().precedence, 4);
+        assert!(
+            table.infix_or_postfix(&TokenKind::And).unwrap().precedence
+                > table.infix_or_postfix(&TokenKind::Or).unwrap().precedence
+        );
+        assert!(table.infix_or_postfix(&TokenKind::LeftParen).is_none());
+    }
+
+    #[test]
+    fn test_add_replaces_same_position() {
+        let mut table = OperatorTable::standard();
+        table
+            .add(Operator::infix(TokenKind::Minus, 25, Associativity::Right))
+            .unwrap();
+        assert_eq!(
+            table
+                .infix_or_postfix(&TokenKind::Minus)
+                .unwrap()
+                .precedence,
+            25
+        );
+        assert_eq!(table.prefix(&TokenKind::Minus).unwrap().precedence, 30);
+    }
+
+    #[test]
+    fn test_add_rejects_custom_operator_without_function() {
+        let mut table = OperatorTable::standard();
+        let symbol = TokenKind::Operator(String::from("<>"));
+        assert_eq!(
+            table.add(Operator::infix(symbol.clone(), 15, Associativity::Left)),
+            Err(OperatorError::NoFunction)
+        );
+        assert!(table.infix_or_postfix(&symbol).is_none());
+        let operator = Operator::infix(symbol.clone(), 15, Associativity::Left).calling("avg");
+        assert_eq!(table.add(operator), Ok(()));
+        assert!(table.infix_or_postfix(&symbol).is_some());
+    }
+
+    #[test]
+    fn test_add_rejects_unreadable_symbols() {
+        let mut table = OperatorTable::standard();
+        for (symbol, error) in [
+            ("", OperatorError::EmptySymbol),
+            (" ", OperatorError::Whitespace),
+            ("<\t>", OperatorError::Whitespace),
+            ("2x", OperatorError::LeadingDigit),
+        ] {
+            let token = TokenKind::Operator(String::from(symbol));
+            let operator = Operator::infix(token, 20, Associativity::Left).calling("max");
+            assert_eq!(table.add(operator), Err(error));
+        }
+        assert!(table.symbols().is_empty());
+        assert_eq!(table, OperatorTable::standard());
+    }
+
+    #[test]
+    fn test_symbols_longest_first() {
+        let mut table = OperatorTable::standard();
+        table
+            .add(
+                Operator::infix(
+                    TokenKind::Operator(String::from("<")),
+                    5,
+                    Associativity::Left,
+                )
+                .calling("less"),
+            )
+            .unwrap();
+        table
+            .add(
+                Operator::infix(
+                    TokenKind::Operator(String::from("<=>")),
+                    5,
+                    Associativity::Left,
+                )
+                .calling("compare"),
+            )
+            .unwrap();
+        assert_eq!(table.symbols(), vec!["<=>", "<"]);
+    }
+}
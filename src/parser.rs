@@ -4,178 +4,783 @@
 //!
 //! ## Expression
 //!
-//! The `Expression` enum represents the different types of expressions in the AST:
+//! Each `Expression` node pairs an `ExpressionKind` with the `Span` of input it was parsed from.
+//! The `ExpressionKind` enum represents the different types of expressions in the AST:
 //!
 //! - `Number`: Represents a numeric value.
+//! - `Angle`: Represents an angle literal with an explicit unit, such as `30deg`.
+//! - `Imaginary`: Represents an imaginary number literal, such as `4i`.
+//! - `Boolean`: Represents a boolean literal, `true` or `false`.
 //! - `BinaryOp`: Represents a binary operation with a left operand, an operator, and a right operand.
 //! - `UnaryOp`: Represents a unary operation with an operator and an operand.
+//! - `Variable`: Represents a reference to a variable by name.
+//! - `Assign`: Represents the assignment of a value to a variable, such as `x = 3 * 2`.
+//! - `Call`: Represents a call to a function by name with a list of arguments, such as `f(3, 4)` or `sin(0.5)`.
+//! - `FunctionDef`: Represents the definition of a function with named parameters, such as `f(x, y) = x * y`.
+//! - `Unit`: Represents a physical unit written after a number, such as the `km` of `3 km`.
+//! - `Convert`: Represents the conversion of a quantity to a unit, such as `3 km in mi`.
+//! - `Matrix`: Represents a matrix literal as its rows of elements, such as `[1, 2; 3, 4]`.
+//! - `Conditional`: Represents a condition and the two expressions it chooses between, such
+//!   as `x > 0 ? x : -x`.
+//! - `Error`: Stands in for a sub-expression that could not be parsed.
+//!
+//! ## Errors
+//!
+//! Parsing fails with a `ParseError` (see the `error` module) carrying the `Span` of the token
+//! where the problem was found:
+//!
+//! - `UnexpectedToken`: Indicates a token that cannot appear at this position.
+//! - `UnexpectedEndOfInput`: Indicates that the input ended in the middle of an expression.
+//! - `ExpectedToken`: Indicates that a specific token, such as a parenthesis, was missing.
+//! - `TrailingToken`: Indicates a token left over after a complete expression.
+//! - `InvalidAssignmentTarget`: Indicates that the left-hand side of `=` is neither a variable
+//!   nor a function signature with distinct parameter names.
+//! - `UnknownUnit`: Indicates a name that is not a unit in the target of a conversion.
+//!
+//! ## Statements
+//!
+//! An input may contain several expressions separated by `;`. Every expression must be
+//! followed by a `;` or by the end of the input, so `1 + 2 3` is rejected instead of
+//! silently evaluating `1 + 2`. A statement may assign its value to a variable with
+//! `name = expression`; assignments can be chained, as in `x = y = 0`. A statement of the
+//! form `name(param, ...) = expression` defines a function instead, as long as the signature
+//! is written that way: `(f(x)) = x^2`, or a custom operator standing for a call, is not one.
+//!
+//! ## Precedence
+//!
+//! Operators are parsed by precedence climbing over an `OperatorTable` (see the `operator`
+//! module), which gives the fixity, precedence and associativity of every operator. From
+//! loosest to tightest, the standard operators are: `or`; `and`; `not`; the comparisons `<`,
+//! `<=`, `>`, `>=`, `==` and `!=`; `+` and `-`; `*`, `/`, `%`, the element-wise `.*` and `./`,
+//! and the left division `\`; unary `-`; the exponent operator `^` (also written `**`) and the
+//! element-wise `.^`; and the factorial `!`. All binary operators are left-associative except
+//! `^` and `.^`, which are right-associative. This follows mathematical convention: `-2^2` is
+//! `-4` and `2^3^2` is `512`. An operator that names a function is parsed into a `Call` of
+//! that function with its operands.
+//!
+//! ## Conditionals
+//!
+//! An expression followed by `?`, an expression, `:` and another expression is a conditional,
+//! as in `x > 0 ? x : -x`. It is parsed at the loosest level like a conversion, and is
+//! right-associative, so `a ? b : c ? d : e` chooses between `b` and `c ? d : e`.
+//!
+//! ## Function Calls
+//!
+//! A name followed by `(` is a call, whether the function is user-defined or registered in a
+//! `FunctionRegistry` (see the `function` module). The parser consults the registry for the
+//! other names: a registered function must be called, so `sin 3` is reported as a missing
+//! `(`, while any other name is a variable.
+//!
+//! ## Matrices
+//!
+//! A matrix literal lists its elements between `[` and `]`, separating the elements of a row
+//! with `,` and the rows with `;`, as in `[1, 2; 3, 4]`; `[]` is the empty matrix. Each element
+//! is a full expression. Inside brackets, `;` separates rows rather than statements.
+//!
+//! ## Units
+//!
+//! A number followed by the names of units (see the `unit` module), each with an optional
+//! integer exponent, is multiplied by them: `9.81 m s^-2` is `9.81 * m * s^-2`, and units
+//! after a `/` that follows them divide it, so `9.81 m/s^2` is `9.81 * m / s^2`. Elsewhere a
+//! unit name is an ordinary variable, so `3 km / 20 min` divides two quantities while `t * 2`
+//! is an undefined variable rather than two tonnes.
+//!
+//! An expression followed by `in` or `to` and a unit is a conversion, as in `3 km in mi` or
+//! `72 km/h to m/s`. The target is a product or quotient of units with integer exponents,
+//! optionally grouped with parentheses, such as `kg m^2/s^2` or `W/(m^2 K)`. A conversion is
+//! parsed at the loosest level, so `1 km + 2 mi in ft` converts the sum.
+//!
+//! ## Error Recovery
+//!
+//! The parser does not stop at the first syntax error. It records the error, replaces the
+//! broken sub-expression with an `Error` node and skips ahead to the next `)`, `]`, `,` or `;`
+//! (ignoring the ones nested inside parentheses or brackets) before carrying on. A single pass
+//! therefore reports every syntax error of the input together with a partial AST.
 //!
 //! ## Functions
 //!
-//! - `parse(tokens: &Vec<Token>) -> Result<Expression, String>`: Parses the tokens into an AST.
-//! - `parse_expression(iter: &mut std::iter::Peekable<std::slice::Iter<Token>>) -> Result<Expression, String>`: Parses an expression.
-//! - `parse_term(iter: &mut std::iter::Peekable<std::slice::Iter<Token>>) -> Result<Expression, String>`: Parses a term.
-//! - `parse_factor(iter: &mut std::iter::Peekable<std::slice::Iter<Token>>) -> Result<Expression, String>`: Parses a factor.
-//! - `parse_unary_op(iter: &mut std::iter::Peekable<std::slice::Iter<Token>>, op: Token) -> Result<Expression, String>`: Parses a unary operation.
-//! - `parse_binary_op(iter: &mut std::iter::Peekable<std::slice::Iter<Token>>, op: Token) -> Result<Expression, String>`: Parses a binary operation.
+//! - `parse(tokens: &[Token]) -> Result<Expression, CalcError>`: Parses a single expression into an AST.
+//! - `parse_program(tokens: &[Token]) -> Result<Vec<Expression>, CalcError>`: Parses `;`-separated expressions into ASTs.
+//! - `parse_with_recovery(tokens: &[Token]) -> ParseOutput`: Parses `;`-separated expressions, collecting every syntax error.
+//! - `parse_with(tokens: &[Token], operators: &OperatorTable, functions: &FunctionRegistry) -> ParseOutput`: Like
+//!   `parse_with_recovery`, with the given operators and functions instead of the standard ones.
+//!
+//! Internally, a `Parser` walks the tokens with the following methods:
+//!
+//! - `parse_statement() -> Expression`: Parses an expression followed by `;` or the end of input.
+//! - `parse_assignment() -> Expression`: Parses an expression, or an assignment or function definition when it is followed by `=`.
+//! - `parse_expression(min_precedence: u8) -> Expression`: Parses an expression whose operators have at least `min_precedence`.
+//! - `parse_factor() -> Expression`: Parses a factor.
+//! - `parse_call(name: String, start: usize) -> Expression`: Parses the arguments of a function call.
+//! - `parse_matrix(start: usize) -> Expression`: Parses the rows of a matrix literal.
+//! - `parse_conditional(condition: Expression) -> Expression`: Parses the branches of a conditional.
+//! - `parse_units(number: Expression) -> Expression`: Parses the units written after a number.
+//! - `parse_unit() -> Expression`: Parses the target unit of a conversion.
+
+use crate::error::{CalcError, ParseError};
+use crate::evaluator::AngleMode;
+use crate::function::FunctionRegistry;
+use crate::lexer::{Literal, Span, Token, TokenKind};
+use crate::operator::{Associativity, Fixity, Operator, OperatorTable};
+use crate::unit;
+
+/// A node of the AST.
+///
+/// Spans are not part of equality: two expressions are equal when they have
+/// the same structure, wherever they appear in the input.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ExpressionKind {
+    Number(Literal),
+    Angle(Literal, AngleMode),
+    Imaginary(f64),
+    Boolean(bool),
+    BinaryOp(Box<Expression>, TokenKind, Box<Expression>),
+    UnaryOp(TokenKind, Box<Expression>),
+    Variable(String),
+    Assign(String, Box<Expression>),
+    Call(String, Vec<Expression>),
+    FunctionDef(String, Vec<String>, Box<Expression>),
+    Unit(String),
+    Convert(Box<Expression>, Box<Expression>),
+    Matrix(Vec<Vec<Expression>>),
+    Conditional(Box<Expression>, Box<Expression>, Box<Expression>),
+    Error,
+}
 
-use crate::lexer::Token;
+impl Expression {
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Expression { kind, span }
+    }
+}
 
+impl PartialEq for Expression {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+/// The statements parsed from an input and the syntax errors found along the way.
+///
+/// When `errors` is not empty, `statements` is a partial AST in which the broken
+/// sub-expressions are replaced by `ExpressionKind::Error` nodes.
 #[derive(Debug, PartialEq)]
-pub enum Expression {
-    Number(f64),
-    BinaryOp(Box<Expression>, Token, Box<Expression>),
-    UnaryOp(Token, Box<Expression>),
+pub struct ParseOutput {
+    pub statements: Vec<Expression>,
+    pub errors: Vec<CalcError>,
 }
 
-pub fn parse(tokens: &Vec<Token>) -> Result<Expression, String> {
-    let mut iter = tokens.iter().peekable();
-    parse_expression(&mut iter)
+pub fn parse(tokens: &[Token]) -> Result<Expression, CalcError> {
+    parse_statement_with(
+        tokens,
+        &OperatorTable::standard(),
+        &FunctionRegistry::standard(),
+    )
 }
 
-fn parse_expression(
-    iter: &mut std::iter::Peekable<std::slice::Iter<Token>>,
-) -> Result<Expression, String> {
-    let mut left = parse_term(iter)?;
-
-    while let Some(&token) = iter.peek() {
-        match token {
-            Token::Plus | Token::Minus => {
-                iter.next();
-                let right = parse_term(iter)?;
-                left = Expression::BinaryOp(Box::new(left), token.clone(), Box::new(right));
-            }
-            _ => break,
+/// Like `parse`, with the given operators and functions instead of the standard ones.
+pub(crate) fn parse_statement_with(
+    tokens: &[Token],
+    operators: &OperatorTable,
+    functions: &FunctionRegistry,
+) -> Result<Expression, CalcError> {
+    let mut parser = Parser::new(tokens, operators, functions);
+    let expr = parser.parse_statement();
+    if let Some(token) = parser.peek() {
+        parser.error(ParseError::TrailingToken {
+            found: token.kind.clone(),
+            span: token.span,
+        });
+    }
+    match parser.errors.into_iter().next() {
+        Some(err) => Err(err.into()),
+        None => Ok(expr),
+    }
+}
+
+pub fn parse_program(tokens: &[Token]) -> Result<Vec<Expression>, CalcError> {
+    let output = parse_with_recovery(tokens);
+    match output.errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(output.statements),
+    }
+}
+
+pub fn parse_with_recovery(tokens: &[Token]) -> ParseOutput {
+    parse_with(
+        tokens,
+        &OperatorTable::standard(),
+        &FunctionRegistry::standard(),
+    )
+}
+
+pub fn parse_with(
+    tokens: &[Token],
+    operators: &OperatorTable,
+    functions: &FunctionRegistry,
+) -> ParseOutput {
+    let mut parser = Parser::new(tokens, operators, functions);
+    let mut statements = Vec::new();
+    while parser.peek().is_some() {
+        if parser.eat(TokenKind::Semicolon) {
+            continue;
         }
+        statements.push(parser.parse_statement());
+    }
+    ParseOutput {
+        statements,
+        errors: parser.errors.into_iter().map(CalcError::from).collect(),
     }
+}
 
-    Ok(left)
+struct Parser<'a> {
+    tokens: &'a [Token],
+    operators: &'a OperatorTable,
+    functions: &'a FunctionRegistry,
+    pos: usize,
+    errors: Vec<ParseError>,
 }
 
-fn parse_term(
-    iter: &mut std::iter::Peekable<std::slice::Iter<Token>>,
-) -> Result<Expression, String> {
-    let mut left = parse_factor(iter)?;
-
-    while let Some(&token) = iter.peek() {
-        match token {
-            Token::Multiply | Token::Divide => {
-                iter.next();
-                let right = parse_factor(iter)?;
-                left = Expression::BinaryOp(Box::new(left), token.clone(), Box::new(right));
+impl<'a> Parser<'a> {
+    fn new(
+        tokens: &'a [Token],
+        operators: &'a OperatorTable,
+        functions: &'a FunctionRegistry,
+    ) -> Self {
+        Parser {
+            tokens,
+            operators,
+            functions,
+            pos: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.pos);
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// The span of the next token, or an empty span at the end of the input.
+    fn next_span(&self) -> Span {
+        match self.peek() {
+            Some(token) => token.span,
+            None => {
+                let end = self.tokens.last().map_or(0, |token| token.span.end);
+                Span::new(end, end)
             }
-            _ => break,
         }
     }
 
-    Ok(left)
-}
+    /// Consumes the next token if it is `expected`.
+    fn eat(&mut self, expected: TokenKind) -> bool {
+        match self.peek() {
+            Some(token) if token.kind == expected => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
 
-fn parse_factor(
-    iter: &mut std::iter::Peekable<std::slice::Iter<Token>>,
-) -> Result<Expression, String> {
-    match iter.next() {
-        Some(Token::Number(val)) => Ok(Expression::Number(*val)),
-        Some(Token::Minus) => {
-            let expr = parse_factor(iter)?;
-            Ok(Expression::UnaryOp(Token::Minus, Box::new(expr)))
+    /// The name of the identifier at position `pos`, if it is followed by `(`.
+    fn call_name(&self, pos: usize) -> Option<&'a str> {
+        match (&self.tokens.get(pos)?.kind, &self.tokens.get(pos + 1)?.kind) {
+            (TokenKind::Identifier(name), TokenKind::LeftParen) => Some(name),
+            _ => None,
         }
+    }
 
-        Some(Token::LeftParen) => {
-            let expr = parse_expression(iter)?;
-            match iter.next() {
-                Some(Token::RightParen) => Ok(expr),
-                _ => Err(String::from("Expected right parenthese")),
+    /// Records a syntax error, unless one was already reported at the same position.
+    fn error(&mut self, err: ParseError) {
+        let start = err.span().start;
+        if self.errors.last().map(|last| last.span().start) != Some(start) {
+            self.errors.push(err);
+        }
+    }
+
+    /// Skips tokens up to the next `)`, `]`, `,` or `;` that is not nested inside parentheses
+    /// or brackets, leaving it unconsumed.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.peek() {
+            match token.kind {
+                TokenKind::LeftParen | TokenKind::LeftBracket => depth += 1,
+                TokenKind::RightParen | TokenKind::RightBracket if depth > 0 => depth -= 1,
+                TokenKind::Comma | TokenKind::Semicolon if depth > 0 => {}
+                TokenKind::RightParen
+                | TokenKind::RightBracket
+                | TokenKind::Comma
+                | TokenKind::Semicolon => break,
+                _ => {}
             }
+            self.pos += 1;
         }
-        Some(Token::Cos) => parse_unary_op(iter, Token::Cos),
-        Some(Token::Acos) => parse_unary_op(iter, Token::Acos),
-        Some(Token::Sin) => parse_unary_op(iter, Token::Sin),
-        Some(Token::Asin) => parse_unary_op(iter, Token::Asin),
-        Some(Token::Tan) => parse_unary_op(iter, Token::Tan),
-        Some(Token::Atan) => parse_unary_op(iter, Token::Atan),
-        Some(Token::Sqrt) => parse_unary_op(iter, Token::Sqrt),
-        Some(Token::Pow) => parse_binary_op(iter, Token::Pow),
-        Some(_) => Err(String::from("Unexpected token")),
-        None => Err(String::from("Unexpected end of input")),
     }
-}
 
-fn parse_unary_op(
-    iter: &mut std::iter::Peekable<std::slice::Iter<Token>>,
-    op: Token,
-) -> Result<Expression, String> {
-    match op {
-        Token::Minus => {
-            let expr = parse_factor(iter)?;
-            Ok(Expression::UnaryOp(op, Box::new(expr)))
+    /// Consumes the next token if it is `expected`, returning the end of its span.
+    ///
+    /// Otherwise the error is recorded and the parser synchronizes; if that lands on
+    /// `expected`, it is consumed as well.
+    fn expect(&mut self, expected: TokenKind) -> Option<usize> {
+        if let Some(token) = self.peek() {
+            if token.kind == expected {
+                self.pos += 1;
+                return Some(token.span.end);
+            }
         }
-        _ => match iter.next() {
-            Some(Token::LeftParen) => {
-                let expr = parse_expression(iter)?;
-                match iter.next() {
-                    Some(Token::RightParen) => Ok(Expression::UnaryOp(op, Box::new(expr))),
-                    _ => Err(String::from("Expected right parenthese")),
+
+        let span = self.next_span();
+        self.error(ParseError::ExpectedToken {
+            expected: expected.clone(),
+            span,
+        });
+        self.synchronize();
+        match self.peek() {
+            Some(token) if token.kind == expected => {
+                self.pos += 1;
+                Some(token.span.end)
+            }
+            _ => None,
+        }
+    }
+
+    /// The end of the last consumed token, used to close spans after a recovery.
+    fn last_end(&self) -> usize {
+        self.pos
+            .checked_sub(1)
+            .map_or(0, |index| self.tokens[index].span.end)
+    }
+
+    fn parse_statement(&mut self) -> Expression {
+        let expr = self.parse_assignment();
+        match self.peek() {
+            None => {}
+            Some(token) if token.kind == TokenKind::Semicolon => self.pos += 1,
+            Some(token) => {
+                self.error(ParseError::TrailingToken {
+                    found: token.kind.clone(),
+                    span: token.span,
+                });
+                while let Some(token) = self.next() {
+                    if token.kind == TokenKind::Semicolon {
+                        break;
+                    }
                 }
             }
-            _ => Err(String::from("Expected left parenthese")),
-        },
+        }
+        expr
     }
-}
 
-fn parse_binary_op(
-    iter: &mut std::iter::Peekable<std::slice::Iter<Token>>,
-    op: Token,
-) -> Result<Expression, String> {
-    match iter.next() {
-        Some(Token::LeftParen) => {
-            let left = parse_expression(iter)?;
-            match iter.next() {
-                Some(Token::Comma) => {
-                    let right = parse_expression(iter)?;
-                    match iter.next() {
-                        Some(Token::RightParen) => {
-                            Ok(Expression::BinaryOp(Box::new(left), op, Box::new(right)))
-                        }
-                        _ => Err(String::from("Expected right parenthese")),
+    fn parse_assignment(&mut self) -> Expression {
+        let start = self.pos;
+        let target = self.parse_expression(0);
+        if !self.eat(TokenKind::Assign) {
+            return target;
+        }
+
+        let value = self.parse_assignment();
+        let span = Span::new(target.span.start, value.span.end);
+        let written = self.call_name(start);
+        match target.kind {
+            ExpressionKind::Variable(name) => {
+                Expression::new(ExpressionKind::Assign(name, Box::new(value)), span)
+            }
+            // Only a call written as `f(...)` defines a function, not one that a custom
+            // operator stands for or one in parentheses.
+            ExpressionKind::Call(name, args) if written == Some(name.as_str()) => {
+                match parameters(&args) {
+                    Some(params) => Expression::new(
+                        ExpressionKind::FunctionDef(name, params, Box::new(value)),
+                        span,
+                    ),
+                    None => {
+                        self.error(ParseError::InvalidAssignmentTarget { span: target.span });
+                        Expression::new(ExpressionKind::Error, span)
                     }
                 }
-                _ => Err(String::from("Expected comma")),
+            }
+            ExpressionKind::Error => Expression::new(ExpressionKind::Error, span),
+            _ => {
+                self.error(ParseError::InvalidAssignmentTarget { span: target.span });
+                Expression::new(ExpressionKind::Error, span)
             }
         }
-        _ => Err(String::from("Expected left parenthese")),
+    }
+
+    /// Parses an expression whose operators have at least `min_precedence`.
+    ///
+    /// Operators are looked up in the operator table: a prefix operator parses its operand
+    /// at its own precedence, an infix operator parses its right operand one level above its
+    /// precedence when it is left-associative (at its precedence when right-associative), and
+    /// a postfix operator applies to the expression parsed so far.
+    fn parse_expression(&mut self, min_precedence: u8) -> Expression {
+        let prefix = self
+            .peek()
+            .and_then(|token| Some((token, self.operators.prefix(&token.kind)?)));
+        let mut left = match prefix {
+            Some((token, operator)) => {
+                self.next();
+                let operand = self.parse_expression(operator.precedence);
+                let span = Span::new(token.span.start, operand.span.end);
+                unary_operation(operator, operand, span)
+            }
+            None => self.parse_factor(),
+        };
+
+        while let Some(token) = self.peek() {
+            if min_precedence == 0 && is_conversion(&token.kind) {
+                self.next();
+                let target = self.parse_unit();
+                let span = Span::new(left.span.start, target.span.end);
+                let kind = ExpressionKind::Convert(Box::new(left), Box::new(target));
+                left = Expression::new(kind, span);
+                continue;
+            }
+            if min_precedence == 0 && token.kind == TokenKind::Question {
+                self.next();
+                left = self.parse_conditional(left);
+                continue;
+            }
+            let operator = match self.operators.infix_or_postfix(&token.kind) {
+                Some(operator) if operator.precedence >= min_precedence => operator,
+                _ => break,
+            };
+            self.next();
+            left = match operator.fixity {
+                Fixity::Infix(associativity) => {
+                    let min_precedence = match associativity {
+                        Associativity::Left => operator.precedence.saturating_add(1),
+                        Associativity::Right => operator.precedence,
+                    };
+                    let right = self.parse_expression(min_precedence);
+                    binary_operation(operator, left, right)
+                }
+                Fixity::Postfix | Fixity::Prefix => {
+                    let span = Span::new(left.span.start, token.span.end);
+                    unary_operation(operator, left, span)
+                }
+            };
+        }
+
+        left
+    }
+
+    fn parse_factor(&mut self) -> Expression {
+        let span = self.next_span();
+        let token = match self.peek() {
+            Some(token) => token,
+            None => {
+                self.error(ParseError::UnexpectedEndOfInput { span });
+                return Expression::new(ExpressionKind::Error, span);
+            }
+        };
+
+        match &token.kind {
+            TokenKind::Number(val) => {
+                self.next();
+                self.parse_units(Expression::new(ExpressionKind::Number(val.clone()), span))
+            }
+            TokenKind::Angle(val, unit) => {
+                self.next();
+                Expression::new(ExpressionKind::Angle(val.clone(), *unit), span)
+            }
+            TokenKind::Imaginary(val) => {
+                self.next();
+                Expression::new(ExpressionKind::Imaginary(*val), span)
+            }
+            TokenKind::Boolean(val) => {
+                self.next();
+                Expression::new(ExpressionKind::Boolean(*val), span)
+            }
+            TokenKind::Identifier(name) => {
+                self.next();
+                if self.eat(TokenKind::LeftParen) {
+                    self.parse_call(name.clone(), span.start)
+                } else if self.functions.contains(name) && unit::lookup(name).is_none() {
+                    self.expect(TokenKind::LeftParen);
+                    let span = Span::new(span.start, self.last_end());
+                    Expression::new(ExpressionKind::Error, span)
+                } else {
+                    Expression::new(ExpressionKind::Variable(name.clone()), span)
+                }
+            }
+            TokenKind::LeftParen => {
+                self.next();
+                let expr = self.parse_expression(0);
+                let end = self
+                    .expect(TokenKind::RightParen)
+                    .unwrap_or_else(|| self.last_end());
+                Expression::new(expr.kind, Span::new(span.start, end))
+            }
+            TokenKind::LeftBracket => {
+                self.next();
+                self.parse_matrix(span.start)
+            }
+            _ => self.unexpected(),
+        }
+    }
+
+    /// Parses the two branches of a conditional after the `?` that follows `condition`.
+    fn parse_conditional(&mut self, condition: Expression) -> Expression {
+        let then = self.parse_expression(0);
+        self.expect(TokenKind::Colon);
+        let otherwise = self.parse_expression(0);
+        let span = Span::new(condition.span.start, otherwise.span.end);
+        let kind =
+            ExpressionKind::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise));
+        Expression::new(kind, span)
+    }
+
+    /// Records the next token as unexpected and skips ahead, returning an `Error` node.
+    fn unexpected(&mut self) -> Expression {
+        let span = self.next_span();
+        match self.peek() {
+            Some(token) => self.error(ParseError::UnexpectedToken {
+                found: token.kind.clone(),
+                span,
+            }),
+            None => self.error(ParseError::UnexpectedEndOfInput { span }),
+        }
+        self.synchronize();
+        let end = self.last_end().max(span.start);
+        Expression::new(ExpressionKind::Error, Span::new(span.start, end))
+    }
+
+    /// Multiplies `number` by the units that follow it, as in `3 km` or `9.81 m s^-2`, and
+    /// divides it by the units that follow a `/` after them, as in `9.81 m/s^2`.
+    ///
+    /// A name followed by `(` or `=` is left alone, since it starts a call or an assignment.
+    fn parse_units(&mut self, number: Expression) -> Expression {
+        let mut expr = number;
+        let mut has_units = false;
+        loop {
+            let kind = |offset: usize| self.tokens.get(self.pos + offset).map(|token| &token.kind);
+            let divide = has_units && kind(0) == Some(&TokenKind::Divide);
+            let offset = usize::from(divide);
+            let is_unit = match (kind(offset), kind(offset + 1)) {
+                (_, Some(TokenKind::LeftParen | TokenKind::Assign)) => false,
+                (Some(TokenKind::Identifier(name)), _) => unit::lookup(name).is_some(),
+                _ => false,
+            };
+            if !is_unit {
+                break expr;
+            }
+            let operator = if divide {
+                self.next();
+                TokenKind::Divide
+            } else {
+                TokenKind::Multiply
+            };
+            let unit = self.parse_unit_power();
+            expr = binary_op(expr, operator, unit);
+            has_units = true;
+        }
+    }
+
+    /// Parses the target unit of a conversion, after `in` or `to`.
+    ///
+    /// Units are multiplied with `*` or by writing them side by side, and divided with `/`.
+    fn parse_unit(&mut self) -> Expression {
+        let mut left = self.parse_unit_power();
+        loop {
+            let operator = match self.peek().map(|token| &token.kind) {
+                Some(TokenKind::Multiply) | Some(TokenKind::Divide) => {
+                    self.next().map(|token| token.kind.clone())
+                }
+                Some(kind @ TokenKind::Identifier(_)) if !is_conversion(kind) => {
+                    Some(TokenKind::Multiply)
+                }
+                Some(TokenKind::LeftParen) => Some(TokenKind::Multiply),
+                _ => None,
+            };
+            let Some(operator) = operator else {
+                break left;
+            };
+            let right = self.parse_unit_power();
+            left = binary_op(left, operator, right);
+        }
+    }
+
+    /// Parses a unit or a parenthesized unit, with an optional integer exponent.
+    fn parse_unit_power(&mut self) -> Expression {
+        let span = self.next_span();
+        let base = match self.peek().map(|token| &token.kind) {
+            Some(TokenKind::Identifier(name)) => {
+                self.next();
+                if unit::lookup(name).is_some() {
+                    Expression::new(ExpressionKind::Unit(name.clone()), span)
+                } else {
+                    self.error(ParseError::UnknownUnit {
+                        name: name.clone(),
+                        span,
+                    });
+                    Expression::new(ExpressionKind::Error, span)
+                }
+            }
+            Some(TokenKind::LeftParen) => {
+                self.next();
+                let unit = self.parse_unit();
+                let end = self
+                    .expect(TokenKind::RightParen)
+                    .unwrap_or_else(|| self.last_end());
+                Expression::new(unit.kind, Span::new(span.start, end))
+            }
+            _ => return self.unexpected(),
+        };
+        if !self.eat(TokenKind::Caret) {
+            return base;
+        }
+
+        let start = self.next_span().start;
+        let negative = self.eat(TokenKind::Minus);
+        let exponent = match self.peek().map(|token| &token.kind) {
+            Some(TokenKind::Number(val)) => {
+                let val = if negative { -val.clone() } else { val.clone() };
+                self.next();
+                let span = Span::new(start, self.last_end());
+                Expression::new(ExpressionKind::Number(val), span)
+            }
+            _ => self.unexpected(),
+        };
+        binary_op(base, TokenKind::Caret, exponent)
+    }
+
+    /// Parses the rows of a matrix literal, after its `[`.
+    fn parse_matrix(&mut self, start: usize) -> Expression {
+        let mut rows = Vec::new();
+        let end = if self.peek().map(|token| &token.kind) == Some(&TokenKind::RightBracket) {
+            self.next().map(|token| token.span.end)
+        } else {
+            let mut row = Vec::new();
+            loop {
+                row.push(self.parse_expression(0));
+                if self.eat(TokenKind::Semicolon) {
+                    rows.push(std::mem::take(&mut row));
+                } else if !self.eat(TokenKind::Comma) {
+                    rows.push(row);
+                    break self.expect(TokenKind::RightBracket);
+                }
+            }
+        };
+        let end = end.unwrap_or_else(|| self.last_end());
+        Expression::new(ExpressionKind::Matrix(rows), Span::new(start, end))
+    }
+
+    /// Parses the comma-separated arguments of a call, after its `(`.
+    fn parse_call(&mut self, name: String, start: usize) -> Expression {
+        let mut args = Vec::new();
+        let end = if self.peek().map(|token| &token.kind) == Some(&TokenKind::RightParen) {
+            self.next().map(|token| token.span.end)
+        } else {
+            loop {
+                args.push(self.parse_expression(0));
+                if !self.eat(TokenKind::Comma) {
+                    break self.expect(TokenKind::RightParen);
+                }
+            }
+        };
+        let end = end.unwrap_or_else(|| self.last_end());
+        Expression::new(ExpressionKind::Call(name, args), Span::new(start, end))
     }
 }
 
+/// Whether the token introduces a conversion to a unit, as in `3 km in mi`.
+fn is_conversion(kind: &TokenKind) -> bool {
+    matches!(kind, TokenKind::Identifier(name) if name == "in" || name == "to")
+}
+
+/// The parameter names of a definition such as `f(x, y) = ...`, if every argument
+/// of the call on the left of `=` is a distinct variable name.
+fn parameters(args: &[Expression]) -> Option<Vec<String>> {
+    let mut params: Vec<String> = Vec::new();
+    for arg in args {
+        match &arg.kind {
+            ExpressionKind::Variable(name) if !params.contains(name) => params.push(name.clone()),
+            _ => return None,
+        }
+    }
+    Some(params)
+}
+
+/// Applies a prefix or postfix operator to its operand.
+fn unary_operation(operator: &Operator, operand: Expression, span: Span) -> Expression {
+    let kind = match &operator.function {
+        Some(name) => ExpressionKind::Call(name.clone(), vec![operand]),
+        None => ExpressionKind::UnaryOp(operator.token.clone(), Box::new(operand)),
+    };
+    Expression::new(kind, span)
+}
+
+/// Applies an infix operator to its operands.
+fn binary_operation(operator: &Operator, left: Expression, right: Expression) -> Expression {
+    match &operator.function {
+        Some(name) => {
+            let span = Span::new(left.span.start, right.span.end);
+            Expression::new(ExpressionKind::Call(name.clone(), vec![left, right]), span)
+        }
+        None => binary_op(left, operator.token.clone(), right),
+    }
+}
+
+fn binary_op(left: Expression, op: TokenKind, right: Expression) -> Expression {
+    let span = Span::new(left.span.start, right.span.end);
+    Expression::new(
+        ExpressionKind::BinaryOp(Box::new(left), op, Box::new(right)),
+        span,
+    )
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::function::Arity;
     use crate::lexer::tokenize;
 
     use super::*;
 
+    fn number(val: f64) -> Expression {
+        Expression::new(ExpressionKind::Number(val.into()), Span::default())
+    }
+
+    fn unary(op: TokenKind, expr: Expression) -> Expression {
+        Expression::new(ExpressionKind::UnaryOp(op, Box::new(expr)), Span::default())
+    }
+
+    fn call(name: &str, args: Vec<Expression>) -> Expression {
+        Expression::new(
+            ExpressionKind::Call(String::from(name), args),
+            Span::default(),
+        )
+    }
+
     #[test]
     fn test_parse_expression() {
         let input = "1 + (2 * 3 - 10.5) / sin(0.5)";
-        let tokens = tokenize(input);
-        let expected_ast = Expression::BinaryOp(
-            Box::new(Expression::Number(1.0)),
-            Token::Plus,
-            Box::new(Expression::BinaryOp(
-                Box::new(Expression::BinaryOp(
-                    Box::new(Expression::BinaryOp(
-                        Box::new(Expression::Number(2.0)),
-                        Token::Multiply,
-                        Box::new(Expression::Number(3.0)),
-                    )),
-                    Token::Minus,
-                    Box::new(Expression::Number(10.5)),
-                )),
-                Token::Divide,
-                Box::new(Expression::UnaryOp(
-                    Token::Sin,
-                    Box::new(Expression::Number(0.5)),
-                )),
-            )),
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = binary_op(
+            number(1.0),
+            TokenKind::Plus,
+            binary_op(
+                binary_op(
+                    binary_op(number(2.0), TokenKind::Multiply, number(3.0)),
+                    TokenKind::Minus,
+                    number(10.5),
+                ),
+                TokenKind::Divide,
+                call("sin", vec![number(0.5)]),
+            ),
         );
 
         let ast = parse(&tokens).unwrap();
@@ -185,8 +790,8 @@ mod tests {
     #[test]
     fn test_parse_unary_op() {
         let input = "sin(0.5)";
-        let tokens = tokenize(input);
-        let expected_ast = Expression::UnaryOp(Token::Sin, Box::new(Expression::Number(0.5)));
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = call("sin", vec![number(0.5)]);
 
         let ast = parse(&tokens).unwrap();
         assert_eq!(ast, expected_ast);
@@ -195,12 +800,8 @@ mod tests {
     #[test]
     fn test_parse_binary_op_pow() {
         let input = "pow(2, 3)";
-        let tokens = tokenize(input);
-        let expected_ast = Expression::BinaryOp(
-            Box::new(Expression::Number(2.0)),
-            Token::Pow,
-            Box::new(Expression::Number(3.0)),
-        );
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = call("pow", vec![number(2.0), number(3.0)]);
 
         let ast = parse(&tokens).unwrap();
         assert_eq!(ast, expected_ast);
@@ -209,31 +810,19 @@ mod tests {
     #[test]
     fn test_parse_nested_expressions() {
         let input = "((1 + 2) * 3 - (4 / 2)) * (5 + 6)";
-        let tokens = tokenize(input);
-        let expected_ast = Expression::BinaryOp(
-            Box::new(Expression::BinaryOp(
-                Box::new(Expression::BinaryOp(
-                    Box::new(Expression::BinaryOp(
-                        Box::new(Expression::Number(1.0)),
-                        Token::Plus,
-                        Box::new(Expression::Number(2.0)),
-                    )),
-                    Token::Multiply,
-                    Box::new(Expression::Number(3.0)),
-                )),
-                Token::Minus,
-                Box::new(Expression::BinaryOp(
-                    Box::new(Expression::Number(4.0)),
-                    Token::Divide,
-                    Box::new(Expression::Number(2.0)),
-                )),
-            )),
-            Token::Multiply,
-            Box::new(Expression::BinaryOp(
-                Box::new(Expression::Number(5.0)),
-                Token::Plus,
-                Box::new(Expression::Number(6.0)),
-            )),
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = binary_op(
+            binary_op(
+                binary_op(
+                    binary_op(number(1.0), TokenKind::Plus, number(2.0)),
+                    TokenKind::Multiply,
+                    number(3.0),
+                ),
+                TokenKind::Minus,
+                binary_op(number(4.0), TokenKind::Divide, number(2.0)),
+            ),
+            TokenKind::Multiply,
+            binary_op(number(5.0), TokenKind::Plus, number(6.0)),
         );
 
         let ast = parse(&tokens).unwrap();
@@ -243,16 +832,10 @@ mod tests {
     #[test]
     fn test_parse_multiple_unary_ops() {
         let input = "sin(cos(tan(0.5)))";
-        let tokens = tokenize(input);
-        let expected_ast = Expression::UnaryOp(
-            Token::Sin,
-            Box::new(Expression::UnaryOp(
-                Token::Cos,
-                Box::new(Expression::UnaryOp(
-                    Token::Tan,
-                    Box::new(Expression::Number(0.5)),
-                )),
-            )),
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = call(
+            "sin",
+            vec![call("cos", vec![call("tan", vec![number(0.5)])])],
         );
 
         let ast = parse(&tokens).unwrap();
@@ -262,19 +845,13 @@ mod tests {
     #[test]
     fn test_parse_binary_op_pow_with_expressions() {
         let input = "pow(2 + 3, 4 - 1)";
-        let tokens = tokenize(input);
-        let expected_ast = Expression::BinaryOp(
-            Box::new(Expression::BinaryOp(
-                Box::new(Expression::Number(2.0)),
-                Token::Plus,
-                Box::new(Expression::Number(3.0)),
-            )),
-            Token::Pow,
-            Box::new(Expression::BinaryOp(
-                Box::new(Expression::Number(4.0)),
-                Token::Minus,
-                Box::new(Expression::Number(1.0)),
-            )),
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = call(
+            "pow",
+            vec![
+                binary_op(number(2.0), TokenKind::Plus, number(3.0)),
+                binary_op(number(4.0), TokenKind::Minus, number(1.0)),
+            ],
         );
 
         let ast = parse(&tokens).unwrap();
@@ -284,7 +861,7 @@ mod tests {
     #[test]
     fn test_parse_invalid_expression() {
         let input = "1 + (2 * 3";
-        let tokens = tokenize(input);
+        let tokens = tokenize(input).unwrap();
         let result = parse(&tokens);
         assert!(result.is_err());
     }
@@ -292,8 +869,585 @@ mod tests {
     #[test]
     fn test_parse_empty_input() {
         let input = "";
-        let tokens = tokenize(input);
+        let tokens = tokenize(input).unwrap();
         let result = parse(&tokens);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_parse_spans() {
+        let input = "1 + (2 * 3) / sin(0.5)";
+        let tokens = tokenize(input).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(ast.span, Span::new(0, 22));
+
+        let ExpressionKind::BinaryOp(left, _, right) = ast.kind else {
+            panic!("expected a binary operation");
+        };
+        assert_eq!(left.span, Span::new(0, 1));
+        assert_eq!(right.span, Span::new(4, 22));
+
+        let ExpressionKind::BinaryOp(paren, _, sin) = right.kind else {
+            panic!("expected a binary operation");
+        };
+        assert_eq!(paren.span, Span::new(4, 11));
+        assert_eq!(sin.span, Span::new(14, 22));
+    }
+
+    #[test]
+    fn test_parse_error_span() {
+        let input = "1 + (2 * 3";
+        let tokens = tokenize(input).unwrap();
+        let result = parse(&tokens);
+        assert_eq!(
+            result,
+            Err(CalcError::Parse(ParseError::ExpectedToken {
+                expected: TokenKind::RightParen,
+                span: Span::new(10, 10),
+            }))
+        );
+
+        let input = "1 + * 2";
+        let tokens = tokenize(input).unwrap();
+        let result = parse(&tokens);
+        assert_eq!(
+            result,
+            Err(CalcError::Parse(ParseError::UnexpectedToken {
+                found: TokenKind::Multiply,
+                span: Span::new(4, 5),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_parse_trailing_tokens() {
+        let input = "1 + 2 3";
+        let tokens = tokenize(input).unwrap();
+        let result = parse(&tokens);
+        assert_eq!(
+            result,
+            Err(CalcError::Parse(ParseError::TrailingToken {
+                found: TokenKind::Number(3.0.into()),
+                span: Span::new(6, 7),
+            }))
+        );
+
+        let input = "2 ) 4";
+        let tokens = tokenize(input).unwrap();
+        let result = parse(&tokens);
+        assert_eq!(
+            result,
+            Err(CalcError::Parse(ParseError::TrailingToken {
+                found: TokenKind::RightParen,
+                span: Span::new(2, 3),
+            }))
+        );
+
+        let input = "1; 2";
+        let tokens = tokenize(input).unwrap();
+        let result = parse(&tokens);
+        assert_eq!(
+            result,
+            Err(CalcError::Parse(ParseError::TrailingToken {
+                found: TokenKind::Number(2.0.into()),
+                span: Span::new(3, 4),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_parse_program() {
+        let input = "1 + 2; sin(0.5);; 3;";
+        let tokens = tokenize(input).unwrap();
+        let expected_asts = vec![
+            binary_op(number(1.0), TokenKind::Plus, number(2.0)),
+            call("sin", vec![number(0.5)]),
+            number(3.0),
+        ];
+
+        let asts = parse_program(&tokens).unwrap();
+        assert_eq!(asts, expected_asts);
+
+        let input = "1 + 2; 3 4";
+        let tokens = tokenize(input).unwrap();
+        let result = parse_program(&tokens);
+        assert_eq!(
+            result,
+            Err(CalcError::Parse(ParseError::TrailingToken {
+                found: TokenKind::Number(4.0.into()),
+                span: Span::new(9, 10),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_parse_with_recovery_reports_all_errors() {
+        let input = "1 + * 2; sin 3; pow(2 3) + (4";
+        let tokens = tokenize(input).unwrap();
+        let output = parse_with_recovery(&tokens);
+        assert_eq!(
+            output.errors,
+            vec![
+                CalcError::Parse(ParseError::UnexpectedToken {
+                    found: TokenKind::Multiply,
+                    span: Span::new(4, 5),
+                }),
+                CalcError::Parse(ParseError::ExpectedToken {
+                    expected: TokenKind::LeftParen,
+                    span: Span::new(13, 14),
+                }),
+                CalcError::Parse(ParseError::ExpectedToken {
+                    expected: TokenKind::RightParen,
+                    span: Span::new(22, 23),
+                }),
+                CalcError::Parse(ParseError::ExpectedToken {
+                    expected: TokenKind::RightParen,
+                    span: Span::new(29, 29),
+                }),
+            ]
+        );
+        assert_eq!(output.statements.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_with_recovery_partial_ast() {
+        let input = "(1 + * 3) * 2, 4";
+        let tokens = tokenize(input).unwrap();
+        let output = parse_with_recovery(&tokens);
+        let error = Expression::new(ExpressionKind::Error, Span::default());
+        assert_eq!(
+            output.statements,
+            vec![binary_op(
+                binary_op(number(1.0), TokenKind::Plus, error),
+                TokenKind::Multiply,
+                number(2.0),
+            )]
+        );
+        assert_eq!(
+            output.errors,
+            vec![
+                CalcError::Parse(ParseError::UnexpectedToken {
+                    found: TokenKind::Multiply,
+                    span: Span::new(5, 6),
+                }),
+                CalcError::Parse(ParseError::TrailingToken {
+                    found: TokenKind::Comma,
+                    span: Span::new(13, 14),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_with_recovery_valid_input() {
+        let input = "1 + 2; 3";
+        let tokens = tokenize(input).unwrap();
+        let output = parse_with_recovery(&tokens);
+        assert!(output.errors.is_empty());
+        assert_eq!(
+            output.statements,
+            vec![
+                binary_op(number(1.0), TokenKind::Plus, number(2.0)),
+                number(3.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_assignment() {
+        let input = "x = y = 2 * z";
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = Expression::new(
+            ExpressionKind::Assign(
+                String::from("x"),
+                Box::new(Expression::new(
+                    ExpressionKind::Assign(
+                        String::from("y"),
+                        Box::new(binary_op(
+                            number(2.0),
+                            TokenKind::Multiply,
+                            Expression::new(
+                                ExpressionKind::Variable(String::from("z")),
+                                Span::default(),
+                            ),
+                        )),
+                    ),
+                    Span::default(),
+                )),
+            ),
+            Span::default(),
+        );
+
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(ast, expected_ast);
+        assert_eq!(ast.span, Span::new(0, 13));
+    }
+
+    #[test]
+    fn test_parse_invalid_assignment_target() {
+        let input = "2 = 3";
+        let tokens = tokenize(input).unwrap();
+        let result = parse(&tokens);
+        assert_eq!(
+            result,
+            Err(CalcError::Parse(ParseError::InvalidAssignmentTarget {
+                span: Span::new(0, 1),
+            }))
+        );
+
+        let input = "f(x, 2) = x";
+        let tokens = tokenize(input).unwrap();
+        let result = parse(&tokens);
+        assert_eq!(
+            result,
+            Err(CalcError::Parse(ParseError::InvalidAssignmentTarget {
+                span: Span::new(0, 7),
+            }))
+        );
+
+        let input = "f(x, x) = x";
+        let tokens = tokenize(input).unwrap();
+        let result = parse(&tokens);
+        assert!(result.is_err());
+
+        let input = "(f(x)) = x^2";
+        let tokens = tokenize(input).unwrap();
+        let result = parse(&tokens);
+        assert_eq!(
+            result,
+            Err(CalcError::Parse(ParseError::InvalidAssignmentTarget {
+                span: Span::new(0, 6),
+            }))
+        );
+
+        // A custom operator calling a function does not define it.
+        let mut operators = OperatorTable::standard();
+        let symbol = TokenKind::Operator(String::from("max"));
+        operators
+            .add(Operator::infix(symbol, 5, Associativity::Left).calling("larger"))
+            .unwrap();
+        let input = "a max b = a";
+        let tokens = crate::lexer::tokenize_with(input, &operators).unwrap();
+        let output = parse_with(&tokens, &operators, &FunctionRegistry::standard());
+        assert_eq!(
+            output.errors,
+            vec![CalcError::Parse(ParseError::InvalidAssignmentTarget {
+                span: Span::new(0, 7),
+            })]
+        );
+    }
+
+    #[test]
+    fn test_parse_call_and_function_def() {
+        let variable = |name: &str| {
+            Expression::new(
+                ExpressionKind::Variable(String::from(name)),
+                Span::default(),
+            )
+        };
+
+        let input = "f(x, y) = x * y";
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = Expression::new(
+            ExpressionKind::FunctionDef(
+                String::from("f"),
+                vec![String::from("x"), String::from("y")],
+                Box::new(binary_op(variable("x"), TokenKind::Multiply, variable("y"))),
+            ),
+            Span::default(),
+        );
+        assert_eq!(parse(&tokens).unwrap(), expected_ast);
+
+        let input = "f(3, g()) + 1";
+        let tokens = tokenize(input).unwrap();
+        let f = call("f", vec![number(3.0), call("g", Vec::new())]);
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(ast, binary_op(f, TokenKind::Plus, number(1.0)));
+        assert_eq!(ast.span, Span::new(0, 13));
+    }
+
+    #[test]
+    fn test_parse_power_precedence() {
+        let input = "-2^2";
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = unary(
+            TokenKind::Minus,
+            binary_op(number(2.0), TokenKind::Caret, number(2.0)),
+        );
+        assert_eq!(parse(&tokens).unwrap(), expected_ast);
+
+        let input = "2 * 3^2";
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = binary_op(
+            number(2.0),
+            TokenKind::Multiply,
+            binary_op(number(3.0), TokenKind::Caret, number(2.0)),
+        );
+        assert_eq!(parse(&tokens).unwrap(), expected_ast);
+
+        let input = "2^-1";
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = binary_op(
+            number(2.0),
+            TokenKind::Caret,
+            unary(TokenKind::Minus, number(1.0)),
+        );
+        assert_eq!(parse(&tokens).unwrap(), expected_ast);
+    }
+
+    #[test]
+    fn test_parse_power_right_associative() {
+        let input = "2^3**2";
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = binary_op(
+            number(2.0),
+            TokenKind::Caret,
+            binary_op(number(3.0), TokenKind::Caret, number(2.0)),
+        );
+        assert_eq!(parse(&tokens).unwrap(), expected_ast);
+        assert_eq!(parse(&tokens).unwrap().span, Span::new(0, 6));
+    }
+
+    #[test]
+    fn test_parse_registered_functions() {
+        let input = "sqrt + 1; sqrt(x) = 2; margin(1, 2)";
+        let tokens = tokenize(input).unwrap();
+        let output = parse_with_recovery(&tokens);
+        assert_eq!(
+            output.errors,
+            vec![CalcError::Parse(ParseError::ExpectedToken {
+                expected: TokenKind::LeftParen,
+                span: Span::new(5, 6),
+            })]
+        );
+
+        let mut functions = FunctionRegistry::standard();
+        functions.register("margin", Arity::Fixed(2), |args| args[0] - args[1]);
+        let output = parse_with(&tokens[3..], &OperatorTable::standard(), &functions);
+        assert!(output.errors.is_empty());
+        assert_eq!(
+            output.statements[1],
+            call("margin", vec![number(1.0), number(2.0)])
+        );
+
+        let input = "margin + 1";
+        let tokens = tokenize(input).unwrap();
+        assert!(parse(&tokens).is_ok());
+        let output = parse_with(&tokens, &OperatorTable::standard(), &functions);
+        assert_eq!(output.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_modulo_and_factorial() {
+        let input = "-3! % 4 * 2";
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = binary_op(
+            binary_op(
+                unary(TokenKind::Minus, unary(TokenKind::Bang, number(3.0))),
+                TokenKind::Percent,
+                number(4.0),
+            ),
+            TokenKind::Multiply,
+            number(2.0),
+        );
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(ast, expected_ast);
+        assert_eq!(ast.span, Span::new(0, 11));
+    }
+
+    #[test]
+    fn test_parse_with_custom_operators() {
+        let mut operators = OperatorTable::standard();
+        let symbol = TokenKind::Operator(String::from("<>"));
+        operators
+            .add(Operator::infix(symbol.clone(), 15, Associativity::Right).calling("avg"))
+            .unwrap();
+        operators
+            .add(Operator::prefix(TokenKind::Plus, 30))
+            .unwrap();
+
+        let input = "1 <> +2 <> 3 * 4";
+        let tokens = crate::lexer::tokenize_with(input, &operators).unwrap();
+        let expected_ast = call(
+            "avg",
+            vec![
+                number(1.0),
+                call(
+                    "avg",
+                    vec![
+                        unary(TokenKind::Plus, number(2.0)),
+                        binary_op(number(3.0), TokenKind::Multiply, number(4.0)),
+                    ],
+                ),
+            ],
+        );
+
+        let output = parse_with(&tokens, &operators, &FunctionRegistry::standard());
+        assert!(output.errors.is_empty());
+        assert_eq!(output.statements, vec![expected_ast]);
+        assert_eq!(output.statements[0].span, Span::new(0, 16));
+    }
+
+    #[test]
+    fn test_parse_units() {
+        let unit =
+            |name: &str| Expression::new(ExpressionKind::Unit(String::from(name)), Span::default());
+
+        let input = "9.81 m s^-2";
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = binary_op(
+            binary_op(number(9.81), TokenKind::Multiply, unit("m")),
+            TokenKind::Multiply,
+            binary_op(unit("s"), TokenKind::Caret, number(-2.0)),
+        );
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(ast, expected_ast);
+        assert_eq!(ast.span, Span::new(0, 11));
+
+        let input = "2 km in W/(m^2 K)";
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = Expression::new(
+            ExpressionKind::Convert(
+                Box::new(binary_op(number(2.0), TokenKind::Multiply, unit("km"))),
+                Box::new(binary_op(
+                    unit("W"),
+                    TokenKind::Divide,
+                    binary_op(
+                        binary_op(unit("m"), TokenKind::Caret, number(2.0)),
+                        TokenKind::Multiply,
+                        unit("K"),
+                    ),
+                )),
+            ),
+            Span::default(),
+        );
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(ast, expected_ast);
+        assert_eq!(ast.span, Span::new(0, 17));
+
+        let input = "72 km/h/s";
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = binary_op(
+            binary_op(
+                binary_op(number(72.0), TokenKind::Multiply, unit("km")),
+                TokenKind::Divide,
+                unit("h"),
+            ),
+            TokenKind::Divide,
+            unit("s"),
+        );
+        assert_eq!(parse(&tokens).unwrap(), expected_ast);
+
+        // A `/` followed by a number is a division.
+        let tokens = tokenize("3 km / 20 min").unwrap();
+        let expected_ast = binary_op(
+            binary_op(number(3.0), TokenKind::Multiply, unit("km")),
+            TokenKind::Divide,
+            binary_op(number(20.0), TokenKind::Multiply, unit("min")),
+        );
+        assert_eq!(parse(&tokens).unwrap(), expected_ast);
+
+        // A name followed by `(` is a call, and one that is not a unit is left over.
+        let tokens = tokenize("2 * min(1, 2)").unwrap();
+        assert!(parse(&tokens).is_ok());
+        let tokens = tokenize("1 h / min").unwrap();
+        assert!(parse(&tokens).is_ok());
+        let tokens = tokenize("2 x").unwrap();
+        assert!(matches!(
+            parse(&tokens),
+            Err(CalcError::Parse(ParseError::TrailingToken { .. }))
+        ));
+
+        let tokens = tokenize("3 km in m/furlong").unwrap();
+        assert_eq!(
+            parse(&tokens),
+            Err(CalcError::Parse(ParseError::UnknownUnit {
+                name: String::from("furlong"),
+                span: Span::new(10, 17),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_parse_matrix() {
+        let input = "[1, 2; 3, 4 + 5]";
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = Expression::new(
+            ExpressionKind::Matrix(vec![
+                vec![number(1.0), number(2.0)],
+                vec![
+                    number(3.0),
+                    binary_op(number(4.0), TokenKind::Plus, number(5.0)),
+                ],
+            ]),
+            Span::default(),
+        );
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(ast, expected_ast);
+        assert_eq!(ast.span, Span::new(0, 16));
+
+        let tokens = tokenize("[]").unwrap();
+        assert_eq!(
+            parse(&tokens).unwrap().kind,
+            ExpressionKind::Matrix(Vec::new())
+        );
+
+        let tokens = tokenize("[1, 2").unwrap();
+        assert!(matches!(
+            parse(&tokens),
+            Err(CalcError::Parse(ParseError::ExpectedToken { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_parse_conditional() {
+        let boolean = |val| Expression::new(ExpressionKind::Boolean(val), Span::default());
+        let conditional = |condition, then, otherwise| {
+            Expression::new(
+                ExpressionKind::Conditional(
+                    Box::new(condition),
+                    Box::new(then),
+                    Box::new(otherwise),
+                ),
+                Span::default(),
+            )
+        };
+
+        let tokens = tokenize("not 1 + 1 < 2 or true and false").unwrap();
+        let expected_ast = binary_op(
+            unary(
+                TokenKind::Not,
+                binary_op(
+                    binary_op(number(1.0), TokenKind::Plus, number(1.0)),
+                    TokenKind::Less,
+                    number(2.0),
+                ),
+            ),
+            TokenKind::Or,
+            binary_op(boolean(true), TokenKind::And, boolean(false)),
+        );
+        assert_eq!(parse(&tokens).unwrap(), expected_ast);
+
+        // `?:` binds loosest and groups to the right.
+        let input = "1 == 2 ? 3 : 4 != 5 ? 6 : 7";
+        let tokens = tokenize(input).unwrap();
+        let expected_ast = conditional(
+            binary_op(number(1.0), TokenKind::Equal, number(2.0)),
+            number(3.0),
+            conditional(
+                binary_op(number(4.0), TokenKind::NotEqual, number(5.0)),
+                number(6.0),
+                number(7.0),
+            ),
+        );
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(ast, expected_ast);
+        assert_eq!(ast.span, Span::new(0, input.len()));
+
+        let tokens = tokenize("true ? 1").unwrap();
+        assert!(matches!(
+            parse(&tokens),
+            Err(CalcError::Parse(ParseError::ExpectedToken { .. }))
+        ));
+    }
 }
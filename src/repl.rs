@@ -0,0 +1,228 @@
+//! # REPL Module
+//!
+//! This module implements the interactive session started when `calcrs` is run without an
+//! expression.
+//!
+//! ## Behaviour
+//!
+//! - Lines are read with `rustyline`, which provides line editing and history navigation.
+//! - The history is saved to `calcrs/history.txt` under the user's data directory
+//!   (for example `~/.local/share` on Linux) and reloaded on the next start.
+//! - While an input has more `(` than `)`, further lines are appended to it before it is
+//!   evaluated, so long expressions can be spread over several lines.
+//! - `angle` shows the angle mode of the trigonometric functions, and `angle deg`,
+//!   `angle rad` or `angle grad` sets it.
+//! - `numbers` shows whether numbers are floats, exact rationals or decimals, and
+//!   `numbers float`, `numbers rational` or `numbers decimal [digits]` switches between them.
+//! - `complex` shows whether complex results are printed in rectangular or polar form, and
+//!   `complex rectangular` or `complex polar` sets it.
+//! - `help` lists the commands, the built-in functions, the built-in constants and the units.
+//! - A single `Environment` is shared by all inputs, so variables and functions persist
+//!   for the whole session.
+//!
+//! ## Functions
+//!
+//! - `start(env: Environment) -> rustyline::Result<()>`: Runs the REPL in `env` until end of input.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use calcrs::decimal::DEFAULT_PRECISION;
+use calcrs::evaluator::CONSTANTS;
+use calcrs::unit;
+use calcrs::{tokenize, AngleMode, ComplexFormat, Environment, NumericMode, TokenKind};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+
+pub fn start(mut env: Environment) -> rustyline::Result<()> {
+    let mut editor = DefaultEditor::new()?;
+    let history = history_path();
+    if let Some(path) = &history {
+        // A missing history file simply means this is the first session.
+        let _ = editor.load_history(path);
+    }
+
+    println!(
+        "calcrs {} (`help` for help, Ctrl-D or `exit` to quit)",
+        env!("CARGO_PKG_VERSION")
+    );
+
+    let mut input = String::new();
+    let result = loop {
+        let prompt = if input.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+
+        match editor.readline(prompt) {
+            Ok(line) => {
+                if !input.is_empty() {
+                    input.push('\n');
+                }
+                input.push_str(&line);
+                if is_incomplete(&input) {
+                    continue;
+                }
+
+                let source = std::mem::take(&mut input);
+                let command = source.trim();
+                if command.is_empty() {
+                    continue;
+                }
+                // The session goes on without history rather than ending.
+                if let Err(err) = editor.add_history_entry(command) {
+                    eprintln!("History error: {}", err);
+                }
+                match command {
+                    "exit" | "quit" => break Ok(()),
+                    "help" => {
+                        print_help(&env);
+                        continue;
+                    }
+                    _ => {}
+                }
+                match command.split_whitespace().collect::<Vec<_>>()[..] {
+                    ["angle"] => {
+                        println!("{}", env.angle_mode().suffix());
+                        continue;
+                    }
+                    ["angle", suffix] => {
+                        if let Some(unit) = AngleMode::from_suffix(suffix) {
+                            env.set_angle_mode(unit);
+                            continue;
+                        }
+                    }
+                    ["numbers"] => {
+                        match env.numeric_mode() {
+                            NumericMode::Float => println!("float"),
+                            NumericMode::Rational => println!("rational"),
+                            NumericMode::Decimal { precision } => println!("decimal {}", precision),
+                        }
+                        continue;
+                    }
+                    ["numbers", "decimal"] => {
+                        env.set_numeric_mode(NumericMode::Decimal {
+                            precision: DEFAULT_PRECISION,
+                        });
+                        continue;
+                    }
+                    ["numbers", "decimal", digits] => {
+                        if let Some(precision) = digits.parse().ok().filter(|&digits| digits > 0) {
+                            env.set_numeric_mode(NumericMode::Decimal { precision });
+                            continue;
+                        }
+                    }
+                    ["numbers", "float"] => {
+                        env.set_numeric_mode(NumericMode::Float);
+                        continue;
+                    }
+                    ["numbers", "rational"] => {
+                        env.set_numeric_mode(NumericMode::Rational);
+                        continue;
+                    }
+                    ["complex"] => {
+                        match env.complex_format() {
+                            ComplexFormat::Rectangular => println!("rectangular"),
+                            ComplexFormat::Polar => println!("polar"),
+                        }
+                        continue;
+                    }
+                    ["complex", "rectangular"] => {
+                        env.set_complex_format(ComplexFormat::Rectangular);
+                        continue;
+                    }
+                    ["complex", "polar"] => {
+                        env.set_complex_format(ComplexFormat::Polar);
+                        continue;
+                    }
+                    _ => {}
+                }
+                crate::run(&source, &mut env);
+            }
+            Err(ReadlineError::Interrupted) => input.clear(),
+            Err(ReadlineError::Eof) => break Ok(()),
+            Err(err) => break Err(err),
+        }
+    };
+
+    // The history is saved even when reading a line failed, which is reported first.
+    let saved = match &history {
+        Some(path) => save_history(&mut editor, path),
+        None => Ok(()),
+    };
+    result.and(saved)
+}
+
+fn save_history(editor: &mut DefaultEditor, path: &Path) -> rustyline::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    editor.save_history(path)
+}
+
+fn print_help(env: &Environment) {
+    println!("Enter an expression to evaluate it, e.g. `2 * pi * r`.");
+    println!(
+        "Separate statements with `;`, assign with `x = 1` and define functions with `f(x) = x^2`."
+    );
+    println!();
+    println!("Commands:");
+    println!("  help            show this help");
+    println!("  angle [unit]    show or set the angle mode: deg, rad or grad");
+    println!("  numbers [mode]  show or set the numbers: float, rational for exact fractions,");
+    println!("                  or decimal [digits] for decimals with that many digits");
+    println!("  complex [form]  show or set the form of complex results: rectangular or polar");
+    println!("  exit, quit      end the session (or press Ctrl-D)");
+    println!();
+    println!("Functions:");
+    for names in env.registry().names().chunks(8) {
+        println!("  {}", names.join(", "));
+    }
+    println!();
+    println!("Constants:");
+    for (name, value, description) in CONSTANTS {
+        println!("  {:<6} {:<20} {}", name, value, description);
+    }
+    println!();
+    println!("Units (metric ones take SI prefixes, e.g. `km`, `ms`, `kWh`; convert with `in`):");
+    for names in unit::names().chunks(12) {
+        println!("  {}", names.join(", "));
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("calcrs").join("history.txt"))
+}
+
+/// Whether `input` has unclosed parentheses or brackets and should continue on the next line.
+fn is_incomplete(input: &str) -> bool {
+    let Ok(tokens) = tokenize(input) else {
+        return false;
+    };
+    let depth = tokens.iter().fold(0i32, |depth, token| match token.kind {
+        TokenKind::LeftParen | TokenKind::LeftBracket => depth + 1,
+        TokenKind::RightParen | TokenKind::RightBracket => depth - 1,
+        _ => depth,
+    });
+    depth > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_incomplete() {
+        assert!(is_incomplete("f(x) = (x +"));
+        assert!(is_incomplete("sin(1 +\n(2"));
+        assert!(!is_incomplete("sin(1 +\n2)"));
+        assert!(!is_incomplete("1 + 2)"));
+        assert!(!is_incomplete("1 # ("));
+        assert!(is_incomplete("[1, 2;\n3"));
+        assert!(!is_incomplete("[1, 2;\n3, 4]"));
+    }
+}
@@ -0,0 +1,308 @@
+//! # Statistics Module
+//!
+//! This module provides the aggregate and statistics functions computed over lists of
+//! numbers, for floats, decimals and rationals.
+//!
+//! ## Functions
+//!
+//! - `sum` and `prod`: The sum and the product of the numbers.
+//! - `min` and `max`: The smallest and the largest number.
+//! - `mean` and `median`: The arithmetic mean and the middle number, or the mean of the two
+//!   middle numbers.
+//! - `var` and `stddev`: The sample variance and standard deviation, divided by `n - 1`;
+//!   both are zero for a single number.
+//! - `percentile`: The `p`-th percentile for `p` between `0` and `100`, interpolated linearly
+//!   between the closest ranks, so `percentile([1, 2, 3, 4], 50)` is `2.5` like the median.
+//! - `mode`: The most frequent number, or the smallest of the most frequent ones.
+//!
+//! Each function returns `None` for an empty list, and `percentile` also for a `p` outside of
+//! `0` to `100`. A `NaN` among the numbers makes every result `NaN`.
+//!
+//! ```
+//! use calcrs::statistics;
+//!
+//! assert_eq!(statistics::median(&[3.0, 1.0, 4.0, 1.0]), Some(2.0));
+//! assert_eq!(statistics::mode(&[3.0, 1.0, 4.0, 1.0]), Some(1.0));
+//! ```
+
+use std::cmp::Ordering;
+use std::ops::{Add, Div, Mul, Sub};
+
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive};
+
+use crate::decimal::Decimal;
+
+/// A number the statistics can be computed on.
+pub trait Sample:
+    Sized + Clone + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self>
+{
+    /// The integer `n`, as a number of the same kind and precision as `self`.
+    fn integer(&self, n: usize) -> Self;
+
+    fn compare(&self, other: &Self) -> Ordering;
+
+    fn is_nan(&self) -> bool;
+
+    fn sqrt(&self) -> Option<Self>;
+
+    fn to_f64(&self) -> f64;
+}
+
+impl Sample for f64 {
+    fn integer(&self, n: usize) -> Self {
+        n as f64
+    }
+
+    fn compare(&self, other: &Self) -> Ordering {
+        self.total_cmp(other)
+    }
+
+    fn is_nan(&self) -> bool {
+        f64::is_nan(*self)
+    }
+
+    fn sqrt(&self) -> Option<Self> {
+        Some(f64::sqrt(*self))
+    }
+
+    fn to_f64(&self) -> f64 {
+        *self
+    }
+}
+
+impl Sample for Decimal {
+    fn integer(&self, n: usize) -> Self {
+        Decimal::new(BigDecimal::from(n as u64), self.precision())
+    }
+
+    fn compare(&self, other: &Self) -> Ordering {
+        self.value().cmp(other.value())
+    }
+
+    fn is_nan(&self) -> bool {
+        false
+    }
+
+    fn sqrt(&self) -> Option<Self> {
+        Decimal::sqrt(self)
+    }
+
+    fn to_f64(&self) -> f64 {
+        Decimal::to_f64(self)
+    }
+}
+
+impl Sample for BigRational {
+    fn integer(&self, n: usize) -> Self {
+        BigRational::from_integer(BigInt::from(n))
+    }
+
+    fn compare(&self, other: &Self) -> Ordering {
+        self.cmp(other)
+    }
+
+    fn is_nan(&self) -> bool {
+        false
+    }
+
+    /// The exact square root, if the numerator and the denominator are perfect squares.
+    fn sqrt(&self) -> Option<Self> {
+        if self.is_negative() {
+            return None;
+        }
+        let (numer, denom) = (self.numer().sqrt(), self.denom().sqrt());
+        let exact = &numer * &numer == *self.numer() && &denom * &denom == *self.denom();
+        exact.then(|| BigRational::new(numer, denom))
+    }
+
+    fn to_f64(&self) -> f64 {
+        ToPrimitive::to_f64(self).unwrap_or(f64::NAN)
+    }
+}
+
+pub fn sum<T: Sample>(values: &[T]) -> Option<T> {
+    let (first, rest) = values.split_first()?;
+    Some(rest.iter().cloned().fold(first.clone(), T::add))
+}
+
+pub fn prod<T: Sample>(values: &[T]) -> Option<T> {
+    let (first, rest) = values.split_first()?;
+    Some(rest.iter().cloned().fold(first.clone(), T::mul))
+}
+
+pub fn min<T: Sample>(values: &[T]) -> Option<T> {
+    nan(values).or_else(|| values.iter().min_by(|a, b| a.compare(b)).cloned())
+}
+
+pub fn max<T: Sample>(values: &[T]) -> Option<T> {
+    nan(values).or_else(|| values.iter().max_by(|a, b| a.compare(b)).cloned())
+}
+
+pub fn mean<T: Sample>(values: &[T]) -> Option<T> {
+    let sum = sum(values)?;
+    let count = sum.integer(values.len());
+    Some(sum / count)
+}
+
+pub fn median<T: Sample>(values: &[T]) -> Option<T> {
+    let fifty = values.first()?.integer(50);
+    percentile(values, &fifty)
+}
+
+/// The sample variance, which is zero for a single number.
+pub fn var<T: Sample>(values: &[T]) -> Option<T> {
+    let mean = mean(values)?;
+    let squares: Vec<T> = values
+        .iter()
+        .map(|value| {
+            let deviation = value.clone() - mean.clone();
+            deviation.clone() * deviation
+        })
+        .collect();
+    let sum = sum(&squares)?;
+    match values.len() {
+        1 => Some(sum.integer(0)),
+        n => {
+            let count = sum.integer(n - 1);
+            Some(sum / count)
+        }
+    }
+}
+
+pub fn stddev<T: Sample>(values: &[T]) -> Option<T> {
+    var(values)?.sqrt()
+}
+
+/// The `p`-th percentile, interpolated linearly between the closest ranks.
+pub fn percentile<T: Sample>(values: &[T], p: &T) -> Option<T> {
+    let first = values.first()?;
+    let hundred = first.integer(100);
+    if p.compare(&first.integer(0)).is_lt() || p.compare(&hundred).is_gt() {
+        return None;
+    }
+    if let Some(nan) = nan(values) {
+        return Some(nan);
+    }
+    let sorted = sorted(values);
+
+    // The rank `p (n - 1) / 100` lies between the indices `lower` and `lower + 1`.
+    let last = sorted.len() - 1;
+    let rank = p.clone() * first.integer(last) / hundred;
+    let lower = (rank.to_f64().floor() as usize).min(last);
+    let fraction = rank - first.integer(lower);
+    let (low, high) = (&sorted[lower], &sorted[(lower + 1).min(last)]);
+    Some(low.clone() + (high.clone() - low.clone()) * fraction)
+}
+
+/// The most frequent number, or the smallest of the most frequent ones.
+pub fn mode<T: Sample>(values: &[T]) -> Option<T> {
+    if let Some(nan) = nan(values) {
+        return Some(nan);
+    }
+    let sorted = sorted(values);
+    let mut best: Option<(&T, usize)> = None;
+    let mut start = 0;
+    for end in 1..=sorted.len() {
+        if end < sorted.len() && sorted[end].compare(&sorted[start]).is_eq() {
+            continue;
+        }
+        if best.is_none_or(|(_, count)| end - start > count) {
+            best = Some((&sorted[start], end - start));
+        }
+        start = end;
+    }
+    best.map(|(value, _)| value.clone())
+}
+
+/// The first `NaN` among the values, which is the result of any statistic that orders them,
+/// since `total_cmp` would otherwise sort it above or below every number depending on its sign.
+fn nan<T: Sample>(values: &[T]) -> Option<T> {
+    values.iter().find(|value| value.is_nan()).cloned()
+}
+
+fn sorted<T: Sample>(values: &[T]) -> Vec<T> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.compare(b));
+    sorted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregates() {
+        let values = [3.0, 1.0, 4.0, 1.0, 5.0];
+        assert_eq!(sum(&values), Some(14.0));
+        assert_eq!(prod(&values), Some(60.0));
+        assert_eq!(min(&values), Some(1.0));
+        assert_eq!(max(&values), Some(5.0));
+        assert_eq!(mean(&values), Some(2.8));
+        assert_eq!(sum::<f64>(&[]), None);
+        assert_eq!(max::<f64>(&[]), None);
+        assert!(max(&[f64::NAN, 1.0]).unwrap().is_nan());
+        assert!(min(&[1.0, f64::NAN]).unwrap().is_nan());
+        assert!(min(&[1.0, -f64::NAN]).unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_statistics() {
+        let values = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert_eq!(median(&values), Some(4.5));
+        assert_eq!(median(&[3.0, 1.0, 2.0]), Some(2.0));
+        assert_eq!(var(&values), Some(32.0 / 7.0));
+        assert_eq!(stddev(&[1.0, 3.0]), Some(2f64.sqrt()));
+        assert_eq!(var(&[7.0]), Some(0.0));
+        assert_eq!(mode(&values), Some(4.0));
+        assert_eq!(mode(&[2.0, 1.0, 2.0, 1.0]), Some(1.0));
+
+        assert_eq!(percentile(&values, &0.0), Some(2.0));
+        assert_eq!(percentile(&values, &100.0), Some(9.0));
+        assert_eq!(percentile(&[1.0, 2.0, 3.0, 4.0], &25.0), Some(1.75));
+        assert_eq!(percentile(&values, &101.0), None);
+        assert_eq!(percentile(&[], &50.0), None);
+        assert!(median(&[1.0, f64::NAN, 3.0]).unwrap().is_nan());
+        assert!(mode(&[1.0, 1.0, f64::NAN]).unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_decimal_statistics() {
+        let decimals = |values: &[f64]| -> Vec<Decimal> {
+            values
+                .iter()
+                .map(|&value| Decimal::from_f64(value, 20).unwrap())
+                .collect()
+        };
+        let values = decimals(&[0.1, 0.2, 0.3, 0.4]);
+        assert_eq!(sum(&values).unwrap().to_string(), "1");
+        assert_eq!(mean(&values).unwrap().to_string(), "0.25");
+        assert_eq!(median(&values).unwrap().to_string(), "0.25");
+        assert_eq!(max(&values).unwrap().to_string(), "0.4");
+        assert_eq!(
+            stddev(&decimals(&[1.0, 3.0])).unwrap().to_string(),
+            "1.4142135623730950488"
+        );
+        let p = Decimal::from_f64(90.0, 20).unwrap();
+        assert_eq!(percentile(&values, &p).unwrap().to_string(), "0.37");
+    }
+
+    #[test]
+    fn test_rational_statistics() {
+        let rational = |numer: i64, denom: i64| BigRational::new(numer.into(), denom.into());
+        let values = [rational(1, 3), rational(1, 4), rational(1, 2)];
+        assert_eq!(sum(&values), Some(rational(13, 12)));
+        assert_eq!(mean(&values), Some(rational(13, 36)));
+        assert_eq!(median(&values), Some(rational(1, 3)));
+        assert_eq!(max(&values), Some(rational(1, 2)));
+        assert_eq!(var(&[rational(1, 2), rational(3, 2)]), Some(rational(1, 2)));
+        assert_eq!(
+            stddev(&[rational(1, 1), rational(3, 1), rational(5, 1)]),
+            Some(rational(2, 1))
+        );
+        assert_eq!(stddev(&[rational(1, 3), rational(1, 1)]), None);
+        assert_eq!(percentile(&values, &rational(25, 1)), Some(rational(7, 24)));
+    }
+}
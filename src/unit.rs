@@ -0,0 +1,314 @@
+//! # Unit Module
+//!
+//! This module provides the physical units known to the calculator and the dimensions of the
+//! quantities built from them.
+//!
+//! ## Dimensions
+//!
+//! A `Dimension` is a product of powers of the seven SI base dimensions, written with their
+//! base units: length (`m`), mass (`kg`), time (`s`), electric current (`A`), temperature
+//! (`K`), amount of substance (`mol`) and luminous intensity (`cd`). A speed has the
+//! dimension `m/s` and a force `kg m/s^2`; plain numbers have the dimension `1`.
+//!
+//! ## Quantities
+//!
+//! A `Quantity` is a magnitude expressed in SI base units together with its dimension, so
+//! `3 km` is stored as `3000` with the dimension `m`. A quantity converted with `in` also
+//! records the `Unit` it is displayed in.
+//!
+//! ## Unit Database
+//!
+//! The embedded database (see `lookup`) contains:
+//!
+//! - The SI base units `m`, `g`, `s`, `A`, `K`, `mol` and `cd`.
+//! - The derived units `Hz`, `N`, `Pa`, `J`, `W`, `C`, `V` and `ohm` (or `Ω`), the litre `L`,
+//!   the electronvolt `eV`, the watt-hour `Wh`, the calorie `cal` and the `bar`.
+//! - `min`, `h`, `day`, `week` and `yr` (a Julian year).
+//! - `inch`, `ft`, `yd`, `mi`, `au`, `ly`, `lb`, `oz`, the tonne `t`, `atm`, `psi` and `mph`.
+//!
+//! The metric units accept the SI prefixes from `f` (femto) to `P` (peta), so `km`, `ms`,
+//! `µA`, `kWh` and `MPa` are units too; the micro prefix may be written `u`, `µ` or `μ`.
+
+use std::fmt;
+
+/// The base units, in the order of the exponents of a `Dimension`.
+const BASE_UNITS: [&str; 7] = ["kg", "m", "s", "A", "K", "mol", "cd"];
+
+/// The exponents of the SI base dimensions, in the order of `BASE_UNITS`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Dimension([i8; 7]);
+
+impl Dimension {
+    /// The dimension of plain numbers.
+    pub const NONE: Dimension = Dimension([0; 7]);
+
+    pub fn is_none(self) -> bool {
+        self == Dimension::NONE
+    }
+
+    /// The dimension raised to `exponent`, if every resulting exponent is an integer.
+    pub fn pow(self, exponent: f64) -> Option<Dimension> {
+        let mut exponents = [0; 7];
+        for (result, base) in exponents.iter_mut().zip(self.0) {
+            let power = f64::from(base) * exponent;
+            if power.fract() != 0.0 || power.abs() > f64::from(i8::MAX) {
+                return None;
+            }
+            *result = power as i8;
+        }
+        Some(Dimension(exponents))
+    }
+
+    /// The dimension of a product, unless an exponent overflows.
+    pub fn checked_mul(self, other: Dimension) -> Option<Dimension> {
+        self.combine(other, i8::checked_add)
+    }
+
+    /// The dimension of a quotient, unless an exponent overflows.
+    pub fn checked_div(self, other: Dimension) -> Option<Dimension> {
+        self.combine(other, i8::checked_sub)
+    }
+
+    fn combine(self, other: Dimension, exponent: fn(i8, i8) -> Option<i8>) -> Option<Dimension> {
+        let mut exponents = [0; 7];
+        for (result, (left, right)) in exponents.iter_mut().zip(self.0.into_iter().zip(other.0)) {
+            *result = exponent(left, right)?;
+        }
+        Some(Dimension(exponents))
+    }
+}
+
+impl fmt::Display for Dimension {
+    /// Writes the dimension with its base units, as in `kg m/s^2` or `1/(s A)`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let powers = |sign: i8| {
+            let powers: Vec<String> = BASE_UNITS
+                .iter()
+                .zip(self.0)
+                .filter(|&(_, exponent)| exponent.signum() == sign)
+                .map(|(unit, exponent)| match exponent.abs() {
+                    1 => unit.to_string(),
+                    exponent => format!("{}^{}", unit, exponent),
+                })
+                .collect();
+            powers
+        };
+        let (numerator, denominator) = (powers(1), powers(-1));
+        let numerator = match numerator.len() {
+            0 => String::from("1"),
+            _ => numerator.join(" "),
+        };
+        match denominator.len() {
+            0 => write!(f, "{}", numerator),
+            1 => write!(f, "{}/{}", numerator, denominator[0]),
+            _ => write!(f, "{}/({})", numerator, denominator.join(" ")),
+        }
+    }
+}
+
+/// A named unit and its size in SI base units.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Unit {
+    pub name: String,
+    pub factor: f64,
+    pub dimension: Dimension,
+}
+
+/// A magnitude in SI base units and its dimension.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Quantity {
+    pub value: f64,
+    pub dimension: Dimension,
+    /// The unit the quantity is displayed in, set by a conversion.
+    pub unit: Option<Unit>,
+}
+
+impl Quantity {
+    pub fn new(value: f64, dimension: Dimension) -> Self {
+        Quantity {
+            value,
+            dimension,
+            unit: None,
+        }
+    }
+
+    /// The magnitude in the unit the quantity is displayed in.
+    pub fn magnitude(&self) -> f64 {
+        match &self.unit {
+            Some(unit) => self.value / unit.factor,
+            None => self.value,
+        }
+    }
+}
+
+impl From<Unit> for Quantity {
+    fn from(unit: Unit) -> Self {
+        Quantity::new(unit.factor, unit.dimension)
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.unit {
+            Some(unit) => write!(f, "{} {}", self.magnitude(), unit.name),
+            None => write!(f, "{} {}", self.value, self.dimension),
+        }
+    }
+}
+
+const fn dimension(kg: i8, m: i8, s: i8, a: i8) -> Dimension {
+    Dimension([kg, m, s, a, 0, 0, 0])
+}
+
+const LENGTH: Dimension = dimension(0, 1, 0, 0);
+const MASS: Dimension = dimension(1, 0, 0, 0);
+const TIME: Dimension = dimension(0, 0, 1, 0);
+const CURRENT: Dimension = dimension(0, 0, 0, 1);
+const ENERGY: Dimension = dimension(1, 2, -2, 0);
+const PRESSURE: Dimension = dimension(1, -1, -2, 0);
+
+/// The units of the database, as `(name, factor, dimension, accepts SI prefixes)`.
+const UNITS: &[(&str, f64, Dimension, bool)] = &[
+    ("m", 1.0, LENGTH, true),
+    ("g", 1e-3, MASS, true),
+    ("s", 1.0, TIME, true),
+    ("A", 1.0, CURRENT, true),
+    ("K", 1.0, Dimension([0, 0, 0, 0, 1, 0, 0]), true),
+    ("mol", 1.0, Dimension([0, 0, 0, 0, 0, 1, 0]), true),
+    ("cd", 1.0, Dimension([0, 0, 0, 0, 0, 0, 1]), true),
+    ("Hz", 1.0, dimension(0, 0, -1, 0), true),
+    ("N", 1.0, dimension(1, 1, -2, 0), true),
+    ("Pa", 1.0, PRESSURE, true),
+    ("J", 1.0, ENERGY, true),
+    ("W", 1.0, dimension(1, 2, -3, 0), true),
+    ("C", 1.0, dimension(0, 0, 1, 1), true),
+    ("V", 1.0, dimension(1, 2, -3, -1), true),
+    ("ohm", 1.0, dimension(1, 2, -3, -2), true),
+    ("Ω", 1.0, dimension(1, 2, -3, -2), true),
+    ("L", 1e-3, dimension(0, 3, 0, 0), true),
+    ("eV", 1.602_176_634e-19, ENERGY, true),
+    ("Wh", 3600.0, ENERGY, true),
+    ("cal", 4.184, ENERGY, true),
+    ("bar", 1e5, PRESSURE, true),
+    ("min", 60.0, TIME, false),
+    ("h", 3600.0, TIME, false),
+    ("day", 86_400.0, TIME, false),
+    ("week", 604_800.0, TIME, false),
+    ("yr", 31_557_600.0, TIME, false),
+    ("inch", 0.0254, LENGTH, false),
+    ("ft", 0.3048, LENGTH, false),
+    ("yd", 0.9144, LENGTH, false),
+    ("mi", 1609.344, LENGTH, false),
+    ("au", 149_597_870_700.0, LENGTH, false),
+    ("ly", 9_460_730_472_580_800.0, LENGTH, false),
+    ("lb", 0.453_592_37, MASS, false),
+    ("oz", 0.028_349_523_125, MASS, false),
+    ("t", 1000.0, MASS, false),
+    ("atm", 101_325.0, PRESSURE, false),
+    ("psi", 6_894.757_293_168_361, PRESSURE, false),
+    ("mph", 0.447_04, dimension(0, 1, -1, 0), false),
+];
+
+/// The SI prefixes, as `(prefix, factor)`; `da` comes before `d` so that it is tried first.
+const PREFIXES: &[(&str, f64)] = &[
+    ("P", 1e15),
+    ("T", 1e12),
+    ("G", 1e9),
+    ("M", 1e6),
+    ("k", 1e3),
+    ("h", 1e2),
+    ("da", 1e1),
+    ("d", 1e-1),
+    ("c", 1e-2),
+    ("m", 1e-3),
+    ("u", 1e-6),
+    ("µ", 1e-6),
+    ("μ", 1e-6),
+    ("n", 1e-9),
+    ("p", 1e-12),
+    ("f", 1e-15),
+];
+
+/// The unit called `name` in the database, possibly with an SI prefix.
+pub fn lookup(name: &str) -> Option<Unit> {
+    let unit = |name: &str, factor: f64, dimension: Dimension| Unit {
+        name: String::from(name),
+        factor,
+        dimension,
+    };
+    if let Some(&(_, factor, dimension, _)) = UNITS.iter().find(|(unit, ..)| *unit == name) {
+        return Some(unit(name, factor, dimension));
+    }
+    PREFIXES.iter().find_map(|(prefix, scale)| {
+        let base = name.strip_prefix(prefix)?;
+        let &(_, factor, dimension, _) = UNITS
+            .iter()
+            .find(|&&(unit, .., prefixes)| prefixes && unit == base)?;
+        Some(unit(name, scale * factor, dimension))
+    })
+}
+
+/// The names of the units of the database, without prefixes.
+pub fn names() -> Vec<&'static str> {
+    UNITS.iter().map(|&(name, ..)| name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dimension() {
+        let speed = LENGTH.checked_div(TIME).unwrap();
+        assert_eq!(speed.to_string(), "m/s");
+        assert_eq!(ENERGY.to_string(), "kg m^2/s^2");
+        assert_eq!(TIME.pow(-1.0).unwrap().to_string(), "1/s");
+        assert_eq!(
+            CURRENT
+                .checked_mul(TIME)
+                .unwrap()
+                .pow(-1.0)
+                .unwrap()
+                .to_string(),
+            "1/(s A)"
+        );
+        assert_eq!(Dimension::NONE.to_string(), "1");
+        assert_eq!(speed.checked_mul(TIME), Some(LENGTH));
+        assert_eq!(LENGTH.pow(127.0).unwrap().checked_mul(LENGTH), None);
+        assert_eq!(
+            LENGTH
+                .pow(100.0)
+                .unwrap()
+                .checked_div(LENGTH.pow(-100.0).unwrap()),
+            None
+        );
+        assert_eq!(LENGTH.pow(2.0).unwrap().pow(0.5), Some(LENGTH));
+        assert_eq!(LENGTH.pow(0.5), None);
+        assert!(speed.checked_div(speed).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(lookup("m").unwrap().factor, 1.0);
+        assert_eq!(lookup("km").unwrap().factor, 1000.0);
+        assert_eq!(lookup("kg").unwrap().dimension, MASS);
+        assert_eq!(lookup("min").unwrap().factor, 60.0);
+        assert_eq!(lookup("mm").unwrap().factor, 1e-3);
+        assert_eq!(lookup("dam").unwrap().factor, 10.0);
+        assert_eq!(lookup("µs").unwrap().dimension, TIME);
+        assert_eq!(lookup("kWh").unwrap().factor, 3.6e6);
+        assert_eq!(lookup("cd").unwrap().factor, 1.0);
+        assert_eq!(lookup("kmi"), None);
+        assert_eq!(lookup("x"), None);
+        assert!(names().contains(&"ohm"));
+    }
+
+    #[test]
+    fn test_quantity() {
+        let mut quantity = Quantity::from(lookup("km").unwrap());
+        assert_eq!(quantity.to_string(), "1000 m");
+        quantity.unit = lookup("mm");
+        assert_eq!(quantity.magnitude(), 1e6);
+        assert_eq!(quantity.to_string(), "1000000 mm");
+    }
+}
@@ -0,0 +1,945 @@
+//! # Value Module
+//!
+//! This module provides the values computed by the evaluator.
+//!
+//! ## Numeric Modes
+//!
+//! A `Value` is a floating-point number, an exact rational number or an arbitrary-precision
+//! decimal. The `NumericMode` of an `Environment` selects the numeric backend, which decides
+//! how number literals are read:
+//!
+//! - `NumericMode::Float` (the default) reads every literal as an `f64`, so `0.1 + 0.2` is
+//!   `0.30000000000000004`.
+//! - `NumericMode::Rational` reads every literal as the exact fraction it denotes, so `0.1` is
+//!   `1/10` and `0.1 + 0.2` is exactly `0.3`.
+//! - `NumericMode::Decimal` reads every literal as a `Decimal` (see the `decimal` module) with
+//!   the given number of significant digits, so `1/3` is `0.333...` to that precision.
+//!
+//! Rationals stay exact through `+`, `-`, `*`, `/`, `%`, unary minus, the factorial of a
+//! natural number and powers with an integer exponent, unless the power would be too large to
+//! compute (see `MAX_EXACT_BITS`), and through the native functions with an exact
+//! implementation (see the `function` module), such as `abs`, `max` or `pow`. Any other
+//! operation, such as a root, a transcendental function or a constant like `pi`, computes with
+//! floats, and so does an operation with a float operand.
+//!
+//! Decimals are rounded to their precision after every operation, including the operations on
+//! the elements of a matrix, and the built-in functions and constants are computed to that
+//! precision. An operation between a decimal and a rational
+//! yields a decimal, and one with a float operand yields a float.
+//!
+//! In rational mode, a literal is read exactly as written, so `1.00000000000000000001 - 1` is
+//! not `0`; only a literal whose power of ten exceeds `MAX_EXACT_EXPONENT` in magnitude, such
+//! as `1e20000`, is read as a float. In decimal mode, a literal is read as written and rounded
+//! to the precision, so it keeps more digits than an `f64`.
+//!
+//! ## Complex Numbers
+//!
+//! A complex value has `f64` real and imaginary parts, whatever the numeric mode. An operation
+//! with a complex operand yields a complex value, and a complex result whose imaginary part is
+//! zero becomes a float again, so `(1+2i) * (1-2i)` is the float `5`. A real power with a
+//! negative base and a fractional exponent, such as `(-8)^(1/3)`, is the principal complex
+//! value. The modulo and the factorial of a complex number are `NaN`.
+//!
+//! ## Quantities
+//!
+//! A quantity with a physical dimension, such as `3 km` (see the `unit` module), has an `f64`
+//! magnitude in SI base units. Multiplying and dividing quantities multiplies and divides
+//! their dimensions, and a quantity raised to a number raises its dimension, as long as the
+//! resulting exponents are integers. Adding, subtracting or taking the modulo of quantities
+//! of different dimensions is `NaN`, and so is any operation that would leave a fractional
+//! dimension; the evaluator reports these cases as errors before they happen. A quantity
+//! whose dimension cancels out, such as `1 km / 1 m`, becomes a float again.
+//!
+//! ## Matrices
+//!
+//! A matrix (see the `matrix` module) has real elements, which are combined like numbers, so
+//! a matrix of rationals stays exact. A `1x1` matrix becomes its element, so
+//! `[1, 2] * [3; 4]` is the number `11`. `+`, `-` and `%` apply element by element to matrices
+//! of the same shape, and an operation between a matrix and a real number applies to every
+//! element, as in `2 * [1, 2]`. `*` is the matrix product and a matrix raised to an integer is
+//! a matrix power, while `mul_elementwise`, `div_elementwise` and `pow_elementwise` (the `.*`,
+//! `./` and `.^` operators) work element by element. Operands of incompatible shapes, or a
+//! matrix with a complex number or a quantity, give `NaN`; the evaluator reports these cases
+//! as errors before they happen.
+//!
+//! ## Booleans
+//!
+//! A boolean is the result of a comparison or of a boolean operator, such as `1 < 2` or
+//! `not x`. It is not a number: the evaluator reports a boolean used in arithmetic, and a
+//! number used as a condition, as errors. `compare` orders real numbers, exactly for two
+//! rationals or two decimals, and `equals` also compares complex numbers, matrices and
+//! booleans. As a float, `true` is `1` and `false` is `0`.
+//!
+//! ## Display
+//!
+//! Floats are displayed like `f64`, and decimals with their significant digits. A rational is
+//! displayed as an integer or an exact decimal when its denominator only has the prime factors
+//! 2 and 5, and as a fraction otherwise:
+//!
+//! ```
+//! use calcrs::value::{NumericMode, Value};
+//!
+//! assert_eq!(Value::literal(0.25, NumericMode::Rational).to_string(), "0.25");
+//! let third = Value::literal(1.0, NumericMode::Rational) / Value::literal(3.0, NumericMode::Rational);
+//! assert_eq!(third.to_string(), "1/3");
+//! assert_eq!(third.to_f64(), 1.0 / 3.0);
+//! ```
+//!
+//! Matrices are displayed like their literals, as in `[1, 2; 3, 4]`, and booleans as `true`
+//! or `false`.
+//!
+//! Quantities are displayed in SI base units, as in `2.5 m/s`, or in the unit they were
+//! converted to, as in `9 km/h`.
+//!
+//! Complex values are displayed in rectangular form, as in `3-4i`, or with `Value::format` in
+//! the polar form given by a `ComplexFormat`, as in `5∠-53.13010235415598deg`.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Rem, Sub};
+
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_complex::Complex64;
+
+use crate::decimal::Decimal;
+use crate::evaluator::AngleMode;
+use crate::lexer::Literal;
+use crate::matrix::{Matrix, Shape};
+use crate::unit::{Dimension, Quantity, Unit};
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+/// The largest exponent magnitude for which a power of a rational is computed exactly.
+pub const MAX_EXACT_EXPONENT: i32 = 10_000;
+
+/// The largest size, in bits of its numerator and denominator, of a power of a rational that
+/// is computed exactly.
+pub const MAX_EXACT_BITS: u64 = 1 << 18;
+
+/// The largest number whose factorial is computed exactly.
+pub const MAX_EXACT_FACTORIAL: u32 = 10_000;
+
+/// How number literals are represented during evaluation.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum NumericMode {
+    /// Literals are `f64` numbers.
+    #[default]
+    Float,
+    /// Literals are exact rational numbers.
+    Rational,
+    /// Literals are decimals with `precision` significant digits.
+    Decimal { precision: u64 },
+}
+
+/// How complex values are written.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ComplexFormat {
+    /// The real and imaginary parts, as in `3+4i`.
+    #[default]
+    Rectangular,
+    /// The magnitude and the argument, as in `5∠53.13010235415598deg`.
+    Polar,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Float(f64),
+    Rational(BigRational),
+    Decimal(Decimal),
+    Complex(Complex64),
+    Quantity(Quantity),
+    Matrix(Matrix),
+    Boolean(bool),
+}
+
+/// The kind of a value, as far as the operators accepting it are concerned.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ValueType {
+    /// A real or complex number, possibly with a unit.
+    Number,
+    Matrix,
+    Boolean,
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueType::Number => write!(f, "number"),
+            ValueType::Matrix => write!(f, "matrix"),
+            ValueType::Boolean => write!(f, "boolean"),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl Value {
+    /// The value of a number literal in `mode`.
+    pub fn literal(literal: impl Into<Literal>, mode: NumericMode) -> Self {
+        let Literal { value, exact } = literal.into();
+        match (mode, exact) {
+            (NumericMode::Rational, Some(exact)) => {
+                rational(&exact).map_or(Value::Float(value), Value::Rational)
+            }
+            (NumericMode::Decimal { precision }, Some(exact)) => {
+                Value::Decimal(Decimal::new(exact, precision))
+            }
+            _ => Value::Float(value),
+        }
+    }
+
+    /// A complex value, which is a float if its imaginary part is zero.
+    pub fn complex(value: Complex64) -> Self {
+        if value.im == 0.0 {
+            Value::Float(value.re)
+        } else {
+            Value::Complex(value)
+        }
+    }
+
+    /// A quantity, which is a float if it has no dimension.
+    pub fn quantity(value: f64, dimension: Dimension) -> Self {
+        if dimension.is_none() {
+            Value::Float(value)
+        } else {
+            Value::Quantity(Quantity::new(value, dimension))
+        }
+    }
+
+    /// A matrix, which is a float if it has a single element.
+    pub fn matrix(matrix: Matrix) -> Self {
+        if matrix.shape() == Shape::new(1, 1) {
+            matrix.elements()[0].clone()
+        } else {
+            Value::Matrix(matrix)
+        }
+    }
+
+    /// The value as a float, which is `NaN` for a complex number with an imaginary part or a
+    /// matrix, the magnitude in the unit it is displayed in for a quantity, and `1` or `0` for
+    /// a boolean.
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Value::Float(value) => *value,
+            Value::Rational(value) => value.to_f64().unwrap_or(f64::NAN),
+            Value::Decimal(value) => value.to_f64(),
+            Value::Complex(value) if value.im == 0.0 => value.re,
+            Value::Complex(_) => f64::NAN,
+            Value::Quantity(quantity) => quantity.magnitude(),
+            Value::Matrix(_) => f64::NAN,
+            Value::Boolean(value) => f64::from(u8::from(*value)),
+        }
+    }
+
+    /// Whether the value is a real number, rather than complex, a quantity, a matrix or a
+    /// boolean.
+    pub fn is_real(&self) -> bool {
+        matches!(
+            self,
+            Value::Float(_) | Value::Rational(_) | Value::Decimal(_)
+        )
+    }
+
+    /// The value as a matrix, which is `1x1` for a real number.
+    pub fn to_matrix(&self) -> Option<Matrix> {
+        match self {
+            Value::Matrix(matrix) => Some(matrix.clone()),
+            value if value.is_real() => Some(Matrix::new(Shape::new(1, 1), vec![value.clone()])),
+            _ => None,
+        }
+    }
+
+    /// The integer `n`, as a real number of the same kind and precision as this one.
+    pub(crate) fn integer(&self, n: i64) -> Value {
+        match self {
+            Value::Rational(_) => Value::Rational(BigRational::from_integer(BigInt::from(n))),
+            Value::Decimal(value) => {
+                Value::Decimal(Decimal::new(BigDecimal::from(n), value.precision()))
+            }
+            _ => Value::Float(n as f64),
+        }
+    }
+
+    /// The relative rounding error of an operation on real numbers of this kind, which is zero
+    /// for a rational.
+    pub(crate) fn epsilon(&self) -> f64 {
+        match self {
+            Value::Rational(_) => 0.0,
+            Value::Decimal(value) => {
+                let digits = i32::try_from(value.precision()).unwrap_or(i32::MAX);
+                10f64.powi(1 - digits.min(400))
+            }
+            _ => f64::EPSILON,
+        }
+    }
+
+    /// The value as a float, in SI base units for a quantity.
+    pub fn to_si(&self) -> f64 {
+        match self {
+            Value::Quantity(quantity) => quantity.value,
+            value => value.to_f64(),
+        }
+    }
+
+    pub fn to_complex(&self) -> Complex64 {
+        match self {
+            Value::Complex(value) => *value,
+            value => Complex64::from(value.to_f64()),
+        }
+    }
+
+    /// The value as a decimal with `precision` digits, unless it is a float or complex.
+    pub fn to_decimal(&self, precision: u64) -> Option<Decimal> {
+        match self {
+            Value::Float(_)
+            | Value::Complex(_)
+            | Value::Quantity(_)
+            | Value::Matrix(_)
+            | Value::Boolean(_) => None,
+            Value::Rational(value) => Some(Decimal::from_rational(value, precision)),
+            Value::Decimal(value) => Some(value.clone()),
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Value::Float(value) => *value == 0.0,
+            Value::Rational(value) => value.is_zero(),
+            Value::Decimal(value) => value.is_zero(),
+            Value::Complex(value) => value.is_zero(),
+            Value::Quantity(quantity) => quantity.value == 0.0,
+            Value::Matrix(_) | Value::Boolean(_) => false,
+        }
+    }
+
+    pub fn is_complex(&self) -> bool {
+        matches!(self, Value::Complex(_))
+    }
+
+    pub fn is_quantity(&self) -> bool {
+        matches!(self, Value::Quantity(_))
+    }
+
+    pub fn is_matrix(&self) -> bool {
+        matches!(self, Value::Matrix(_))
+    }
+
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            Value::Matrix(_) => ValueType::Matrix,
+            Value::Boolean(_) => ValueType::Boolean,
+            _ => ValueType::Number,
+        }
+    }
+
+    /// The shape of a matrix, or `None` for any other value.
+    pub fn shape(&self) -> Option<Shape> {
+        match self {
+            Value::Matrix(matrix) => Some(matrix.shape()),
+            _ => None,
+        }
+    }
+
+    /// The value as a quantity displayed in `unit`, which must have the same dimension.
+    pub fn in_unit(&self, unit: Unit) -> Value {
+        Value::Quantity(Quantity {
+            value: self.to_si(),
+            dimension: unit.dimension,
+            unit: Some(unit),
+        })
+    }
+
+    /// The dimension of the value, which is `Dimension::NONE` unless it is a quantity.
+    pub fn dimension(&self) -> Dimension {
+        match self {
+            Value::Quantity(quantity) => quantity.dimension,
+            _ => Dimension::NONE,
+        }
+    }
+
+    pub fn is_nan(&self) -> bool {
+        match self {
+            Value::Float(value) => value.is_nan(),
+            Value::Complex(value) => value.is_nan(),
+            Value::Quantity(quantity) => quantity.value.is_nan(),
+            Value::Matrix(matrix) => matrix.elements().iter().any(Value::is_nan),
+            Value::Rational(_) | Value::Decimal(_) | Value::Boolean(_) => false,
+        }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        match self {
+            Value::Float(value) => value.is_finite(),
+            Value::Complex(value) => value.is_finite(),
+            Value::Quantity(quantity) => quantity.value.is_finite(),
+            Value::Matrix(matrix) => matrix.elements().iter().all(Value::is_finite),
+            Value::Rational(_) | Value::Decimal(_) | Value::Boolean(_) => true,
+        }
+    }
+
+    /// Orders two real numbers, or two quantities by their magnitudes in SI base units; `None`
+    /// if either is `NaN`.
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Rational(left), Value::Rational(right)) => Some(left.cmp(right)),
+            (Value::Decimal(left), Value::Decimal(right)) => Some(left.value().cmp(right.value())),
+            _ => self.to_si().partial_cmp(&other.to_si()),
+        }
+    }
+
+    /// Whether two values are equal: numbers by value whatever their representation, complex
+    /// numbers by their parts, matrices by their shapes and elements, and booleans by truth.
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Boolean(left), Value::Boolean(right)) => left == right,
+            (Value::Matrix(left), Value::Matrix(right)) => {
+                left.shape() == right.shape()
+                    && (left.elements().iter())
+                        .zip(right.elements())
+                        .all(|(a, b)| a.equals(b))
+            }
+            (Value::Complex(_), _) | (_, Value::Complex(_)) => {
+                self.to_complex() == other.to_complex()
+            }
+            _ => self.compare(other) == Some(Ordering::Equal),
+        }
+    }
+
+    /// Converts an angle in the unit `from` to the unit `to`; only decimals stay exact
+    /// through a change of unit.
+    pub fn convert_angle(self, from: AngleMode, to: AngleMode) -> Value {
+        match self {
+            _ if from == to => self,
+            Value::Decimal(value) => Value::Decimal(value.convert_angle(from, to)),
+            Value::Complex(value) => Value::complex(value * from.convert(1.0, to)),
+            value => Value::Float(from.convert(value.to_f64(), to)),
+        }
+    }
+
+    /// Raises the value to the power `exponent`: as a matrix power if the base is a square
+    /// matrix and the exponent an integer, as a quantity if the base is a quantity, as a
+    /// complex number if either is complex, as a decimal if either is a decimal, exactly if the
+    /// base is rational, the exponent is an integer no larger than `MAX_EXACT_EXPONENT` in
+    /// magnitude and the power fits in `MAX_EXACT_BITS`, and as a float otherwise.
+    pub fn pow(&self, exponent: &Value) -> Value {
+        if self.is_matrix() || exponent.is_matrix() {
+            let power = match (self, exponent.to_f64()) {
+                (Value::Matrix(matrix), n) if exponent.is_real() && n.fract() == 0.0 => {
+                    matrix.pow(n as i32).ok()
+                }
+                _ => None,
+            };
+            return power.map_or(Value::Float(f64::NAN), Value::matrix);
+        }
+        if self.is_quantity() || exponent.is_quantity() {
+            let exponent = match exponent {
+                Value::Quantity(_) => f64::NAN,
+                exponent => exponent.to_f64(),
+            };
+            return match self.dimension().pow(exponent) {
+                Some(dimension) => Value::quantity(self.to_si().powf(exponent), dimension),
+                None => Value::Float(f64::NAN),
+            };
+        }
+        if self.is_complex() || exponent.is_complex() {
+            return Value::complex(powc(self.to_complex(), exponent.to_complex()));
+        }
+        if let Some((base, exponent)) = decimals(self, exponent) {
+            if let Some(power) = base.pow(&exponent) {
+                return Value::Decimal(power);
+            }
+        }
+        if let (Value::Rational(base), Value::Rational(exponent)) = (self, exponent) {
+            let exponent = exponent
+                .is_integer()
+                .then(|| exponent.to_integer().to_i32())
+                .flatten()
+                .filter(|exponent| exponent.abs() <= MAX_EXACT_EXPONENT)
+                .filter(|exponent| {
+                    let bits = base.numer().bits() + base.denom().bits();
+                    bits * u64::from(exponent.unsigned_abs()) <= MAX_EXACT_BITS
+                });
+            if let Some(exponent) = exponent {
+                if !(base.is_zero() && exponent < 0) {
+                    return Value::Rational(base.pow(exponent));
+                }
+            }
+        }
+        let (base, exponent) = (self.to_f64(), exponent.to_f64());
+        let power = base.powf(exponent);
+        // A negative base with a fractional exponent has a complex principal value.
+        if power.is_nan() && base.is_finite() && exponent.is_finite() {
+            return Value::complex(powc(Complex64::from(base), Complex64::from(exponent)));
+        }
+        Value::Float(power)
+    }
+
+    /// The factorial of the value: exact for a rational or decimal natural number no larger
+    /// than `MAX_EXACT_FACTORIAL`, `NaN` for a negative or fractional number.
+    pub fn factorial(&self) -> Value {
+        if let Value::Decimal(n) = self {
+            if let Some(product) = n.factorial(MAX_EXACT_FACTORIAL) {
+                return Value::Decimal(product);
+            }
+        }
+        if let Value::Rational(n) = self {
+            let n = n
+                .is_integer()
+                .then(|| n.to_integer().to_u32())
+                .flatten()
+                .filter(|&n| n <= MAX_EXACT_FACTORIAL);
+            if let Some(n) = n {
+                let product = (2..=n).fold(BigInt::one(), |product, k| product * k);
+                return Value::Rational(BigRational::from_integer(product));
+            }
+        }
+        Value::Float(factorial(self.to_f64()))
+    }
+
+    /// Multiplies two values element by element, like `*` unless both are matrices.
+    pub fn mul_elementwise(self, other: Value) -> Value {
+        match self.elementwise(&other, Value::mul) {
+            Some(product) => product,
+            None => self * other,
+        }
+    }
+
+    /// Divides two values element by element, like `/` unless both are matrices.
+    pub fn div_elementwise(self, other: Value) -> Value {
+        match self.elementwise(&other, Value::div) {
+            Some(quotient) => quotient,
+            None => self / other,
+        }
+    }
+
+    /// Raises the value to the power `exponent` element by element, like `pow` unless either
+    /// is a matrix. An element whose power is not a real number, as `(-8)^(1/3)`, is `NaN`.
+    pub fn pow_elementwise(&self, exponent: &Value) -> Value {
+        let pow = |base: Value, exponent: Value| match base.pow(&exponent) {
+            power if power.is_real() => power,
+            _ => Value::Float(f64::NAN),
+        };
+        match self.elementwise(exponent, pow) {
+            Some(power) => power,
+            None => self.pow(exponent),
+        }
+    }
+
+    /// Applies `f` element by element if either value is a matrix, to matrices of the same
+    /// shape or to a matrix and a real number; `None` if neither is a matrix.
+    fn elementwise(&self, other: &Value, f: impl Fn(Value, Value) -> Value) -> Option<Value> {
+        let result = match (self, other) {
+            (Value::Matrix(left), Value::Matrix(right)) => left.zip_with(right, f).ok(),
+            (Value::Matrix(left), right) => {
+                right.is_real().then(|| left.map(|a| f(a, right.clone())))
+            }
+            (left, Value::Matrix(right)) => {
+                left.is_real().then(|| right.map(|b| f(left.clone(), b)))
+            }
+            _ => return None,
+        };
+        Some(result.map_or(Value::Float(f64::NAN), Value::matrix))
+    }
+
+    /// Displays the value, writing a complex number in `format` with its argument in `unit`.
+    pub fn format(&self, format: ComplexFormat, unit: AngleMode) -> String {
+        match (self, format) {
+            (Value::Complex(value), ComplexFormat::Polar) => {
+                let (magnitude, argument) = value.to_polar();
+                let argument = AngleMode::Radians.convert(argument, unit);
+                format!("{}∠{}{}", magnitude, argument, unit.suffix())
+            }
+            _ => self.to_string(),
+        }
+    }
+
+    /// Combines two numbers as a quantity if either is a quantity, as complex numbers if either
+    /// is complex, exactly if both are rational, as decimals if either is a decimal and neither
+    /// is a float, and as floats otherwise. The dimension of a quantity is given by `dimension`,
+    /// or is invalid when it returns `None`. Matrices are combined element by element before.
+    fn combine(
+        self,
+        other: Value,
+        exact: impl FnOnce(BigRational, BigRational) -> BigRational,
+        decimal: impl FnOnce(Decimal, Decimal) -> Decimal,
+        float: impl Fn(f64, f64) -> f64,
+        complex: impl FnOnce(Complex64, Complex64) -> Complex64,
+        dimension: impl FnOnce(Dimension, Dimension) -> Option<Dimension>,
+    ) -> Value {
+        if self.is_quantity() || other.is_quantity() {
+            return match dimension(self.dimension(), other.dimension()) {
+                Some(dimension) => Value::quantity(float(self.to_si(), other.to_si()), dimension),
+                None => Value::Float(f64::NAN),
+            };
+        }
+        if self.is_complex() || other.is_complex() {
+            return Value::complex(complex(self.to_complex(), other.to_complex()));
+        }
+        if let Some((left, right)) = decimals(&self, &other) {
+            return Value::Decimal(decimal(left, right));
+        }
+        match (self, other) {
+            (Value::Rational(left), Value::Rational(right)) => Value::Rational(exact(left, right)),
+            (left, right) => Value::Float(float(left.to_f64(), right.to_f64())),
+        }
+    }
+}
+
+impl Add for Value {
+    type Output = Value;
+
+    fn add(self, other: Value) -> Value {
+        if let Some(sum) = self.elementwise(&other, Value::add) {
+            return sum;
+        }
+        self.combine(
+            other,
+            |a, b| a + b,
+            |a, b| a + b,
+            |a, b| a + b,
+            |a, b| a + b,
+            same,
+        )
+    }
+}
+
+impl Sub for Value {
+    type Output = Value;
+
+    fn sub(self, other: Value) -> Value {
+        if let Some(difference) = self.elementwise(&other, Value::sub) {
+            return difference;
+        }
+        self.combine(
+            other,
+            |a, b| a - b,
+            |a, b| a - b,
+            |a, b| a - b,
+            |a, b| a - b,
+            same,
+        )
+    }
+}
+
+impl Mul for Value {
+    type Output = Value;
+
+    fn mul(self, other: Value) -> Value {
+        if let (Value::Matrix(left), Value::Matrix(right)) = (&self, &other) {
+            return left
+                .product(right)
+                .map_or(Value::Float(f64::NAN), Value::matrix);
+        }
+        if let Some(product) = self.elementwise(&other, Value::mul) {
+            return product;
+        }
+        self.combine(
+            other,
+            |a, b| a * b,
+            |a, b| a * b,
+            |a, b| a * b,
+            |a, b| a * b,
+            Dimension::checked_mul,
+        )
+    }
+}
+
+/// Divides two values; the caller must rule out an exact division by zero.
+impl Div for Value {
+    type Output = Value;
+
+    fn div(self, other: Value) -> Value {
+        if let Some(quotient) = self.elementwise(&other, Value::div) {
+            return quotient;
+        }
+        self.combine(
+            other,
+            |a, b| a / b,
+            |a, b| a / b,
+            |a, b| a / b,
+            |a, b| a / b,
+            Dimension::checked_div,
+        )
+    }
+}
+
+/// The modulo of two values, which is never negative like `f64::rem_euclid` and `NaN` for
+/// complex numbers; the caller must rule out an exact division by zero.
+impl Rem for Value {
+    type Output = Value;
+
+    fn rem(self, other: Value) -> Value {
+        if let Some(remainder) = self.elementwise(&other, Value::rem) {
+            return remainder;
+        }
+        self.combine(
+            other,
+            |a, b| {
+                let b = b.abs();
+                let quotient = (&a / &b).floor();
+                a - b * quotient
+            },
+            |a, b| a % b,
+            f64::rem_euclid,
+            |_, _| Complex64::from(f64::NAN),
+            same,
+        )
+    }
+}
+
+impl Neg for Value {
+    type Output = Value;
+
+    fn neg(self) -> Value {
+        match self {
+            Value::Float(value) => Value::Float(-value),
+            Value::Rational(value) => Value::Rational(-value),
+            Value::Decimal(value) => Value::Decimal(-value),
+            Value::Complex(value) => Value::Complex(-value),
+            Value::Quantity(quantity) => Value::Quantity(Quantity {
+                value: -quantity.value,
+                ..quantity
+            }),
+            Value::Matrix(matrix) => Value::Matrix(matrix.map(Value::neg)),
+            Value::Boolean(_) => Value::Float(f64::NAN),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Quantity(quantity) => write!(f, "{}", quantity),
+            Value::Matrix(matrix) => write!(f, "{}", matrix),
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::Complex(value) => {
+                let imaginary = match value.im.abs() {
+                    1.0 => String::new(),
+                    magnitude => magnitude.to_string(),
+                };
+                let sign = if value.im.is_sign_negative() {
+                    "-"
+                } else {
+                    "+"
+                };
+                match value.re {
+                    0.0 if sign == "+" => write!(f, "{}i", imaginary),
+                    0.0 => write!(f, "-{}i", imaginary),
+                    re => write!(f, "{}{}{}i", re, sign, imaginary),
+                }
+            }
+            Value::Decimal(value) => write!(f, "{}", value),
+            Value::Rational(value) if value.is_integer() => write!(f, "{}", value.numer()),
+            Value::Rational(value) => match decimal_places(value.denom()) {
+                Some(places) => {
+                    let scaled = value * BigInt::from(10).pow(places);
+                    let digits = scaled.to_integer().abs().to_string();
+                    let digits = format!("{:0>width$}", digits, width = places as usize + 1);
+                    let (int, frac) = digits.split_at(digits.len() - places as usize);
+                    let sign = if value.is_negative() { "-" } else { "" };
+                    write!(f, "{}{}.{}", sign, int, frac)
+                }
+                None => write!(f, "{}/{}", value.numer(), value.denom()),
+            },
+        }
+    }
+}
+
+/// The dimension of a sum, a difference or a modulo, which requires equal dimensions.
+fn same(left: Dimension, right: Dimension) -> Option<Dimension> {
+    (left == right).then_some(left)
+}
+
+/// Both values as decimals with the precision of the decimal one, if either is a decimal and
+/// neither is a float.
+fn decimals(left: &Value, right: &Value) -> Option<(Decimal, Decimal)> {
+    let precision = match (left, right) {
+        (Value::Decimal(decimal), _) | (_, Value::Decimal(decimal)) => decimal.precision(),
+        _ => return None,
+    };
+    Some((left.to_decimal(precision)?, right.to_decimal(precision)?))
+}
+
+/// The number of decimal places of the fractions with this denominator, if they have a
+/// finite decimal expansion.
+fn decimal_places(denominator: &BigInt) -> Option<u32> {
+    let mut rest = denominator.clone();
+    let mut places = [0u32; 2];
+    for (count, factor) in places.iter_mut().zip([2, 5]) {
+        let factor = BigInt::from(factor);
+        while (&rest % &factor).is_zero() {
+            rest /= &factor;
+            *count += 1;
+        }
+    }
+    rest.is_one().then(|| places[0].max(places[1]))
+}
+
+/// The exact value of a decimal as a fraction, unless its power of ten is larger than
+/// `MAX_EXACT_EXPONENT` in magnitude.
+fn rational(value: &BigDecimal) -> Option<BigRational> {
+    let (digits, scale) = value.as_bigint_and_exponent();
+    let exponent = i32::try_from(-scale)
+        .ok()
+        .filter(|exponent| exponent.abs() <= MAX_EXACT_EXPONENT)?;
+    let scale = BigRational::from_integer(BigInt::from(10)).pow(exponent);
+    Some(BigRational::from_integer(digits) * scale)
+}
+
+/// The principal value of `base` raised to the power `exponent`, computed by repeated
+/// multiplication for an integer exponent so that `i^2` is exactly `-1`.
+pub(crate) fn powc(base: Complex64, exponent: Complex64) -> Complex64 {
+    if exponent.im == 0.0 && exponent.re.fract() == 0.0 && exponent.re.abs() <= 1024.0 {
+        return base.powi(exponent.re as i32);
+    }
+    if base.is_zero() {
+        let power = if exponent.re > 0.0 { 0.0 } else { f64::NAN };
+        return Complex64::from(power);
+    }
+    base.powc(exponent)
+}
+
+fn factorial(n: f64) -> f64 {
+    if n < 0.0 || n.fract() != 0.0 {
+        return f64::NAN;
+    }
+    // Every factorial above 170! overflows to infinity.
+    (2..=n.min(171.0) as u32).map(f64::from).product()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rational(value: f64) -> Value {
+        Value::literal(value, NumericMode::Rational)
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(Value::literal(0.1, NumericMode::Float), Value::Float(0.1));
+        assert_eq!(
+            rational(0.1),
+            Value::Rational(BigRational::new(1.into(), 10.into()))
+        );
+        assert_eq!(
+            rational(-1.25e-3),
+            Value::Rational(BigRational::new((-1).into(), 800.into()))
+        );
+        assert_eq!(
+            rational(6.02e23),
+            Value::Rational(BigRational::from_integer(
+                "602000000000000000000000".parse().unwrap()
+            ))
+        );
+        assert_eq!(rational(f64::INFINITY), Value::Float(f64::INFINITY));
+    }
+
+    #[test]
+    fn test_exact_arithmetic() {
+        assert_eq!((rational(0.1) + rational(0.2)).to_string(), "0.3");
+        assert_eq!(
+            (rational(1.0) / rational(3.0) * rational(3.0)),
+            rational(1.0)
+        );
+        assert_eq!((rational(-7.0) % rational(3.0)), rational(2.0));
+        assert_eq!((rational(7.5) % rational(-2.0)), rational(1.5));
+        assert_eq!(rational(2.0).pow(&rational(-2.0)), rational(0.25));
+        assert_eq!(
+            rational(20.0).factorial().to_string(),
+            "2432902008176640000"
+        );
+        assert_eq!(
+            rational(2.0).pow(&rational(100.0)).to_string(),
+            "1267650600228229401496703205376"
+        );
+    }
+
+    #[test]
+    fn test_float_fallback() {
+        assert_eq!(rational(0.1) + Value::Float(0.2), Value::Float(0.1 + 0.2));
+        assert_eq!(rational(4.0).pow(&rational(0.5)), Value::Float(2.0));
+        assert_eq!(
+            rational(0.0).pow(&rational(-1.0)),
+            Value::Float(f64::INFINITY)
+        );
+        assert!(rational(-1.0).factorial().to_f64().is_nan());
+        let power = rational(2.0).pow(&rational(10_000.0));
+        assert!(matches!(power, Value::Rational(_)));
+        assert_eq!(power.pow(&rational(10_000.0)), Value::Float(f64::INFINITY));
+        assert_eq!(Value::Float(5.0).factorial(), Value::Float(120.0));
+    }
+
+    #[test]
+    fn test_decimals() {
+        let mode = NumericMode::Decimal { precision: 20 };
+        let decimal = |value| Value::literal(value, mode);
+        assert_eq!(
+            (decimal(1.0) / decimal(3.0)).to_string(),
+            "0.33333333333333333333"
+        );
+        assert_eq!((decimal(0.1) + rational(0.2)).to_string(), "0.3");
+        assert_eq!(
+            (decimal(1.0) - rational(1.0) / rational(3.0)).to_string(),
+            "0.66666666666666666667"
+        );
+        assert_eq!(decimal(0.5) * Value::Float(3.0), Value::Float(1.5));
+        assert_eq!(
+            decimal(2.0).pow(&rational(0.5)).to_string(),
+            "1.4142135623730950488"
+        );
+        assert!(decimal(-2.0).pow(&decimal(0.5)).to_f64().is_nan());
+        assert_eq!(
+            decimal(25.0).factorial().to_string(),
+            "1.5511210043330985984e25"
+        );
+        assert_eq!(Value::literal(f64::NAN, mode).to_string(), "NaN");
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Value::Float(0.5).to_string(), "0.5");
+        assert_eq!(rational(-42.0).to_string(), "-42");
+        assert_eq!(rational(-0.05).to_string(), "-0.05");
+        assert_eq!(rational(1.0 / 64.0).to_string(), "0.015625");
+        assert_eq!((rational(-2.0) / rational(6.0)).to_string(), "-1/3");
+    }
+
+    #[test]
+    fn test_complex() {
+        let complex = |re, im| Value::complex(Complex64::new(re, im));
+        assert_eq!(complex(3.0, 0.0), Value::Float(3.0));
+        assert_eq!(complex(1.0, 2.0) * complex(1.0, -2.0), Value::Float(5.0));
+        assert_eq!(rational(0.5) + complex(0.0, 1.0), complex(0.5, 1.0));
+        assert_eq!(complex(0.0, 1.0).pow(&rational(2.0)), Value::Float(-1.0));
+        assert_eq!(
+            Value::Float(-4.0).pow(&Value::Float(0.5)).to_complex().im,
+            2.0
+        );
+        assert!((complex(1.0, 1.0) % Value::Float(2.0)).is_nan());
+        assert!(complex(0.0, 1.0).to_f64().is_nan());
+        assert_eq!(
+            complex(0.0, 1.0).convert_angle(AngleMode::Radians, AngleMode::Gradians),
+            complex(0.0, 200.0 / std::f64::consts::PI)
+        );
+
+        assert_eq!(complex(3.0, 4.0).to_string(), "3+4i");
+        assert_eq!(complex(-1.5, -1.0).to_string(), "-1.5-i");
+        assert_eq!(complex(0.0, 2.0).to_string(), "2i");
+        assert_eq!(complex(0.0, -1.0).to_string(), "-i");
+        assert_eq!(
+            complex(0.0, -2.0).format(ComplexFormat::Polar, AngleMode::Degrees),
+            "2∠-90deg"
+        );
+        assert_eq!(
+            Value::Float(2.0).format(ComplexFormat::Polar, AngleMode::Radians),
+            "2"
+        );
+    }
+}
@@ -0,0 +1,425 @@
+use calcrs::operator::Associativity;
+use calcrs::unit;
+use calcrs::{
+    eval_str, eval_str_with, eval_value_with, evaluate, parse, parse_program, parse_with_recovery,
+    tokenize, AngleMode, Arity, CalcError, ComplexFormat, Diagnostic, Environment, EvaluationError,
+    EvaluationMode, ExpressionKind, LexError, NumericMode, Operator, ParseError, Shape, Span,
+    TokenKind, Value,
+};
+use num_complex::Complex64;
+
+#[test]
+fn test_eval_str() {
+    assert_eq!(eval_str("2 + 3 * 4 - 10 / 5"), Ok(12.0));
+    assert_eq!(eval_str("pow(2, 3) + sqrt(16)"), Ok(12.0));
+    assert_eq!(eval_str("-(1 + 2)"), Ok(-3.0));
+    assert_eq!(eval_str("log(2, 1024) + hypot(6, 8)"), Ok(20.0));
+    assert_eq!(eval_str("1e3 + 0x10 + 0b11 + 1_000 + .5"), Ok(2019.5));
+}
+
+#[test]
+fn test_eval_str_errors() {
+    assert_eq!(
+        eval_str("1 + #"),
+        Err(CalcError::Lex(LexError::InvalidCharacter {
+            character: '#',
+            span: Span::new(4, 5),
+        }))
+    );
+    assert_eq!(
+        eval_str("1.2.3 * 2"),
+        Err(CalcError::Lex(LexError::MalformedNumber {
+            text: String::from("1.2.3"),
+            span: Span::new(0, 5),
+        }))
+    );
+    assert_eq!(
+        eval_str("1 + (2"),
+        Err(CalcError::Parse(ParseError::ExpectedToken {
+            expected: TokenKind::RightParen,
+            span: Span::new(6, 6),
+        }))
+    );
+    assert_eq!(
+        eval_str("1 / (2 - 2)"),
+        Err(CalcError::Eval(EvaluationError::DivisionByZero {
+            span: Span::new(4, 11),
+        }))
+    );
+}
+
+#[test]
+fn test_variables() {
+    let mut env = Environment::new();
+    assert_eq!(eval_str_with("x = 3 * 2", &mut env), Ok(Some(6.0)));
+    assert_eq!(eval_str_with("y = x + 1", &mut env), Ok(Some(7.0)));
+    assert_eq!(eval_str_with("x * y", &mut env), Ok(Some(42.0)));
+    assert_eq!(env.get("y"), Some(7.0));
+
+    assert_eq!(
+        eval_str("x + 1"),
+        Err(CalcError::Eval(EvaluationError::UndefinedVariable {
+            name: String::from("x"),
+            span: Span::new(0, 1),
+        }))
+    );
+}
+
+#[test]
+fn test_constants() {
+    assert_eq!(eval_str("2 * pi"), Ok(std::f64::consts::TAU));
+    assert_eq!(eval_str("e"), Ok(std::f64::consts::E));
+
+    let mut env = Environment::new();
+    let err = eval_str_with("tau = 6", &mut env).unwrap_err();
+    assert_eq!(err.code(), "E0307");
+    assert_eq!(err.span(), Span::new(0, 3));
+}
+
+#[test]
+fn test_strict_mode() {
+    let mut env = Environment::new();
+    assert_eq!(env.mode(), EvaluationMode::Lenient);
+    assert!(eval_str_with("acos(5)", &mut env)
+        .unwrap()
+        .unwrap()
+        .is_nan());
+    assert!(eval_str_with("(-5)!", &mut env).unwrap().unwrap().is_nan());
+
+    env.set_mode(EvaluationMode::Strict);
+    let err = eval_str_with("acos(5)", &mut env).unwrap_err();
+    assert_eq!(err.code(), "E0308");
+    assert_eq!(err.to_string(), "`acos` is not defined for 5");
+    let err = eval_str_with("(-5)!", &mut env).unwrap_err();
+    assert_eq!(err.code(), "E0308");
+    assert_eq!(err.to_string(), "`!` is not defined for -5");
+    let err = eval_str_with("exp(1000)", &mut env).unwrap_err();
+    assert_eq!(err.code(), "E0309");
+    assert_eq!(
+        eval_str_with("exp(1)", &mut env),
+        Ok(Some(std::f64::consts::E))
+    );
+
+    // A complex or matrix result has no `f64` value.
+    let err = eval_str_with("2i", &mut env).unwrap_err();
+    assert_eq!(err.code(), "E0315");
+    assert_eq!(err.to_string(), "expected a real result, found `2i`");
+    assert_eq!(err.span(), Span::new(0, 2));
+    let err = eval_str_with("[1, 2]", &mut env).unwrap_err();
+    assert_eq!(err.code(), "E0315");
+    assert_eq!(eval_str_with("abs(3+4i)", &mut env), Ok(Some(5.0)));
+    assert_eq!(
+        eval_value_with("2i", &mut env)
+            .unwrap()
+            .unwrap()
+            .to_string(),
+        "2i"
+    );
+}
+
+#[test]
+fn test_angle_modes() {
+    let mut env = Environment::new();
+    assert_eq!(env.angle_mode(), AngleMode::Radians);
+    assert_eq!(eval_str_with("sin(0)", &mut env), Ok(Some(0.0)));
+    let value = eval_str_with("sin(30deg)", &mut env).unwrap().unwrap();
+    assert!((value - 0.5).abs() < 1e-12);
+
+    env.set_angle_mode(AngleMode::Degrees);
+    let value = eval_str_with("cos(60)", &mut env).unwrap().unwrap();
+    assert!((value - 0.5).abs() < 1e-12);
+    let value = eval_str_with("atan(1) + 1rad", &mut env).unwrap().unwrap();
+    assert!((value - (45.0 + 180.0 / std::f64::consts::PI)).abs() < 1e-9);
+}
+
+#[test]
+fn test_rational_mode() {
+    let mut env = Environment::new();
+    assert_eq!(env.numeric_mode(), NumericMode::Float);
+    env.set_numeric_mode(NumericMode::Rational);
+    let value = eval_value_with("1/3 * 3 + 0.1 + 0.2", &mut env)
+        .unwrap()
+        .unwrap();
+    assert_eq!(value.to_string(), "1.3");
+    assert_eq!(eval_str_with("1/4", &mut env), Ok(Some(0.25)));
+    eval_value_with("r = 2/3", &mut env).unwrap();
+    let value = eval_value_with("r^2", &mut env).unwrap().unwrap();
+    assert_eq!(value.to_string(), "4/9");
+    assert!(matches!(
+        eval_value_with("exp(r)", &mut env),
+        Ok(Some(Value::Float(_)))
+    ));
+}
+
+#[test]
+fn test_decimal_mode() {
+    let mut env = Environment::new();
+    env.set_numeric_mode(NumericMode::Decimal { precision: 40 });
+    let value = eval_value_with("sqrt(2)", &mut env).unwrap().unwrap();
+    assert_eq!(
+        value.to_string(),
+        "1.41421356237309504880168872420969807857"
+    );
+    let value = eval_value_with("1e20 + 1", &mut env).unwrap().unwrap();
+    assert_eq!(value.to_string(), "100000000000000000001");
+    assert!(matches!(value, Value::Decimal(_)));
+    assert_eq!(eval_str_with("1/4", &mut env), Ok(Some(0.25)));
+    let value = eval_value_with("mean([1/3, 1/3])", &mut env)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        value.to_string(),
+        "0.3333333333333333333333333333333333333333"
+    );
+
+    env.set_mode(EvaluationMode::Strict);
+    let err = eval_value_with("acos(5)", &mut env).unwrap_err();
+    assert_eq!(err.code(), "E0308");
+}
+
+#[test]
+fn test_user_functions() {
+    let mut env = Environment::new();
+    assert_eq!(eval_str_with("f(x, y) = x * x + y", &mut env), Ok(None));
+    assert_eq!(eval_str_with("f(3, 4)", &mut env), Ok(Some(13.0)));
+    assert_eq!(
+        eval_str_with("f(3)", &mut env),
+        Err(CalcError::Eval(EvaluationError::ArityMismatch {
+            name: String::from("f"),
+            expected: Arity::Fixed(2),
+            found: 1,
+            span: Span::new(0, 4),
+        }))
+    );
+}
+
+#[test]
+fn test_function_registry() {
+    let mut env = Environment::new();
+    env.registry_mut()
+        .register("margin", Arity::Fixed(2), |args| {
+            (args[0] - args[1]) / args[0]
+        });
+    env.registry_mut()
+        .register("mean", Arity::Variadic { min: 1 }, |args| {
+            args.iter().sum::<f64>() / args.len() as f64
+        });
+
+    assert_eq!(eval_str_with("margin(200, 150)", &mut env), Ok(Some(0.25)));
+    assert_eq!(eval_str_with("mean(1, 2, 3, 6)", &mut env), Ok(Some(3.0)));
+    assert_eq!(
+        eval_str_with("mean()", &mut env),
+        Err(CalcError::Eval(EvaluationError::ArityMismatch {
+            name: String::from("mean"),
+            expected: Arity::Variadic { min: 1 },
+            found: 0,
+            span: Span::new(0, 6),
+        }))
+    );
+    let err = eval_str_with("mean + 1", &mut env).unwrap_err();
+    assert_eq!(err.code(), "E0203");
+    assert_eq!(eval_str("margin(2, 1)").unwrap_err().code(), "E0304");
+}
+
+#[test]
+fn test_custom_operators() {
+    let mut env = Environment::new();
+    env.operators_mut()
+        .add(
+            Operator::infix(
+                TokenKind::Operator(String::from("max")),
+                5,
+                Associativity::Left,
+            )
+            .calling("larger"),
+        )
+        .unwrap();
+    env.operators_mut()
+        .add(Operator::postfix(TokenKind::Operator(String::from("%%")), 50).calling("percent"))
+        .unwrap();
+    eval_str_with("larger(a, b) = (a + b + ((a - b)^2)^0.5) / 2", &mut env).unwrap();
+    eval_str_with("percent(x) = x / 100", &mut env).unwrap();
+
+    assert_eq!(eval_str_with("1 + 2 max 5 - 1", &mut env), Ok(Some(4.0)));
+    assert_eq!(eval_str_with("50%% * 8", &mut env), Ok(Some(4.0)));
+    assert_eq!(eval_str_with("7 % 4", &mut env), Ok(Some(3.0)));
+    assert!(eval_str("1 max 2").is_err());
+}
+
+#[test]
+fn test_pipeline_stages() {
+    let tokens = tokenize("1 + 2").unwrap();
+    assert_eq!(tokens.len(), 3);
+    assert_eq!(tokens[1].kind, TokenKind::Plus);
+
+    let ast = parse(&tokens).unwrap();
+    assert_eq!(ast.span, Span::new(0, 5));
+    assert!(matches!(
+        ast.kind,
+        ExpressionKind::BinaryOp(_, TokenKind::Plus, _)
+    ));
+
+    assert_eq!(evaluate(&ast, &mut Environment::new()), Ok(3.0));
+}
+
+#[test]
+fn test_parse_program_and_recovery() {
+    let tokens = tokenize("1 + 2; 3 * 4").unwrap();
+    let mut env = Environment::new();
+    let results: Vec<f64> = parse_program(&tokens)
+        .unwrap()
+        .iter()
+        .map(|ast| evaluate(ast, &mut env).unwrap())
+        .collect();
+    assert_eq!(results, vec![3.0, 12.0]);
+
+    let tokens = tokenize("1 + ; 2 3").unwrap();
+    let output = parse_with_recovery(&tokens);
+    let codes: Vec<&str> = output.errors.iter().map(CalcError::code).collect();
+    assert_eq!(codes, vec!["E0201", "E0204"]);
+}
+
+#[test]
+fn test_diagnostic_rendering() {
+    let input = "1 + 2 3";
+    let err = eval_str(input).unwrap_err();
+    let rendered = Diagnostic::from(&err).render(input, false);
+    assert!(rendered.starts_with("error[E0204]: unexpected token `3` after expression"));
+    assert!(rendered.contains("1 | 1 + 2 3\n  |       ^"));
+}
+
+#[test]
+fn test_complex_numbers() {
+    let mut env = Environment::new();
+    let value = eval_value_with("(2+3i) * (2-3i) + sqrt(-9)", &mut env)
+        .unwrap()
+        .unwrap();
+    assert_eq!(value, Value::Complex(Complex64::new(13.0, 3.0)));
+    assert_eq!(value.to_string(), "13+3i");
+    assert_eq!(eval_str_with("abs(5 + 12i)", &mut env), Ok(Some(13.0)));
+
+    env.set_angle_mode(AngleMode::Degrees);
+    let value = eval_value_with("-2j", &mut env).unwrap().unwrap();
+    assert_eq!(value.format(env.complex_format(), env.angle_mode()), "-2i");
+    env.set_complex_format(ComplexFormat::Polar);
+    assert_eq!(
+        value.format(env.complex_format(), env.angle_mode()),
+        "2∠-90deg"
+    );
+}
+
+#[test]
+fn test_units() {
+    let mut env = Environment::new();
+    eval_str_with("d = 3 km", &mut env).unwrap();
+    let value = eval_value_with("d / 20 min in km/h", &mut env)
+        .unwrap()
+        .unwrap();
+    assert_eq!(value.to_string(), "9 km/h");
+    assert_eq!(value.to_f64(), 9.0);
+    assert_eq!(eval_str_with("d / 1 m", &mut env), Ok(Some(3000.0)));
+
+    let speed = unit::lookup("m")
+        .unwrap()
+        .dimension
+        .checked_div(unit::lookup("s").unwrap().dimension)
+        .unwrap();
+    let value = eval_value_with("60 mph + 1 m/s", &mut env)
+        .unwrap()
+        .unwrap();
+    assert_eq!(value.dimension(), speed);
+
+    env.set_numeric_mode(NumericMode::Rational);
+    let value = eval_value_with("0.5 kg * (2 m/s)^2", &mut env)
+        .unwrap()
+        .unwrap();
+    assert_eq!(value.to_string(), "2 kg m^2/s^2");
+
+    let err = eval_str_with("1 m + 2 s", &mut env).unwrap_err();
+    assert_eq!(err.code(), "E0310");
+    assert_eq!(
+        err.to_string(),
+        "expected a quantity of dimension `m`, found `s`"
+    );
+    let err = eval_str_with("1 m in parsec", &mut env).unwrap_err();
+    assert_eq!(err.code(), "E0206");
+}
+
+#[test]
+fn test_matrices() {
+    let mut env = Environment::new();
+    eval_str_with("A = [2, 1; 1, 3]", &mut env).unwrap();
+    let value = eval_value_with("A \\ [3; 5]", &mut env).unwrap().unwrap();
+    assert_eq!(value.to_string(), "[0.8; 1.4]");
+    assert_eq!(value.shape(), Some(Shape::new(2, 1)));
+    assert_eq!(eval_str_with("det(A) * rank(A)", &mut env), Ok(Some(10.0)));
+    let value = eval_value_with("5 * inv(A)", &mut env).unwrap().unwrap();
+    assert_eq!(value.to_string(), "[3, -1; -1, 2]");
+
+    // Matrices keep the exact values of their elements.
+    env.set_numeric_mode(NumericMode::Rational);
+    let value = eval_value_with("[1, 2] .* [0.5, 1/3]", &mut env)
+        .unwrap()
+        .unwrap();
+    assert_eq!(value.to_string(), "[0.5, 2/3]");
+    let value = eval_value_with("inv([1/2, 1; 1, 1/3])", &mut env)
+        .unwrap()
+        .unwrap();
+    assert_eq!(value.to_string(), "[-0.4, 1.2; 1.2, -0.6]");
+
+    let err = eval_str_with("[1, 2] + [1, 2, 3]", &mut env).unwrap_err();
+    assert_eq!(err.code(), "E0311");
+    assert_eq!(
+        err.to_string(),
+        "expected a matrix of shape `1x2`, found `1x3`"
+    );
+    let err = eval_str_with("[1, 2] * [3, 4]", &mut env).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "expected a matrix with 2 rows, found `1x2`"
+    );
+    let err = eval_str_with("[1, 2] ^ 2", &mut env).unwrap_err();
+    assert_eq!(err.to_string(), "expected a square matrix, found `1x2`");
+    let err = eval_str_with("[1, 2; 2, 4] \\ [1; 1]", &mut env).unwrap_err();
+    assert_eq!(err.code(), "E0312");
+}
+
+#[test]
+fn test_statistics() {
+    let mut env = Environment::new();
+    eval_str_with("data = [2, 4, 4, 4, 5, 5, 7, 9]", &mut env).unwrap();
+    assert_eq!(eval_str_with("mean(data)", &mut env), Ok(Some(5.0)));
+    assert_eq!(eval_str_with("median(data)", &mut env), Ok(Some(4.5)));
+    assert_eq!(eval_str_with("mode(data)", &mut env), Ok(Some(4.0)));
+    assert_eq!(eval_str_with("var(data)", &mut env), Ok(Some(32.0 / 7.0)));
+    assert_eq!(eval_str_with("max(data, 10)", &mut env), Ok(Some(10.0)));
+    assert_eq!(
+        eval_str_with("percentile(data, 50)", &mut env),
+        Ok(Some(4.5))
+    );
+
+    env.set_mode(EvaluationMode::Strict);
+    let err = eval_str_with("percentile(data, 101)", &mut env).unwrap_err();
+    assert_eq!(err.code(), "E0308");
+    let err = eval_str_with("sum([])", &mut env).unwrap_err();
+    assert_eq!(err.code(), "E0313");
+    assert_eq!(err.to_string(), "list is empty");
+}
+
+#[test]
+fn test_conditionals() {
+    let mut env = Environment::new();
+    eval_str_with("clamp(x, lo, hi) = x < lo ? lo : x > hi ? hi : x", &mut env).unwrap();
+    assert_eq!(eval_str_with("clamp(-3, 0, 10)", &mut env), Ok(Some(0.0)));
+    assert_eq!(eval_str_with("clamp(42, 0, 10)", &mut env), Ok(Some(10.0)));
+    assert_eq!(eval_str_with("clamp(7, 0, 10)", &mut env), Ok(Some(7.0)));
+
+    let value = eval_value_with("2 >= 1 and not 1 / 0 == 0 or true", &mut env);
+    assert_eq!(value.unwrap_err().code(), "E0301");
+    let value = eval_value_with("true or 1 / 0 == 0", &mut env).unwrap();
+    assert_eq!(value, Some(Value::Boolean(true)));
+
+    let err = eval_str_with("not 1", &mut env).unwrap_err();
+    assert_eq!(err.code(), "E0314");
+    assert_eq!(err.to_string(), "expected a boolean, found a number");
+}